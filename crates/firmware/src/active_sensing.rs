@@ -0,0 +1,37 @@
+//! Tasks and types related to MIDI Active Sensing, by which a sender signals that its connection is still alive.
+
+use crate::{USB_MIDI, UsbMidi};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+
+/// Per the MIDI spec, once Active Sensing has been received, a gap of more than 300 ms between messages (of any kind) is
+/// to be treated as a loss of connection.
+const ACTIVE_SENSING_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Signaled whenever MIDI is received; carries `true` if an Active Sensing message was among the messages.
+pub static MIDI_RECEIVED: Signal<CriticalSectionRawMutex, bool> = Signal::new();
+
+/// Treats the connection as lost if a sender which has been sending Active Sensing messages stops, so that notes aren't
+/// left stuck on when, e.g., a cable is pulled mid-performance.
+///
+/// The loss is handled by [`midi_consumer_task`][crate::midi_consumer_task] just as a USB disconnection is, so that
+/// notes deferred for chord cleanup are dropped and the output tasks see all notes turned off.
+///
+/// Senders which never send Active Sensing are unaffected.
+#[embassy_executor::task]
+pub async fn watch_active_sensing() -> ! {
+    loop {
+        // the timeout applies only once the sender has shown that it sends Active Sensing…
+        while !MIDI_RECEIVED.wait().await {}
+
+        // …after which any MIDI at all counts as a sign of life
+        while let Either::Second(_) =
+            select(Timer::after(ACTIVE_SENSING_TIMEOUT), MIDI_RECEIVED.wait()).await
+        {}
+
+        #[cfg(feature = "defmt")]
+        defmt::warn!("Active Sensing timed out; turning all notes off");
+        USB_MIDI.send(UsbMidi::ConnectionLost).await;
+    }
+}
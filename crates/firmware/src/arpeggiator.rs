@@ -0,0 +1,84 @@
+//! Tasks and types related to the [`Arpeggiator`].
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal, watch::Watch};
+use embassy_time::{Instant, Timer};
+use midival_renaissance_lib::{
+    beat_clock::ClockDivisions,
+    configuration::{Arpeggiator, RateSource},
+};
+
+/// Signaled each time the arpeggio should move on to its next note.
+pub static ARPEGGIATOR_STEP: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Whether the arpeggio carries on once all keys are released; see
+/// [`LatchedNotes`][midival_renaissance_lib::configuration::LatchedNotes]. Starts out as configured by
+/// [`ARPEGGIATOR`][crate::ARPEGGIATOR], and is toggled by a long press of the note priority button.
+pub static ARPEGGIATOR_LATCH: Watch<CriticalSectionRawMutex, bool, 1> =
+    Watch::new_with(match crate::ARPEGGIATOR {
+        Some(arpeggiator) => arpeggiator.is_latched(),
+        None => false,
+    });
+
+/// Whether held notes are arpeggiated rather than voiced according to note priority. Starts out enabled if
+/// [`ARPEGGIATOR`][crate::ARPEGGIATOR] is configured, and is toggled by the switch assigned
+/// [`SwitchFunction::ArpeggiatorToggle`][midival_renaissance_lib::configuration::SwitchFunction::ArpeggiatorToggle]; the
+/// change takes effect on the next step.
+pub static ARPEGGIATOR_ENABLED: Watch<CriticalSectionRawMutex, bool, 1> =
+    Watch::new_with(crate::ARPEGGIATOR.is_some());
+
+/// Carries a new [`RateSource`] for the arpeggio, e.g., from the [thumbwheel][crate::thumbwheel::thumbwheel]; the change
+/// takes effect on the next step.
+pub static ARPEGGIATOR_RATE: Signal<CriticalSectionRawMutex, RateSource> = Signal::new();
+
+/// Carries the [`ClockDivisions`] which fall on each MIDI Timing Clock pulse, as counted by
+/// [`midi_consumer_task`][crate::midi_consumer_task].
+pub static CLOCK_DIVISIONS: Signal<CriticalSectionRawMutex, ClockDivisions> = Signal::new();
+
+/// Task responsible for pacing the arpeggio, signaling [`ARPEGGIATOR_STEP`] at the `arpeggiator`'s rate.
+///
+/// Paced by [`RateSource::MidiClock`] while the clock runs (see
+/// [`MidiState::bpm`][midival_renaissance_lib::midi_state::MidiState::bpm]), the arpeggio steps on the clock pulses
+/// themselves (see [`CLOCK_DIVISIONS`]), so it stays in phase with the sender. Otherwise, it steps by timer, with the
+/// rate recomputed on each step; should the clock stop, the arpeggio carries on at the last tempo received.
+#[embassy_executor::task]
+pub async fn arpeggiator_clock(mut arpeggiator: Arpeggiator) -> ! {
+    let mut bpm = None;
+    let mut next_step = Instant::now();
+    loop {
+        let estimate = crate::MIDI_STATE_SYNC.try_get().and_then(|state| state.bpm);
+        if estimate.is_some() {
+            bpm = estimate;
+        }
+        if let Some(rate_source) = ARPEGGIATOR_RATE.try_take() {
+            arpeggiator = arpeggiator.with_rate_source(rate_source);
+        }
+
+        let rate = arpeggiator.rate(bpm);
+        match arpeggiator.rate_source() {
+            RateSource::MidiClock(division) if estimate.is_some() => {
+                // a pulse signaled before this step began is stale; the timeout resumes stepping by timer should the
+                // clock stop mid-step
+                CLOCK_DIVISIONS.reset();
+                if let Either::Second(()) =
+                    select(clock_division(division.into()), Timer::after(rate * 2)).await
+                {
+                    defmt::debug!("MIDI clock stopped mid-step; arpeggiating by timer");
+                }
+                next_step = Instant::now();
+            }
+            _ => {
+                // stepping relative to the previous step (rather than to now) keeps the time spent signaling from
+                // accumulating
+                next_step += rate;
+                Timer::at(next_step).await;
+            }
+        }
+        ARPEGGIATOR_STEP.signal(());
+    }
+}
+
+/// Waits for a MIDI Timing Clock pulse on which `division` falls.
+async fn clock_division(division: ClockDivisions) {
+    while !CLOCK_DIVISIONS.wait().await.contains(division) {}
+}
@@ -1,17 +1,22 @@
 //! Tasks and types related the [chord cleanup](`ChordCleanup`) feature.
 
-use crate::MidiStateSender;
-use embassy_futures::select::{Either, select};
-use embassy_stm32::{exti::ExtiInput, gpio::Output};
+use crate::{
+    MidiStateSender,
+    config_change_display::{ConfigChangeType, DISPLAY_CONFIG_CHANGE},
+    debounce::debounced_press,
+};
+use embassy_futures::select::{Either, Either3, select, select3};
+use embassy_stm32::{exti::ExtiInput, gpio::Level};
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
     signal::Signal,
     watch::{AnonReceiver, Sender, Watch},
 };
 use embassy_time::{Instant, Timer};
 use midival_renaissance_lib::{
+    chord_cleanup::ChordCleanupBatch,
     configuration::{ChordCleanup, CycleConfig},
-    midi_state::ActivatedNotes,
 };
 use wmidi::MidiMessage;
 
@@ -33,103 +38,96 @@ pub type ChordCleanupSpy<'a> =
 /// the "chord cleanup" feature. The input and display are provisional because I only have pushbutton inputs at present.
 /// Should it turn out that more states are necessary, a selector switch seems more appropriate. If not, a toggle or slider
 /// switch seems preferable to a pushbutton because they obviate the need for an indicator LED.
+///
+/// The LED itself is driven by [`glide_display`][crate::glide_display::glide_display], which shares it with the
+/// portamento progress display.
 #[embassy_executor::task]
 pub async fn chord_cleanup_config(
     mut button: ExtiInput<'static>,
-    mut led: Output<'static>,
     chord_cleanup: ChordCleanupSender<'static>,
 ) -> ! {
     loop {
-        button.wait_for_falling_edge().await;
+        debounced_press(&mut button, Level::Low).await;
 
-        let new_state = chord_cleanup
+        let previous_state = chord_cleanup
             .try_get()
-            .as_mut()
-            .expect("Chord cleanup state should never be uninitialized")
-            .cycle();
+            .expect("Chord cleanup state should never be uninitialized");
+        let new_state = previous_state.cycle();
         chord_cleanup.send(new_state);
-
-        match new_state {
-            ChordCleanup::None => {
-                led.set_low();
-            }
-            ChordCleanup::ThirtySecondNote => {
-                led.set_high();
-            }
-        }
+        DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::ChordCleanup);
+        #[cfg(feature = "diagnostics")]
+        crate::diagnostics::record_config_change(
+            midival_renaissance_lib::configuration::ConfigChange::ChordCleanup {
+                previous: previous_state,
+                new: new_state,
+            },
+        );
     }
 }
 
-type DeferredMidiSync<'a> = Signal<CriticalSectionRawMutex, (Instant, MidiMessage<'a>)>;
-pub static DEFERRED_MIDI_MSG: DeferredMidiSync = Signal::new();
+/// A USB full-speed packet is at most 64 bytes, i.e., 16 USB-MIDI Event Packets, all of which could be note events
+/// (e.g., a large chord).
+const DEFERRED_MIDI_MSG_CAPACITY: usize = 16;
+type DeferredMidiSync<'a> =
+    Channel<CriticalSectionRawMutex, (Instant, MidiMessage<'a>), DEFERRED_MIDI_MSG_CAPACITY>;
+/// Carries note events (and the expiry of the chord cleanup period to which they belong) to be batched.
+///
+/// This is a [`Channel`] rather than a `Signal` because a `Signal` holds only the latest value: when several note
+/// events arrive in the same USB packet, as is common for chords, each would overwrite the last before
+/// [`handle_deferred_midi_msg`] had a chance to read it, dropping all but the final note.
+pub static DEFERRED_MIDI_MSG: DeferredMidiSync = Channel::new();
+
+/// Signaled by [`discard_deferred_midi_msgs`] to have [`handle_deferred_midi_msg`] discard its batch.
+static DISCARD_BATCH: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
-/// Temporarily caches note events that comprise the performance (or release) of a chord, atomically applying them
-/// upon expiry of the chord cleanup batching period.
+/// Discards the note events deferred for chord cleanup which have yet to be applied, both those still queued and those
+/// already batched.
+///
+/// To be called when all notes are forced off (e.g., upon All Notes Off or loss of connection to the host), as soon as
+/// that takes effect: events deferred beforehand would otherwise be applied afterward, activating notes which nothing
+/// will release.
+pub fn discard_deferred_midi_msgs() {
+    while DEFERRED_MIDI_MSG.try_receive().is_ok() {}
+    DISCARD_BATCH.signal(());
+}
+
+/// Temporarily caches note events that comprise the performance (or release) of a chord, atomically applying them to
+/// the MIDI state as it stands upon expiry of the chord cleanup batching period.
 #[embassy_executor::task]
 pub async fn handle_deferred_midi_msg(midi_state: MidiStateSender<'static>) -> ! {
-    let mut deferred_notes = ActivatedNotes::new();
-    let mut expiry: Option<Instant> = None;
+    let mut batch = ChordCleanupBatch::new();
 
     loop {
         // if a chord cleanup period is active…
-        if let Some(x) = expiry {
-            // …this task wakes on either receipt of new MIDI or end of the period…
-            match select(Timer::at(x), DEFERRED_MIDI_MSG.wait()).await {
-                Either::First(_) => {
-                    #[cfg(feature = "defmt")]
-                    defmt::info!("Chord cleanup period over; updating state");
-                    expiry = None;
-
+        if let Some(x) = batch.expiry() {
+            // …this task wakes on either receipt of new MIDI or end of the period (or on a discard, which is polled first:
+            // any MIDI already queued was queued after it)…
+            match select3(
+                DISCARD_BATCH.wait(),
+                Timer::at(x),
+                DEFERRED_MIDI_MSG.receive(),
+            )
+            .await
+            {
+                Either3::First(()) => batch.clear(),
+                Either3::Second(_) => {
                     let mut state = midi_state
                         .try_get()
                         .expect("MIDI state should never be uninitialized");
-                    state.activated_notes = deferred_notes;
-                    midi_state.send(state);
+                    if batch.expire(Instant::now(), &mut state) {
+                        midi_state.send(state);
+                    }
                 }
-                Either::Second((_, msg)) => {
-                    store_note_event(msg, &mut deferred_notes);
+                Either3::Third((x, msg)) => {
+                    batch.defer(x, &msg);
                 }
             }
         // …otherwise, the task wakes on new MIDI, initiating a new chord cleanup period
         } else {
-            let (x, msg) = DEFERRED_MIDI_MSG.wait().await;
-            #[cfg(feature = "defmt")]
-            defmt::info!("Initiating chord cleanup period");
-            expiry = Some(x);
-            // Take a snapshot of the current state of activated notes to use as the basis for the atomic
-            // update at the end of the cleanup period.
-            deferred_notes = midi_state
-                .try_get()
-                .expect("MIDI state should never be uninitialized")
-                .activated_notes;
-            store_note_event(msg, &mut deferred_notes);
-        }
-    }
-
-    fn store_note_event(msg: MidiMessage, store: &mut ActivatedNotes) {
-        match msg {
-            MidiMessage::NoteOff(_channel, note, _velocity) => {
-                #[cfg(feature = "defmt")]
-                defmt::info!(
-                    "Batching NoteOff: channel {}, note {}, velocity: {}",
-                    _channel.number(),
-                    note.to_str(),
-                    u8::from(_velocity)
-                );
-                store.remove(note);
-            }
-            MidiMessage::NoteOn(_channel, note, _velocity) => {
-                #[cfg(feature = "defmt")]
-                defmt::info!(
-                    "Batching NoteOn: channel {}, note {}, velocity: {}",
-                    _channel.number(),
-                    note.to_str(),
-                    u8::from(_velocity)
-                );
-                store.add(note);
-            }
-            _ => {
-                panic!("Only NoteOff and NoteOn events may be deferred");
+            // a discard with no period active has nothing to clear, but is consumed lest it clear the next period
+            match select(DISCARD_BATCH.wait(), DEFERRED_MIDI_MSG.receive()).await {
+                Either::First(()) => {}
+                Either::Second((x, msg)) => batch.defer(x, &msg),
             }
         }
     }
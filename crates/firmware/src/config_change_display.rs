@@ -0,0 +1,67 @@
+//! Tasks and types related to the indicator which acknowledges configuration changes.
+
+use embassy_stm32::gpio::Output;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+
+/// Identifies which configuration changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigChangeType {
+    /// The [`NotePriority`][midival_renaissance_lib::configuration::NotePriority] changed.
+    NotePriority,
+    /// The [`ChordCleanup`][midival_renaissance_lib::configuration::ChordCleanup] setting changed.
+    ChordCleanup,
+    /// The [`ControllerOutput`][midival_renaissance_lib::configuration::ControllerOutput] setting changed.
+    ControllerOutput,
+    /// The [`Arpeggiator`][midival_renaissance_lib::configuration::Arpeggiator]'s latch mode was toggled.
+    ArpeggiatorLatch,
+    /// The [`Arpeggiator`][midival_renaissance_lib::configuration::Arpeggiator] was switched on or off.
+    Arpeggiator,
+}
+
+impl ConfigChangeType {
+    /// The number of times the LED blinks to identify the change.
+    fn blink_cnt(&self) -> u32 {
+        match self {
+            Self::NotePriority => 1,
+            Self::ChordCleanup => 2,
+            Self::ControllerOutput => 3,
+            Self::ArpeggiatorLatch => 4,
+            Self::Arpeggiator => 5,
+        }
+    }
+}
+
+/// Signaled by every task which changes one of the configurations identified by [`ConfigChangeType`].
+pub static DISPLAY_CONFIG_CHANGE: Signal<CriticalSectionRawMutex, ConfigChangeType> = Signal::new();
+
+/// How long the acknowledgement of a configuration change lasts.
+const DISPLAY_DURATION: Duration = Duration::from_secs(1);
+
+/// The length of each blink (and of the gap between blinks).
+const BLINK_DURATION: Duration = Duration::from_millis(100);
+
+/// Acknowledges any configuration change by blinking the green LED, giving the performer feedback no matter which
+/// setting changed.
+///
+/// The number of blinks identifies the setting (see [`ConfigChangeType`]); the pattern always spans
+/// [`DISPLAY_DURATION`], after which the LED goes dark. The red and blue LEDs, which continuously display the status
+/// of particular settings, are left to their own tasks.
+#[embassy_executor::task]
+pub async fn display_config_change(mut led: Output<'static>) -> ! {
+    loop {
+        let change = DISPLAY_CONFIG_CHANGE.wait().await;
+
+        let blink_cnt = change.blink_cnt();
+        for _ in 0..blink_cnt {
+            led.set_high();
+            Timer::after(BLINK_DURATION).await;
+            led.set_low();
+            Timer::after(BLINK_DURATION).await;
+        }
+        // a pattern too long to fit is left to run over rather than underflowing
+        if let Some(rest) = DISPLAY_DURATION.checked_sub(BLINK_DURATION * 2 * blink_cnt) {
+            Timer::after(rest).await;
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Tasks and types related to the [controller output](`ControllerOutput`) on DAC channel 2.
+
+use crate::{
+    MidiStateReceiver,
+    config_change_display::{ConfigChangeType, DISPLAY_CONFIG_CHANGE},
+    debounce::debounced_press,
+    keyboard::{KBD_VOLTAGE, REFERENCE_VOLTAGE, SECOND_VOICE},
+};
+use embassy_futures::select::{Either3, select3};
+use embassy_stm32::{exti::ExtiInput, gpio::Level};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    watch::{Receiver, Sender, Watch},
+};
+use midival_renaissance_lib::{
+    configuration::{ControllerOutput, CycleConfig},
+    midi_state::{DEFAULT_CONTROLLER_MAX_VOLTS, MidiState},
+    voltage::Voltage,
+};
+
+const CONTROLLER_OUTPUT_RECEIVER_CNT: usize = 1;
+/// Syncs [controller output](`ControllerOutput`) config across tasks, starting from
+/// [`CONTROLLER_OUTPUT`][crate::CONTROLLER_OUTPUT].
+pub static CONTROLLER_OUTPUT_SYNC: Watch<
+    CriticalSectionRawMutex,
+    ControllerOutput,
+    CONTROLLER_OUTPUT_RECEIVER_CNT,
+> = Watch::new_with(crate::CONTROLLER_OUTPUT);
+pub type ControllerOutputSender<'a> =
+    Sender<'a, CriticalSectionRawMutex, ControllerOutput, CONTROLLER_OUTPUT_RECEIVER_CNT>;
+pub type ControllerOutputReceiver<'a> =
+    Receiver<'a, CriticalSectionRawMutex, ControllerOutput, CONTROLLER_OUTPUT_RECEIVER_CNT>;
+
+/// The voltage (in volts) output at the maximum value of the selected controller.
+///
+/// [`DEFAULT_CONTROLLER_MAX_VOLTS`] exceeds the DAC's reference voltage, so the full range is capped at what the DAC
+/// can produce unbuffered.
+const CONTROLLER_MAX_VOLTS: f64 = if DEFAULT_CONTROLLER_MAX_VOLTS < REFERENCE_VOLTAGE {
+    DEFAULT_CONTROLLER_MAX_VOLTS
+} else {
+    REFERENCE_VOLTAGE
+};
+
+/// Returns `true` if a controller currently claims DAC channel 2, in which case the second voice and filter tracking
+/// yield it.
+pub fn is_enabled() -> bool {
+    CONTROLLER_OUTPUT_SYNC
+        .try_get()
+        .is_some_and(|output| output.is_enabled())
+}
+
+/// Returns the voltage for the selected controller, offset from the KBD output's `kbd_voltage` when
+/// [`CONTROLLER_OUTPUT_FOLLOWS_KBD`][crate::CONTROLLER_OUTPUT_FOLLOWS_KBD] is set.
+fn output_voltage(midi: &MidiState, output: ControllerOutput, kbd_voltage: f32) -> Voltage {
+    let voltage = midi.controller_voltage(output, Voltage::from_volts(CONTROLLER_MAX_VOLTS));
+    if crate::CONTROLLER_OUTPUT_FOLLOWS_KBD {
+        Voltage::from_volts((voltage.as_volts() + f64::from(kbd_voltage)).min(CONTROLLER_MAX_VOLTS))
+    } else {
+        voltage
+    }
+}
+
+/// Task responsible for cycling through the [controller output](`ControllerOutput`) options with each press of the
+/// controller output button.
+///
+/// The button is optional: the pin is pulled up, so an unconnected pin never registers a press.
+#[embassy_executor::task]
+pub async fn controller_output_config(
+    mut button: ExtiInput<'static>,
+    controller_output: ControllerOutputSender<'static>,
+) -> ! {
+    loop {
+        debounced_press(&mut button, Level::Low).await;
+
+        let new_state = controller_output
+            .try_get()
+            .expect("Controller output state should never be uninitialized")
+            .cycle();
+        controller_output.send(new_state);
+        DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::ControllerOutput);
+    }
+}
+
+/// Task responsible for converting the selected controller to a voltage on DAC channel 2.
+///
+/// When a new controller is selected, the output jumps to that controller's current value. When the output is
+/// disabled, DAC channel 2 is left as it is until the next voicing update hands it back to the second voice or filter
+/// tracking.
+///
+/// This is the sole reader of [`KBD_VOLTAGE`], which it follows when
+/// [`CONTROLLER_OUTPUT_FOLLOWS_KBD`][crate::CONTROLLER_OUTPUT_FOLLOWS_KBD] is set.
+#[embassy_executor::task]
+pub async fn controller_output(
+    mut midi_state: MidiStateReceiver<'static>,
+    mut config: ControllerOutputReceiver<'static>,
+) -> ! {
+    let mut output = config.get().await;
+    let mut previous_midi = midi_state.get().await;
+    let mut kbd_voltage = 0.0;
+    // some controllers (e.g., Expression) don't rest at zero, so the output starts at the current value
+    if output.is_enabled() {
+        SECOND_VOICE.signal(output_voltage(&previous_midi, output, kbd_voltage));
+    }
+
+    loop {
+        match select3(midi_state.changed(), config.changed(), KBD_VOLTAGE.wait()).await {
+            Either3::First(midi) => {
+                if midi.diff(&previous_midi).contains_any(output.operation()) {
+                    SECOND_VOICE.signal(output_voltage(&midi, output, kbd_voltage));
+                }
+                previous_midi = midi;
+            }
+            Either3::Second(new_output) => {
+                output = new_output;
+                #[cfg(feature = "defmt")]
+                defmt::info!("Controller output set to {}", output);
+                if output.is_enabled() {
+                    SECOND_VOICE.signal(output_voltage(&previous_midi, output, kbd_voltage));
+                }
+            }
+            Either3::Third(voltage) => {
+                kbd_voltage = voltage;
+                if crate::CONTROLLER_OUTPUT_FOLLOWS_KBD && output.is_enabled() {
+                    SECOND_VOICE.signal(output_voltage(&previous_midi, output, kbd_voltage));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Debouncing for pushbutton inputs, whose mechanical contacts "bounce" for a few milliseconds on both press and
+//! release, producing a burst of edges where there should be one.
+
+use embassy_stm32::{exti::ExtiInput, gpio::Level};
+use embassy_time::{Duration, Timer};
+
+/// How long a pin must hold its level after an edge for the edge to count.
+const DEBOUNCE_PERIOD: Duration = Duration::from_millis(5);
+
+/// Resolves once the button wired to `pin` has been pressed, ignoring contact bounce.
+///
+/// `pressed` is the level the pin reads while the button is held: [`Level::Low`] for a button which pulls a pin with
+/// a pull-up resistor to ground, [`Level::High`] for, e.g., the Nucleo's user button.
+///
+/// Before a press is accepted, the previous one must have been released and the pin must have settled, so that
+/// release bounce isn't mistaken for a new press. A press is then accepted only if the pin still reads `pressed` once
+/// [`DEBOUNCE_PERIOD`] has elapsed since the leading edge. Callers act on the press after this function returns; the
+/// release is handled at the start of the next call.
+pub async fn debounced_press(pin: &mut ExtiInput<'static>, pressed: Level) {
+    while pin.get_level() == pressed {
+        match pressed {
+            Level::Low => pin.wait_for_high().await,
+            Level::High => pin.wait_for_low().await,
+        }
+        Timer::after(DEBOUNCE_PERIOD).await;
+    }
+
+    loop {
+        match pressed {
+            Level::Low => pin.wait_for_falling_edge().await,
+            Level::High => pin.wait_for_rising_edge().await,
+        }
+        Timer::after(DEBOUNCE_PERIOD).await;
+        if pin.get_level() == pressed {
+            return;
+        }
+    }
+}
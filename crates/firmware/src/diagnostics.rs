@@ -0,0 +1,16 @@
+//! Tools for diagnosing the device's behavior after the fact, e.g., following a performance.
+
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use midival_renaissance_lib::configuration::{ConfigChange, ConfigChangeLog};
+
+/// History of the most recent configuration changes, shared by all tasks that change configuration.
+pub static CONFIG_CHANGE_LOG: Mutex<CriticalSectionRawMutex, RefCell<ConfigChangeLog>> =
+    Mutex::new(RefCell::new(ConfigChangeLog::new()));
+
+/// Records a [`ConfigChange`] in the [`CONFIG_CHANGE_LOG`].
+pub fn record_config_change(change: ConfigChange) {
+    #[cfg(feature = "defmt")]
+    defmt::info!("Recording configuration change");
+    CONFIG_CHANGE_LOG.lock(|log| log.borrow_mut().record(change));
+}
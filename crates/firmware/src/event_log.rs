@@ -0,0 +1,25 @@
+//! Records the MIDI events the device receives, for post-mortem debugging.
+
+use core::cell::RefCell;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use midival_renaissance_lib::{
+    event_log::MidiEventLog,
+    timestamp::{EmbassyNow, NowProvider},
+};
+use wmidi::MidiMessage;
+
+/// The most recent MIDI events received, shared by [`midi_consumer_task`][crate::midi_consumer_task], which records
+/// them, and [`midi_task`][crate::midi_task], which dumps them to the host upon request; see [`write_sysex_dump`].
+pub static MIDI_EVENT_LOG: Mutex<CriticalSectionRawMutex, RefCell<MidiEventLog>> =
+    Mutex::new(RefCell::new(MidiEventLog::new()));
+
+/// Records a [`MidiMessage`] in the [`MIDI_EVENT_LOG`], timestamped with the current time.
+pub fn record_midi_event(msg: &MidiMessage) {
+    MIDI_EVENT_LOG.lock(|log| log.borrow_mut().record(msg, EmbassyNow::now()));
+}
+
+/// Writes the [`MIDI_EVENT_LOG`] to `buf` as a System Exclusive message, returning the number of bytes written; see
+/// [`MidiEventLog::write_sysex`].
+pub fn write_sysex_dump(buf: &mut [u8]) -> Option<usize> {
+    MIDI_EVENT_LOG.lock(|log| log.borrow().write_sysex(buf))
+}
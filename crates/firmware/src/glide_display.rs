@@ -0,0 +1,68 @@
+//! Tasks and types related to displaying the progress of a portamento.
+
+use crate::chord_cleanup::ChordCleanupSpy;
+use embassy_stm32::gpio::Output;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+use midival_renaissance_lib::{
+    configuration::{CUSTOM_STEP_MICROS, ChordCleanup, NotePriority},
+    portamento::Portamento,
+};
+
+/// Period of the software PWM which drives the LED, i.e., 200 Hz.
+const PWM_PERIOD: Duration = Duration::from_hz(200);
+
+/// The longest [custom](`ChordCleanup::Custom`) chord cleanup period the thumbwheel can select, displayed at full
+/// brightness.
+const MAX_CUSTOM_CHORD_CLEANUP_MICROS: u64 = CUSTOM_STEP_MICROS * 127;
+
+/// Signals the start (`Some`) and end (`None`) of a glide, as well as any change to a glide in progress, e.g., a new
+/// destination or duration.
+pub static GLIDE: Signal<CriticalSectionRawMutex, Option<Portamento<NotePriority>>> = Signal::new();
+
+/// Drives the blue LED, which does double duty as glide progress display and [chord cleanup](`ChordCleanup`) status indicator.
+///
+/// PB7 isn't wired to a timer channel that's convenient to use on the Nucleo, so the LED is dimmed via software PWM, with
+/// a duty cycle proportional to the progress of the glide: off at 0%, solid at 100%. When no glide is in progress, the
+/// LED reverts to displaying the chord cleanup status (see [`chord_cleanup_config`][crate::chord_cleanup::chord_cleanup_config]).
+#[embassy_executor::task]
+pub async fn glide_display(
+    mut led: Output<'static>,
+    mut chord_cleanup: ChordCleanupSpy<'static>,
+) -> ! {
+    let mut glide = None;
+
+    loop {
+        if let Some(g) = GLIDE.try_take() {
+            glide = g;
+        }
+
+        let duty = match &glide {
+            Some(portamento) => portamento.progress(),
+            None => match chord_cleanup
+                .try_get()
+                .expect("Chord cleanup state should never be uninitialized")
+            {
+                ChordCleanup::None => 0.0,
+                ChordCleanup::ThirtySecondNote => 1.0,
+                // a custom period dims the LED in proportion to its length, distinguishing it from the fixed settings
+                ChordCleanup::Custom(micros) => {
+                    micros as f64 / MAX_CUSTOM_CHORD_CLEANUP_MICROS as f64
+                }
+            },
+        };
+
+        let on_time =
+            Duration::from_micros((PWM_PERIOD.as_micros() as f64 * duty) as u64).min(PWM_PERIOD);
+        let off_time = PWM_PERIOD - on_time;
+
+        if on_time > Duration::MIN {
+            led.set_high();
+            Timer::after(on_time).await;
+        }
+        if off_time > Duration::MIN {
+            led.set_low();
+            Timer::after(off_time).await;
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! A factory test mode which exercises each hardware output in turn, so that a freshly built device can be checked
+//! with a multimeter, oscilloscope, or just a pair of eyes.
+//!
+//! The mode is entered by holding both the user button and the chord cleanup button at startup. Each test runs for
+//! [`TEST_DURATION`], after which the next begins; once all have run, the device reboots into normal operation.
+
+use embassy_stm32::{
+    Peri,
+    dac::{DacCh1, DacCh2, Value},
+    gpio::{Input, Output, Pull},
+    mode::Async,
+    peripherals::{DAC1, PC13, PD1},
+};
+use embassy_time::{Duration, Ticker, Timer, with_timeout};
+
+/// How long each output is tested before advancing to the next.
+const TEST_DURATION: Duration = Duration::from_secs(10);
+
+/// How often the DAC test patterns are updated.
+const DAC_STEP: Duration = Duration::from_millis(10);
+
+/// The largest value of the 12-bit DAC.
+const DAC_MAX: u32 = 4095;
+
+/// The outputs exercised by [`hardware_test`].
+pub struct Outputs {
+    /// Drives the Micromoog's KBD input.
+    pub dac_ch1: DacCh1<'static, DAC1, Async>,
+    /// Drives the second voice (or the Micromoog's ext in or filter cutoff, depending on configuration).
+    pub dac_ch2: DacCh2<'static, DAC1, Async>,
+    /// Drives the S-TRIG output.
+    pub gate: Output<'static>,
+    /// Displays the note priority.
+    pub red_led: Output<'static>,
+    /// Displays glide progress and chord cleanup status.
+    pub blue_led: Output<'static>,
+}
+
+/// Returns `true` if all of the device's buttons are held, signaling that hardware test mode should be entered.
+///
+/// The pins are borrowed only for the duration of the check so that they remain available to the normal tasks.
+pub async fn buttons_held(
+    user_button: Peri<'_, PC13>,
+    chord_cleanup_button: Peri<'_, PD1>,
+) -> bool {
+    let user_button = Input::new(user_button, Pull::None);
+    let chord_cleanup_button = Input::new(chord_cleanup_button, Pull::Up);
+    // give the pull-up a moment to settle before trusting the reading
+    Timer::after_millis(10).await;
+    user_button.is_high() && chord_cleanup_button.is_low()
+}
+
+/// Task which runs each hardware test in turn, then reboots the device.
+#[embassy_executor::task]
+pub async fn hardware_test(mut outputs: Outputs) -> ! {
+    #[cfg(feature = "defmt")]
+    defmt::info!("Entering hardware test mode");
+
+    #[cfg(feature = "defmt")]
+    defmt::info!("Testing DAC channel 1: ramp");
+    let _ = with_timeout(TEST_DURATION, test_dac_ch1(&mut outputs.dac_ch1)).await;
+    #[cfg(feature = "defmt")]
+    defmt::info!("Testing DAC channel 2: sine");
+    let _ = with_timeout(TEST_DURATION, test_dac_ch2(&mut outputs.dac_ch2)).await;
+    #[cfg(feature = "defmt")]
+    defmt::info!("Testing gate: 1 s on, 1 s off");
+    let _ = with_timeout(
+        TEST_DURATION,
+        blink(&mut outputs.gate, Duration::from_secs(1)),
+    )
+    .await;
+    outputs.gate.set_low();
+    #[cfg(feature = "defmt")]
+    defmt::info!("Testing red LED: 1 Hz blink");
+    let _ = with_timeout(
+        TEST_DURATION,
+        blink(&mut outputs.red_led, Duration::from_millis(500)),
+    )
+    .await;
+    outputs.red_led.set_low();
+    #[cfg(feature = "defmt")]
+    defmt::info!("Testing blue LED: 0.5 Hz blink");
+    let _ = with_timeout(
+        TEST_DURATION,
+        blink(&mut outputs.blue_led, Duration::from_secs(1)),
+    )
+    .await;
+
+    #[cfg(feature = "defmt")]
+    defmt::info!("Hardware test complete; rebooting");
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+/// Ramps DAC channel 1 from 0 to its maximum and back every 5 seconds.
+async fn test_dac_ch1(dac: &mut DacCh1<'static, DAC1, Async>) -> ! {
+    const HALF_PERIOD_STEPS: u32 = 250;
+    let mut ticker = Ticker::every(DAC_STEP);
+    let mut step = 0;
+    loop {
+        let rise = if step < HALF_PERIOD_STEPS {
+            step
+        } else {
+            2 * HALF_PERIOD_STEPS - step
+        };
+        dac.set(Value::Bit12Right(
+            (rise * DAC_MAX / HALF_PERIOD_STEPS) as u16,
+        ));
+        step = (step + 1) % (2 * HALF_PERIOD_STEPS);
+        ticker.next().await;
+    }
+}
+
+/// Approximates a 1 Hz sine wave on DAC channel 2, centered at half the DAC's range.
+///
+/// Each half-cycle uses Bhaskara I's approximation, `16x(π - x) / (5π² - 4x(π - x))`, which needs no floating point
+/// math: expressed in steps rather than radians, π cancels out.
+async fn test_dac_ch2(dac: &mut DacCh2<'static, DAC1, Async>) -> ! {
+    const HALF_PERIOD_STEPS: u32 = 50;
+    const CENTER: u32 = DAC_MAX / 2;
+    let mut ticker = Ticker::every(DAC_STEP);
+    let mut step = 0;
+    loop {
+        let x = step % HALF_PERIOD_STEPS;
+        let product = x * (HALF_PERIOD_STEPS - x);
+        let amplitude =
+            16 * product * CENTER / (5 * HALF_PERIOD_STEPS * HALF_PERIOD_STEPS - 4 * product);
+        let value = if step < HALF_PERIOD_STEPS {
+            CENTER + amplitude
+        } else {
+            CENTER - amplitude
+        };
+        dac.set(Value::Bit12Right(value as u16));
+        step = (step + 1) % (2 * HALF_PERIOD_STEPS);
+        ticker.next().await;
+    }
+}
+
+/// Toggles the output every `interval`.
+async fn blink(output: &mut Output<'static>, interval: Duration) -> ! {
+    loop {
+        output.toggle();
+        Timer::after(interval).await;
+    }
+}
@@ -1,26 +1,71 @@
 //! Controls the device's communication with the KBD input.
 
 use embassy_stm32::{
-    dac::{DacCh1, Value},
+    dac::{DacCh1, DacCh2, Value},
     mode::Async,
     peripherals::DAC1,
 };
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
-use midival_renaissance_lib::voltage::Voltage;
+use midival_renaissance_lib::{
+    configuration::{MICROMOOG_HIGHEST_NOTE, MICROMOOG_LOWEST_NOTE, MICROMOOG_VOLTS_PER_OCTAVE},
+    dac::DacConfig,
+    voltage::Voltage,
+};
 
 pub static KBD: Signal<CriticalSectionRawMutex, Voltage> = Signal::new();
 
+/// Carries the voltage for a second synthesizer, sent from DAC channel 2; see
+/// [`SecondVoiceOutput`][midival_renaissance_lib::configuration::SecondVoiceOutput].
+pub static SECOND_VOICE: Signal<CriticalSectionRawMutex, Voltage> = Signal::new();
+
+/// Snapshot of the voltage most recently sent to the KBD input, in volts.
+///
+/// Allows other tasks (e.g., one modulating the filter relative to the current pitch) to read the KBD output without
+/// duplicating the note-to-voltage logic. Expressed as a plain `f32` rather than a DAC value to keep it hardware-independent.
+/// Read by the [controller output][crate::controller_output::controller_output] task; as a [`Signal`] has a single
+/// waiter, a further reader would need this to become a [`Watch`][embassy_sync::watch::Watch].
+pub static KBD_VOLTAGE: Signal<CriticalSectionRawMutex, f32> = Signal::new();
+
 /// The reference voltage for the <abbr name="digital-to-analog converter">DAC</abbr> peripheral that services KBD input.
-const REFERENCE_VOLTAGE: f64 = 10.0 / 3.0;
+pub const REFERENCE_VOLTAGE: f64 = 10.0 / 3.0;
+
+/// The DAC channel which services KBD input, configured for 12-bit values.
+const DAC: DacConfig = DacConfig::new(REFERENCE_VOLTAGE, 12);
+
+/// Returns `true` if every note in the range `start..=end` (given as MIDI note numbers) can be voiced at the given
+/// volts per octave without exceeding the DAC's reference voltage.
+const fn validate_range(start: u8, end: u8, volts_per_octave: f64, reference_voltage: f64) -> bool {
+    start <= end && (end - start) as f64 * volts_per_octave / 12.0 <= reference_voltage
+}
 
-const DAC_RESOLUTION: u16 = 4096;
-const DAC_MAX_VALUE: u16 = DAC_RESOLUTION - 1;
+// Voltages beyond the reference voltage would clip, causing the top of the range to sound wrong.
+const _: () = assert!(
+    validate_range(
+        MICROMOOG_LOWEST_NOTE as u8,
+        MICROMOOG_HIGHEST_NOTE as u8,
+        MICROMOOG_VOLTS_PER_OCTAVE,
+        REFERENCE_VOLTAGE
+    ),
+    "Playable range exceeds what the DAC can output"
+);
 
-/// Converts the [`Voltage`] required to play a specific note to a <abbr name="digital-to-analog converter">DAC</abbr> value.
+/// Converts the [`Voltage`] required to play a specific note to a <abbr name="digital-to-analog converter">DAC</abbr>
+/// value, or returns `None` if the DAC can't output it.
+fn voltage_to_dac_value_checked(voltage: Voltage) -> Option<Value> {
+    DAC.to_dac_value_checked(voltage).map(Value::Bit12Right)
+}
+
+/// Converts the [`Voltage`] required to play a specific note to a <abbr name="digital-to-analog converter">DAC</abbr>
+/// value, warning of (and clamping) voltages the DAC can't output.
 fn voltage_to_dac_value(voltage: Voltage) -> Value {
-    Value::Bit12Right(
-        (voltage / Voltage::from_volts(REFERENCE_VOLTAGE) * f64::from(DAC_MAX_VALUE)) as u16,
-    )
+    voltage_to_dac_value_checked(voltage).unwrap_or_else(|| {
+        #[cfg(feature = "defmt")]
+        defmt::warn!(
+            "Voltage of {} is beyond the DAC's range; clamping",
+            voltage.as_volts()
+        );
+        Value::Bit12Right(DAC.to_dac_value(voltage))
+    })
 }
 
 /// Task responsible for communicating with the Micromoog's KBD input.
@@ -36,5 +81,22 @@ pub async fn keyboard(mut dac: DacCh1<'static, DAC1, Async>) -> ! {
             voltage.as_volts()
         );
         dac.set(dac_value);
+        KBD_VOLTAGE.signal(voltage.as_volts() as f32);
+    }
+}
+
+/// Task responsible for communicating with the KBD input of a second synthesizer, e.g., one voicing the top of a chord.
+#[embassy_executor::task]
+pub async fn second_voice(mut dac: DacCh2<'static, DAC1, Async>) -> ! {
+    loop {
+        let voltage = SECOND_VOICE.wait().await;
+        let dac_value = voltage_to_dac_value(voltage);
+        #[cfg(feature = "defmt")]
+        defmt::info!(
+            "Sending {} to DAC channel 2 to achieve a voltage of {}",
+            dac_value,
+            voltage.as_volts()
+        );
+        dac.set(dac_value);
     }
 }
@@ -16,21 +16,45 @@
 #![no_std]
 #![no_main]
 
+mod active_sensing;
+mod arpeggiator;
 mod chord_cleanup;
+mod config_change_display;
+mod controller_output;
+mod debounce;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+#[cfg(feature = "event_log")]
+mod event_log;
+mod glide_display;
+#[cfg(feature = "hw_test")]
+mod hardware_test;
 mod keyboard;
 mod note_provider;
+mod switches;
+mod thumbwheel;
+mod watchdog;
 
 use crate::{
-    chord_cleanup::{CHORD_CLEANUP_SYNC, ChordCleanupSpy, DEFERRED_MIDI_MSG, chord_cleanup_config},
-    keyboard::KBD,
+    active_sensing::{MIDI_RECEIVED, watch_active_sensing},
+    arpeggiator::{ARPEGGIATOR_ENABLED, ARPEGGIATOR_LATCH, ARPEGGIATOR_STEP},
+    chord_cleanup::{
+        CHORD_CLEANUP_SYNC, ChordCleanupSpy, DEFERRED_MIDI_MSG, chord_cleanup_config,
+        discard_deferred_midi_msgs,
+    },
+    controller_output::CONTROLLER_OUTPUT_SYNC,
+    glide_display::{GLIDE, glide_display},
+    keyboard::{KBD, SECOND_VOICE},
     note_provider::{
         NOTE_PROVIDER_SYNC, NoteProviderReceiver, display_note_provider, select_note_provider,
     },
+    switches::{STEP_BACK, SWITCHES, handle_switches},
+    watchdog::Monitored,
 };
 use defmt::{panic, *};
 use embassy_executor::Spawner;
 use embassy_futures::{
-    select::{Either3, select3},
+    select::{Either, Either5, select, select5},
     yield_now,
 };
 use embassy_stm32::{
@@ -40,20 +64,31 @@ use embassy_stm32::{
     gpio::{Level, Output, Pull, Speed},
     interrupt,
     peripherals::{self},
+    rng::{self, Rng},
     time::Hertz,
     usb,
 };
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
     signal::Signal,
     watch::{Receiver, Sender, Watch},
 };
-use embassy_time::Instant;
+use embassy_time::{Duration, Instant, Timer};
 use embassy_usb::{Builder, UsbDevice, class::midi::MidiClass, driver::EndpointError};
 use midival_renaissance_lib::{
-    configuration::{Keyboard, NotePriority},
-    midi_state::{MidiState, bytes_to_midi},
+    beat_clock::BeatClock,
+    configuration::{
+        Arpeggiator, ControllerOutput, FilterTracking, GateBehavior, InputMode, Keyboard,
+        LatchedNotes, NotePriority, PortamentoTimeCurve, SecondVoiceOutput, ThumbwheelAssignment,
+        TriggerEdge, VelocityGlide,
+    },
+    midi_state::{MidiState, Operation, StaccatoDetector, Tempo, bytes_to_midi},
     portamento::Portamento,
+    sysex::SysExRouter,
+    timestamp::{EmbassyNow, NowProvider},
+    usb_midi::{self, MidiPort, PACKET_SIZE, SysExResponder},
+    voice_history::VoiceHistory,
     voltage::Voltage,
 };
 use static_cell::StaticCell;
@@ -64,30 +99,105 @@ use {defmt_rtt as _, panic_probe as _};
 bind_interrupts!(
     #[doc(hidden)]
     struct Irqs {
+        EXTI0 => exti::InterruptHandler<interrupt::typelevel::EXTI0>;
         EXTI1 => exti::InterruptHandler<interrupt::typelevel::EXTI1>;
         EXTI15_10 => exti::InterruptHandler<interrupt::typelevel::EXTI15_10>;
         OTG_FS => usb::InterruptHandler<peripherals::USB_OTG_FS>;
+        RNG => rng::InterruptHandler<peripherals::RNG>;
     }
 );
 
 type UsbDriver = usb::Driver<'static, peripherals::USB_OTG_FS>;
 
-const MIDI_STATE_RECEIVER_CNT: usize = 1;
+const MIDI_STATE_RECEIVER_CNT: usize = 2;
 type MidiStateSync = Watch<CriticalSectionRawMutex, MidiState, MIDI_STATE_RECEIVER_CNT>;
 type MidiStateSender<'a> = Sender<'a, CriticalSectionRawMutex, MidiState, MIDI_STATE_RECEIVER_CNT>;
 type MidiStateReceiver<'a> =
     Receiver<'a, CriticalSectionRawMutex, MidiState, MIDI_STATE_RECEIVER_CNT>;
 
 /// Synchronizes MIDI state.
-static MIDI_STATE_SYNC: MidiStateSync = Watch::new();
+///
+/// Initialized at compile time so that no task can ever observe it uninitialized, regardless of spawn order.
+static MIDI_STATE_SYNC: MidiStateSync = Watch::new_with(MidiState::new());
 
 enum Trigger {
-    On,
+    On(Note),
     Off,
 }
 
 static TRIGGER: Signal<CriticalSectionRawMutex, Trigger> = Signal::new();
 
+/// Signaled when all notes are forced off, whether by All Notes Off (MIDI CC 123) or by the host disconnecting, so that
+/// [`update_voicing`] forgets its [`VoiceHistory`]. Signaled before the resulting MIDI state is sent, so it's seen when
+/// that state is.
+static ALL_NOTES_OFF: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The device ID to which System Exclusive messages (e.g., an Identity Request) may be addressed; see [`SysExRouter`].
+const SYSEX_DEVICE_ID: u8 = 0x10;
+
+/// When `true`, Control Change and aftertouch messages are echoed back to the host (e.g., so that a DAW can record them
+/// as automation). Note events are passed only to the synth, so the host never receives them a second time.
+const SELECTIVE_THRU: bool = false;
+
+/// Shape of the signal sent to the S-TRIG output; see [`GateBehavior`].
+const GATE_BEHAVIOR: GateBehavior = GateBehavior::Sustain;
+
+/// Selects which edge of the S-TRIG output starts a note; see [`TriggerEdge`].
+const TRIGGER_EDGE: TriggerEdge = TriggerEdge::Rising;
+
+/// Selects where note voltages are sent; see [`InputMode`].
+///
+/// In [`InputMode::Oscillator`], DAC channel 2 drives the Micromoog's ext in jack. The DAC's output can't drive the jack
+/// directly: it must pass through a buffer (e.g., an op-amp follower) and, depending on the level the jack expects, a
+/// voltage divider. As DAC channel 2 is otherwise used for [`SECOND_VOICE_OUTPUT`], the two are mutually exclusive.
+const INPUT_MODE: InputMode = InputMode::Keyboard;
+
+/// Selects the note, if any, sent to a second synthesizer via DAC channel 2; see [`SecondVoiceOutput`].
+const SECOND_VOICE_OUTPUT: SecondVoiceOutput = SecondVoiceOutput::Disabled;
+
+/// Determines how strongly DAC channel 2 tracks the voiced note when patched into the Micromoog's filter cutoff input;
+/// see [`FilterTracking`]. Like [`INPUT_MODE`]'s oscillator mode, this claims DAC channel 2, so it takes precedence over
+/// [`SECOND_VOICE_OUTPUT`].
+const FILTER_TRACKING: FilterTracking = FilterTracking::OFF;
+
+/// Selects the MIDI controller, if any, converted to a control voltage on DAC channel 2 at startup, e.g., for patching
+/// into the Micromoog's filter cutoff to sweep it with the mod wheel; see [`ControllerOutput`]. The selection can be
+/// cycled at runtime with the optional pushbutton on PD0 or by the switch assigned
+/// [`SwitchFunction::ControllerOutputToggle`]. While enabled, this claims DAC channel 2, so it takes precedence over
+/// [`FILTER_TRACKING`] and [`SECOND_VOICE_OUTPUT`].
+///
+/// [`SwitchFunction::ControllerOutputToggle`]: midival_renaissance_lib::configuration::SwitchFunction::ControllerOutputToggle
+const CONTROLLER_OUTPUT: ControllerOutput = ControllerOutput::Disabled;
+
+/// When `true`, the [`CONTROLLER_OUTPUT`] is offset from the voltage most recently sent to the KBD input, so that, e.g.,
+/// aftertouch opens the filter relative to the note being played rather than to a fixed cutoff.
+const CONTROLLER_OUTPUT_FOLLOWS_KBD: bool = false;
+
+/// When `Some`, held notes are voiced one at a time by the [`Arpeggiator`] rather than according to note priority.
+/// Configure it with [`RateSource::MidiClock`] to step in time with MIDI clock received over USB.
+///
+/// [`RateSource::MidiClock`]: midival_renaissance_lib::configuration::RateSource::MidiClock
+const ARPEGGIATOR: Option<Arpeggiator> = None;
+
+/// When `Some`, the optional thumbwheel (a potentiometer wired to PA3) controls the given parameter. Leave this `None`
+/// unless the potentiometer is fitted, as the unconnected pin would otherwise feed noise into the parameter.
+const THUMBWHEEL: Option<ThumbwheelAssignment> = None;
+
+/// Determines how Portamento Time maps to the duration of a glide; see [`PortamentoTimeCurve`]. Either curve takes
+/// Portamento Time (LSB), i.e., MIDI CC 37, into account when the controller sends it.
+const PORTAMENTO_TIME_CURVE: PortamentoTimeCurve = PortamentoTimeCurve::Linear;
+
+/// Determines whether the velocity of a note scales the duration of the glide toward it; see [`VelocityGlide`].
+const VELOCITY_GLIDE: VelocityGlide = VelocityGlide::Off;
+
+/// When `true`, portamento applies only to legato transitions, i.e., a note pressed while another is held or within
+/// moments of the last release (see [`StaccatoDetector`]); detached notes are voiced at once.
+const LEGATO_PORTAMENTO: bool = false;
+
+/// How long the S-TRIG output stays on after the last note is released, in case another note follows (e.g., a NoteOff
+/// and NoteOn arriving moments apart during fast legato playing). Zero disables the delay.
+const NOTE_OFF_DEBOUNCE: Duration = Duration::from_millis(0);
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("Initializing MIDIval Renaissance");
@@ -120,6 +230,27 @@ async fn main(spawner: Spawner) {
     }
     let p = embassy_stm32::init(config);
 
+    // holding all buttons at startup enters hardware test mode instead of normal operation
+    #[cfg(feature = "hw_test")]
+    let mut p = p;
+    #[cfg(feature = "hw_test")]
+    if hardware_test::buttons_held(p.PC13.reborrow(), p.PD1.reborrow()).await {
+        let (dac_ch1, dac_ch2) = Dac::new(p.DAC1, p.DMA1_CH5, p.DMA1_CH6, p.PA4, p.PA5).split();
+        unwrap!(
+            spawner.spawn(hardware_test::hardware_test(hardware_test::Outputs {
+                dac_ch1,
+                dac_ch2,
+                gate: Output::new(p.PG0, Level::Low, Speed::Low),
+                red_led: Output::new(p.PB14, Level::Low, Speed::Low),
+                blue_led: Output::new(p.PB7, Level::Low, Speed::Low),
+            }))
+        );
+        return;
+    }
+
+    // hardware test mode runs none of the tasks the watchdog monitors, so it is only started for normal operation
+    unwrap!(spawner.spawn(watchdog::feed_watchdog(watchdog::new(p.IWDG))));
+
     let button = ExtiInput::new(p.PC13, p.EXTI13, Pull::None, Irqs);
     let note_provider_sender = NOTE_PROVIDER_SYNC.sender();
     unwrap!(spawner.spawn(select_note_provider(button, note_provider_sender)));
@@ -130,10 +261,21 @@ async fn main(spawner: Spawner) {
         .expect("Note provider synchronizer should have a receiver available");
     unwrap!(spawner.spawn(display_note_provider(red_led, note_provider_receiver)));
 
+    let green_led = Output::new(p.PB0, Level::Low, Speed::Low);
+    unwrap!(spawner.spawn(config_change_display::display_config_change(green_led)));
+
     let toggle = ExtiInput::new(p.PD1, p.EXTI1, Pull::Up, Irqs);
     let blue_led = Output::new(p.PB7, Level::Low, Speed::Low);
     let chord_cleanup = CHORD_CLEANUP_SYNC.sender();
-    unwrap!(spawner.spawn(chord_cleanup_config(toggle, blue_led, chord_cleanup)));
+    unwrap!(spawner.spawn(chord_cleanup_config(toggle, chord_cleanup)));
+    unwrap!(spawner.spawn(glide_display(blue_led, CHORD_CLEANUP_SYNC.anon_receiver())));
+
+    unwrap!(spawner.spawn(handle_switches(
+        NOTE_PROVIDER_SYNC.sender(),
+        CHORD_CLEANUP_SYNC.sender(),
+        CONTROLLER_OUTPUT_SYNC.sender(),
+        MIDI_STATE_SYNC.sender()
+    )));
 
     // Create the driver, from the HAL.
     static ENDPOINT_OUT_BUFFER: StaticCell<[u8; 256]> = StaticCell::new();
@@ -192,20 +334,43 @@ async fn main(spawner: Spawner) {
     // DMA: direct memory access controller
     let dac_ch1_dma = p.DMA1_CH5;
 
-    // the second DAC channel will provide as-yet unimplemented input to the Micromoog (perhaps to OSC)
+    // the second DAC channel can drive a second synth (see `SECOND_VOICE_OUTPUT`); alternatively, it may someday provide
+    // as-yet unimplemented input to the Micromoog (perhaps to OSC)
     let dac_ch2_out = p.PA5;
     let dac_ch2_dma = p.DMA1_CH6;
 
-    let (dac_ch1, _dac_ch2) =
+    let (dac_ch1, dac_ch2) =
         Dac::new(p.DAC1, dac_ch1_dma, dac_ch2_dma, dac_ch1_out, dac_ch2_out).split();
 
     unwrap!(spawner.spawn(usb_task(usb)));
 
+    unwrap!(spawner.spawn(midi_task(class)));
+
     let chord_cleanup = CHORD_CLEANUP_SYNC.anon_receiver();
     let midi_state_sender = MIDI_STATE_SYNC.sender();
-    // initialize state before any dependent tasks so that they can always assume Some(state)
-    midi_state_sender.send(MidiState::default());
-    unwrap!(spawner.spawn(midi_task(class, chord_cleanup, midi_state_sender)));
+    unwrap!(spawner.spawn(midi_consumer_task(chord_cleanup, midi_state_sender)));
+
+    // the hardware random number generator seeds the arpeggiator, so that its random mode doesn't play the same
+    // sequence each time the unit is powered on; the device's unique ID is mixed in, so that should the RNG fail, each
+    // unit still plays a sequence of its own rather than that of an all-zero seed
+    let arpeggiator = match ARPEGGIATOR {
+        Some(arpeggiator) => {
+            let mut seed = [0; 8];
+            if Rng::new(p.RNG, Irqs)
+                .async_fill_bytes(&mut seed)
+                .await
+                .is_err()
+            {
+                warn!("Failed to seed the arpeggiator; random mode will repeat its sequence");
+            }
+            embassy_stm32::uid::uid()
+                .iter()
+                .enumerate()
+                .for_each(|(i, byte)| seed[i % seed.len()] ^= byte);
+            Some(arpeggiator.with_seed(u64::from_le_bytes(seed)))
+        }
+        None => None,
+    };
 
     let note_provider = NOTE_PROVIDER_SYNC
         .receiver()
@@ -216,16 +381,59 @@ async fn main(spawner: Spawner) {
                 .receiver()
                 .expect("MIDI State synchronizer should have a receiver available"),
             note_provider,
+            arpeggiator,
         ))
     );
 
+    if let Some(arpeggiator) = ARPEGGIATOR {
+        unwrap!(spawner.spawn(arpeggiator::arpeggiator_clock(arpeggiator)));
+    } else if THUMBWHEEL == Some(ThumbwheelAssignment::ArpeggiatorRate) {
+        warn!(
+            "The thumbwheel is assigned to the arpeggiator's rate, but no arpeggiator is configured"
+        );
+    }
+
     unwrap!(spawner.spawn(keyboard::keyboard(dac_ch1)));
+    // the controller output can claim DAC channel 2 at any time, so the task which drives it always runs
+    unwrap!(spawner.spawn(keyboard::second_voice(dac_ch2)));
+    if INPUT_MODE == InputMode::Keyboard {
+        let controller_output_button = ExtiInput::new(p.PD0, p.EXTI0, Pull::Up, Irqs);
+        unwrap!(spawner.spawn(controller_output::controller_output_config(
+            controller_output_button,
+            CONTROLLER_OUTPUT_SYNC.sender(),
+        )));
+        unwrap!(
+            spawner.spawn(controller_output::controller_output(
+                MIDI_STATE_SYNC
+                    .receiver()
+                    .expect("MIDI State synchronizer should have a receiver available"),
+                CONTROLLER_OUTPUT_SYNC
+                    .receiver()
+                    .expect("Controller output synchronizer should have a receiver available"),
+            ))
+        );
+    }
 
     unwrap!(spawner.spawn(chord_cleanup::handle_deferred_midi_msg(
         MIDI_STATE_SYNC.sender()
     )));
 
-    let switch_trigger = Output::new(p.PG0, Level::Low, Speed::Low);
+    unwrap!(spawner.spawn(watch_active_sensing()));
+    if let Some(assignment) = THUMBWHEEL {
+        unwrap!(spawner.spawn(thumbwheel::thumbwheel(
+            assignment,
+            p.ADC1,
+            p.PA3,
+            MIDI_STATE_SYNC.sender(),
+            CHORD_CLEANUP_SYNC.sender()
+        )));
+    }
+
+    let switch_trigger = Output::new(
+        p.PG0,
+        Level::from(!TRIGGER_EDGE.is_active_high()),
+        Speed::Low,
+    );
     unwrap!(spawner.spawn(trigger(switch_trigger)));
 }
 
@@ -234,55 +442,179 @@ async fn main(spawner: Spawner) {
 async fn update_voicing(
     mut midi_state: MidiStateReceiver<'static>,
     mut note_provider_state: NoteProviderReceiver<'static>,
+    mut arpeggiator: Option<Arpeggiator>,
 ) {
     // TODO: if/when support for additional instruments is added, these values should change based on the instrument
     // selection rather than be hardcoded here
-    let default_note = Note::F3;
-    let playable_notes = Note::F3..=Note::C6;
-    let voltage_per_octave = Voltage::from_volts(1.0);
-
-    let mut portamento = Portamento::new(
-        default_note,
-        default_note,
-        U7::from_u8_lossy(0),
-        Keyboard::new(
-            NotePriority::Low,
-            playable_notes.clone(),
-            voltage_per_octave,
-        ),
-    );
+    let micromoog = Keyboard::default();
+    // the Micromoog rests at the bottom of its keyboard when no note is activated, e.g., at startup, as that corresponds
+    // to 0 V at the KBD input
+    let default_note = micromoog.lowest_note();
+
+    let mut portamento: Portamento<NotePriority> =
+        Portamento::new(default_note, default_note, U7::from_u8_lossy(0), micromoog);
+    portamento.set_time_curve(PORTAMENTO_TIME_CURVE);
+    let mut displayed_glide = None;
+    let mut previous_midi = MidiState::new();
+    let mut voice_history = VoiceHistory::new();
+    let mut staccato_detector = StaccatoDetector::<EmbassyNow>::new();
+
+    if INPUT_MODE == InputMode::Oscillator {
+        // the keyboard module plays no part, so it rests at the bottom of its range
+        KBD.signal(Voltage::from_volts(0.0));
+        if SECOND_VOICE_OUTPUT != SecondVoiceOutput::Disabled {
+            warn!("Second voice output is unavailable in oscillator input mode");
+        }
+        if FILTER_TRACKING.is_enabled() {
+            warn!("Filter tracking is unavailable in oscillator input mode");
+        }
+        if CONTROLLER_OUTPUT.is_enabled() {
+            warn!("Controller output is unavailable in oscillator input mode");
+        }
+    } else if CONTROLLER_OUTPUT.is_enabled()
+        && (FILTER_TRACKING.is_enabled() || SECOND_VOICE_OUTPUT != SecondVoiceOutput::Disabled)
+    {
+        warn!(
+            "Filter tracking and second voice output are unavailable while controller output is enabled"
+        );
+    } else if FILTER_TRACKING.is_enabled() && SECOND_VOICE_OUTPUT != SecondVoiceOutput::Disabled {
+        warn!("Second voice output is unavailable while filter tracking is enabled");
+    }
+
+    let mut latched_notes = LatchedNotes::new();
 
     loop {
-        let (midi, note_provider, voltage) = match select3(
-            midi_state.changed(),
-            note_provider_state.changed(),
-            portamento.glide(),
+        let (midi, note_provider, voltage, is_step_back) = match watchdog::checking_in(
+            Monitored::Voicing,
+            select5(
+                midi_state.changed(),
+                note_provider_state.changed(),
+                portamento.glide(),
+                ARPEGGIATOR_STEP.wait(),
+                STEP_BACK.wait(),
+            ),
         )
         .await
         {
-            Either3::First(state) => (Some(state), None, None),
-            Either3::Second(np) => (None, Some(np), None),
-            Either3::Third(voltage) => (None, None, Some(voltage)),
+            Either5::First(state) => (Some(state), None, None, false),
+            Either5::Second(np) => (None, Some(np), None, false),
+            Either5::Third(voltage) => (None, None, Some(voltage), false),
+            // stepping is handled like a change in MIDI state: the note is provided anew, and the glide redirected
+            Either5::Fourth(()) => {
+                if let Some(arp) = arpeggiator.as_mut() {
+                    arp.advance();
+                }
+                (None, None, None, false)
+            }
+            Either5::Fifth(()) => (None, None, None, true),
         };
 
+        if ALL_NOTES_OFF.try_take().is_some() {
+            voice_history.clear();
+        }
+
+        // the MIDI state may have changed several times since this task last ran, so the snapshots are compared rather
+        // than relying on whichever message caused the latest update; changes to, e.g., switches don't affect voicing
+        if let Some(state) = midi {
+            let operation = state.diff(&previous_midi);
+            // a snapshot may both release and press notes; the press is classified against the notes held before it
+            if operation.contains(Operation::NOTE_CHANGE) {
+                let previous = &previous_midi.activated_notes;
+                if previous.iter().any(|n| !state.activated_notes.contains(n)) {
+                    staccato_detector.note_off();
+                }
+                if state.activated_notes.iter().any(|n| !previous.contains(n)) {
+                    staccato_detector.note_on(previous);
+                }
+            }
+            previous_midi = state;
+            if !operation.contains_any(
+                Operation::NOTE_CHANGE
+                    | Operation::PORTAMENTO_CHANGE
+                    | Operation::DETUNE_CHANGE
+                    | Operation::TUNING_CHANGE
+                    | Operation::PITCH_BEND_CHANGE,
+            ) {
+                continue;
+            }
+        }
+
         let midi = midi.unwrap_or(midi_state.get().await);
 
-        let keyboard = Keyboard::new(
-            note_provider.unwrap_or(note_provider_state.get().await),
-            playable_notes.clone(),
-            voltage_per_octave,
-        );
-        let note = keyboard.provide_note(&midi.activated_notes);
+        // while the arpeggiator is switched off, held notes are voiced according to note priority
+        let active_arpeggiator = arpeggiator.filter(|_| {
+            ARPEGGIATOR_ENABLED
+                .try_get()
+                .expect("Arpeggiator enabled state should never be uninitialized")
+        });
+
+        // in latch mode, the arpeggio continues through released notes; switching latch off while no keys are held
+        // stops the arpeggio on its next step
+        let is_latched = active_arpeggiator.is_some()
+            && ARPEGGIATOR_LATCH
+                .try_get()
+                .expect("Arpeggiator latch state should never be uninitialized");
+        let activated_notes = if is_latched {
+            latched_notes.update(&midi.activated_notes);
+            latched_notes.notes()
+        } else {
+            latched_notes.clear();
+            &midi.activated_notes
+        };
+
+        let keyboard =
+            micromoog.with_note_provider(note_provider.unwrap_or(note_provider_state.get().await));
+        let note = match active_arpeggiator {
+            Some(arp) => micromoog
+                .with_note_provider(arp)
+                .provide_note(activated_notes),
+            None => keyboard.provide_note(&midi.activated_notes),
+        };
 
         // when waking due to changes in MIDI or note priority config, the portamento state may need to be invalidated
         if voltage.is_none() {
-            portamento.set_duration(midi.portamento.time());
+            // with Portamento switched off, or on a detached note in legato mode, note changes are instantaneous
+            portamento.set_high_res_duration(
+                if midi.portamento.is_enabled()
+                    && (!LEGATO_PORTAMENTO || staccato_detector.is_legato())
+                {
+                    midi.portamento.high_res_time()
+                } else {
+                    0
+                },
+            );
 
             if let Some(n) = note
                 && portamento.destination() != n
             {
                 portamento = portamento.new_destination(n);
+                // CC 84 (Portamento Control) names the note to glide from in place of wherever the voice was
+                if let Some(origin) = midi.portamento.glide_origin() {
+                    portamento.set_origin_note(origin);
+                }
+                voice_history.expire(EmbassyNow::now());
+                voice_history.push(n);
+                if let Some(velocity) = activated_notes.velocity_of(n) {
+                    portamento.set_velocity(velocity, VELOCITY_GLIDE);
+                }
+            } else if is_step_back && activated_notes.is_empty() {
+                // the held voltage moves back through the history, gliding just as a played note would
+                voice_history.expire(EmbassyNow::now());
+                if let Some(n) = voice_history.step_back() {
+                    portamento = portamento.new_destination(n);
+                }
+            }
+        }
+
+        if activated_notes.is_empty() {
+            voice_history.release(EmbassyNow::now());
+            // the next chord starts a fresh arpeggio
+            if let Some(arp) = arpeggiator.as_mut() {
+                arp.reset();
             }
+        } else {
+            // pressing the note already voiced again doesn't push it, but still ends the rest
+            voice_history.hold();
         }
 
         // Calculating the voltage involves a fair amount of math (and hence some number of processor ticks). Taking a snapshot of the status here
@@ -290,12 +622,49 @@ async fn update_voicing(
         // calculating that the portamento is complete, precluding entering the loop again before actually sending the 100% true voltage. See usage below.
         let portamento_has_more_work = !portamento.is_done();
 
-        KBD.signal(voltage.unwrap_or(portamento.voltage()));
+        // the glide display tracks progress on its own, so it only needs to hear about a glide when it starts, ends, or is
+        // retargeted or re-timed mid-flight
+        let glide = portamento_has_more_work.then_some(portamento);
+        if glide != displayed_glide {
+            displayed_glide = glide;
+            GLIDE.signal(glide);
+        }
+
+        // transposing, detuning and bending apply on top of the glide, as the Micromoog's octave switch, fine-tune knob and
+        // pitch ribbon would; any of them may push the voltage past what the KBD input tolerates, e.g., bending the top
+        // note up, so the sum is clamped
+        let note_voltage = keyboard.clamp_voltage(
+            voltage.unwrap_or(portamento.voltage())
+                + keyboard.interval_voltage(midi.detune() + f64::from(midi.transpose()))
+                + midi.pitch_bend.offset_voltage(&keyboard),
+        );
+        match INPUT_MODE {
+            InputMode::Keyboard => KBD.signal(note_voltage),
+            InputMode::Oscillator => SECOND_VOICE.signal(note_voltage),
+        }
 
-        TRIGGER.signal(if note.is_none() {
-            Trigger::Off
-        } else {
-            Trigger::On
+        // the second voice is updated in the same iteration as the first to avoid timing skew between the two
+        let second_voice = micromoog.with_note_provider(SECOND_VOICE_OUTPUT);
+        // in oscillator mode, DAC channel 2 carries the note voltage; with controller output, it belongs to that task
+        let dac_ch2_available =
+            INPUT_MODE == InputMode::Keyboard && !controller_output::is_enabled();
+        if dac_ch2_available && FILTER_TRACKING.is_enabled() {
+            // the filter follows the note being voiced, holding its cutoff after release just as the KBD voltage does
+            SECOND_VOICE.signal(
+                keyboard.filter_tracking_cv(
+                    portamento.destination(),
+                    FILTER_TRACKING.tracking_amount(),
+                ),
+            );
+        } else if dac_ch2_available
+            && let Some(n) = second_voice.provide_note(&midi.activated_notes)
+        {
+            SECOND_VOICE.signal(second_voice.voltage(n));
+        }
+
+        TRIGGER.signal(match note {
+            Some(n) => Trigger::On(n),
+            None => Trigger::Off,
         });
 
         // If the portamento hasn't reached its destination yet, this task should execute again soon. First, however, it should yield control back
@@ -309,19 +678,57 @@ async fn update_voicing(
 }
 
 /// Task responsible for communicating with the Micromoog's S-TRIG input.
+///
+/// When [`GATE_BEHAVIOR`] calls for a trigger, each new note produces a pulse of fixed width, and the output returns to
+/// rest afterward even if the note is still held. Otherwise, the output returns to rest only once [`NOTE_OFF_DEBOUNCE`]
+/// elapses without another note. The output rests low unless [`TRIGGER_EDGE`] inverts it.
 #[embassy_executor::task]
 async fn trigger(mut switch_trigger: Output<'static>) -> ! {
+    // with a falling edge, the output idles high and the levels are simply swapped
+    let note_on = Level::from(TRIGGER_EDGE.is_active_high());
+    let note_off = Level::from(!TRIGGER_EDGE.is_active_high());
+    let mut voiced_note: Option<Note> = None;
+    let mut next_trigger: Option<Trigger> = None;
+
     loop {
-        match TRIGGER.wait().await {
-            Trigger::On => {
-                #[cfg(feature = "defmt")]
-                info!("Note is on");
-                switch_trigger.set_high();
+        let trigger = match next_trigger.take() {
+            Some(trigger) => trigger,
+            None => TRIGGER.wait().await,
+        };
+        match trigger {
+            Trigger::On(note) => {
+                match GATE_BEHAVIOR.pulse_width() {
+                    None => {
+                        #[cfg(feature = "defmt")]
+                        info!("Note is on");
+                        switch_trigger.set_level(note_on);
+                    }
+                    // the voicing task signals repeatedly (e.g., during a glide), so only a new note fires a pulse
+                    Some(width) if voiced_note != Some(note) => {
+                        #[cfg(feature = "defmt")]
+                        info!("Triggering note");
+                        switch_trigger.set_level(note_on);
+                        Timer::after(width).await;
+                        switch_trigger.set_level(note_off);
+                    }
+                    Some(_) => {}
+                }
+                voiced_note = Some(note);
             }
             Trigger::Off => {
+                if voiced_note.is_some() && NOTE_OFF_DEBOUNCE > Duration::MIN {
+                    // a trigger arriving before the debounce expires is handled as though the note were never released
+                    if let Either::First(trigger) =
+                        select(TRIGGER.wait(), Timer::after(NOTE_OFF_DEBOUNCE)).await
+                    {
+                        next_trigger = Some(trigger);
+                        continue;
+                    }
+                }
                 #[cfg(feature = "defmt")]
                 info!("Note is off");
-                switch_trigger.set_low();
+                switch_trigger.set_level(note_off);
+                voiced_note = None;
             }
         }
     }
@@ -332,17 +739,51 @@ async fn usb_task(mut usb: UsbDevice<'static, UsbDriver>) -> ! {
     usb.run().await
 }
 
+/// Task responsible for receiving MIDI over USB, one connection at a time.
+///
+/// Received packets are handed off to [`midi_consumer_task`] via [`USB_MIDI`], so that time spent processing MIDI
+/// never delays reading the next packet. System Exclusive messages are answered here, as this task holds the
+/// connection; with the `event_log` feature, this includes requests for a dump of the `MIDI_EVENT_LOG`.
+///
+/// Checks in with the watchdog while waiting for a connection or a packet; if [`USB_MIDI`] is never drained, the task
+/// stops checking in and the device resets.
 #[embassy_executor::task]
-async fn midi_task(
-    mut class: MidiClass<'static, UsbDriver>,
-    mut chord_cleanup: ChordCleanupSpy<'static>,
-    mut midi_state: MidiStateSender<'static>,
-) -> ! {
+async fn midi_task(class: MidiClass<'static, UsbDriver>) -> ! {
+    let mut port = UsbMidiPort(class);
+    let sysex = SysExResponder::new(SysExRouter::new(SYSEX_DEVICE_ID));
+    #[cfg(feature = "event_log")]
+    let sysex = sysex.with_event_log(event_log::write_sysex_dump);
+    let mut sysex = sysex;
     loop {
-        class.wait_connection().await;
+        watchdog::checking_in(Monitored::Midi, port.0.wait_connection()).await;
         info!("USB connected");
-        let _ = process_midi(&mut class, &mut chord_cleanup, &mut midi_state).await;
+        let state = MIDI_STATE_SYNC
+            .anon_receiver()
+            .try_get()
+            .expect("MIDI state should never be uninitialized");
+        let _ = usb_midi::announce_state(&mut port, &state).await;
+        // received packets are passed along to `midi_consumer_task`; if `SELECTIVE_THRU` is enabled, the appropriate
+        // MIDI is echoed back to the host (see `usb_midi::echo_thru`); reading stops once the host disconnects, though a
+        // buffer overflow panics (see `Disconnected`)
+        let _ = usb_midi::receive_midi(
+            &mut port,
+            SELECTIVE_THRU,
+            &mut sysex,
+            async |data: &[u8]| {
+                let mut packet = [0; PACKET_SIZE];
+                packet[..data.len()].copy_from_slice(data);
+                USB_MIDI
+                    .send(UsbMidi::Packet {
+                        data: packet,
+                        len: data.len(),
+                    })
+                    .await;
+            },
+        )
+        .await
+        .map_err(Disconnected::from);
         info!("USB disconnected");
+        USB_MIDI.send(UsbMidi::ConnectionLost).await;
     }
 }
 
@@ -358,19 +799,109 @@ impl From<EndpointError> for Disconnected {
     }
 }
 
-/// Helper function which interprets data received over USB.
+/// Adapts the USB MIDI class to the library's [`MidiPort`], through which the receive path is written so that it can be
+/// exercised on the host; see [`usb_midi::receive_midi`].
+struct UsbMidiPort(MidiClass<'static, UsbDriver>);
+
+impl MidiPort for UsbMidiPort {
+    type Error = EndpointError;
+
+    /// Checks in with the watchdog while waiting for the next packet, which may legitimately take indefinitely.
+    async fn read_packet(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        watchdog::checking_in(Monitored::Midi, self.0.read_packet(buf)).await
+    }
+
+    async fn write_packet(&mut self, data: &[u8]) -> Result<(), EndpointError> {
+        self.0.write_packet(data).await
+    }
+}
+
+/// What [`midi_task`] hands off to [`midi_consumer_task`].
+enum UsbMidi {
+    /// Data read from the USB MIDI class, containing one or more USB-MIDI Event Packets.
+    Packet {
+        /// Buffer sized for a full-speed USB packet.
+        data: [u8; 64],
+        /// The number of bytes of `data` which were actually received.
+        len: usize,
+    },
+    /// The connection to the sender was lost, whether the host disconnected or Active Sensing timed out (see
+    /// [`watch_active_sensing`]). Sent through the same queue as the data so that it's processed after any data received
+    /// before it.
+    ConnectionLost,
+}
+
+/// The number of USB packets which may await processing before [`midi_task`] stops reading.
+const USB_MIDI_CAPACITY: usize = 4;
+
+/// Carries MIDI from the task which reads it to the task which processes it.
+static USB_MIDI: Channel<CriticalSectionRawMutex, UsbMidi, USB_MIDI_CAPACITY> = Channel::new();
+
+/// Task responsible for interpreting MIDI received by [`midi_task`].
 ///
 /// Extracts MIDI from bytes, updates state, and schedules voicing update if appropriate.
-async fn process_midi<'d, T: usb::Instance + 'd>(
-    class: &mut MidiClass<'d, usb::Driver<'d, T>>,
-    chord_cleanup: &mut ChordCleanupSpy<'static>,
-    midi_state: &mut MidiStateSender<'static>,
-) -> Result<(), Disconnected> {
-    let mut buf = [0; 64];
+///
+/// [`MidiState`] lives in [`MIDI_STATE_SYNC`] rather than in any task, so settings (e.g., Portamento Time) survive a
+/// reconnect. Notes and performance controllers (e.g., the sustain pedal, Pitch Bend), however, are returned to rest on
+/// loss of connection (see [`UsbMidi::ConnectionLost`]): the messages releasing any keys (or pedal) held at the time
+/// would otherwise never arrive, leaving them stuck.
+///
+/// Timing Clock is kept out of [`MidiState::update`], as it arrives 24 times per beat: this task estimates the tempo
+/// itself, recording it in [`MidiState::bpm`], and doesn't publish packets of clock alone unless the tempo changes. The
+/// tempo sizes the chord cleanup period; as the clock may stop without any other MIDI following, this task also wakes
+/// to forget the tempo once it expires. The clock is also counted into [`ClockDivisions`] for the arpeggiator (see
+/// [`arpeggiator::CLOCK_DIVISIONS`]), restarting from the downbeat on MIDI Start.
+///
+/// [`ClockDivisions`]: midival_renaissance_lib::beat_clock::ClockDivisions
+#[embassy_executor::task]
+async fn midi_consumer_task(
+    mut chord_cleanup: ChordCleanupSpy<'static>,
+    midi_state: MidiStateSender<'static>,
+) -> ! {
     let mut chord_cleanup_start: Option<Instant> = None;
+    let mut tempo = Tempo::<EmbassyNow>::new();
+    let mut beat_clock = BeatClock::<EmbassyNow>::new();
     loop {
-        let n = class.read_packet(&mut buf).await?;
-        let bytes = &buf[..n];
+        let received = match tempo.expiry() {
+            Some(expiry) => match select(
+                USB_MIDI.receive(),
+                Timer::at(Instant::from_micros(expiry.as_micros())),
+            )
+            .await
+            {
+                Either::First(received) => received,
+                Either::Second(()) => {
+                    tempo.expire();
+                    let mut state = midi_state
+                        .try_get()
+                        .expect("MIDI state should never be uninitialized");
+                    if state.bpm != tempo.bpm() {
+                        state.bpm = tempo.bpm();
+                        midi_state.send(state);
+                    }
+                    continue;
+                }
+            },
+            None => USB_MIDI.receive().await,
+        };
+        let (data, len) = match received {
+            UsbMidi::Packet { data, len } => (data, len),
+            UsbMidi::ConnectionLost => {
+                // the sender can no longer release what it left held or bent, so the synth is returned to rest; sending
+                // the new state wakes `update_voicing`, which sees no activated notes and ends the trigger. Note events
+                // deferred beforehand are dropped, lest they reactivate notes once the batch expires
+                let mut state = midi_state
+                    .try_get()
+                    .expect("MIDI state should never be uninitialized");
+                state.lose_connection();
+                discard_deferred_midi_msgs();
+                chord_cleanup_start = None;
+                ALL_NOTES_OFF.signal(());
+                midi_state.send(state);
+                continue;
+            }
+        };
+        let bytes = &data[..len];
 
         let chord_cleanup = chord_cleanup
             .try_get()
@@ -381,11 +912,34 @@ async fn process_midi<'d, T: usb::Instance + 'd>(
             .as_mut()
             .expect("MIDI state should never be uninitialized"));
 
+        #[cfg(feature = "event_log")]
+        bytes_to_midi(bytes).for_each(|msg| event_log::record_midi_event(&msg));
+
+        let mut is_clock_only = true;
+        bytes_to_midi(bytes).for_each(|msg| match msg {
+            MidiMessage::TimingClock => {
+                tempo.tick();
+                if let Some(divisions) = beat_clock.tick() {
+                    arpeggiator::CLOCK_DIVISIONS.signal(divisions);
+                }
+            }
+            MidiMessage::Start => {
+                beat_clock.reset();
+                is_clock_only = false;
+            }
+            _ => is_clock_only = false,
+        });
+        let previous_bpm = state.bpm;
+        state.bpm = tempo.bpm();
+
+        // the batching period follows the tempo, as of the start of the packet
+        let chord_cleanup_period = state.bpm.map_or(chord_cleanup.duration(), |bpm| {
+            chord_cleanup.duration_at_bpm(bpm)
+        });
+
         let mut is_immediate_state_update = true;
+        let mut operation = Operation::empty();
         bytes_to_midi(bytes).for_each(|msg| match (chord_cleanup.is_enabled(), &msg) {
-            (false, _) => {
-                state.update(msg);
-            }
             (true, MidiMessage::NoteOn(_, _, _) | MidiMessage::NoteOff(_, _, _)) => {
                 is_immediate_state_update = false;
                 let now = Instant::now();
@@ -394,14 +948,14 @@ async fn process_midi<'d, T: usb::Instance + 'd>(
                 match chord_cleanup_start {
                     None => {
                         chord_cleanup_start = Some(now);
-                        expiry = now + chord_cleanup.duration();
+                        expiry = now + chord_cleanup_period;
                     }
                     Some(start) => {
-                        let x = start + chord_cleanup.duration();
+                        let x = start + chord_cleanup_period;
                         if now > x {
                             // in this branch, the note event arrived outside the previous cleanup period, starting a new period
                             chord_cleanup_start = Some(now);
-                            expiry = now + chord_cleanup.duration();
+                            expiry = now + chord_cleanup_period;
                         } else {
                             // otherwise, the previous expiry is valid for this event
                             expiry = x;
@@ -409,15 +963,40 @@ async fn process_midi<'d, T: usb::Instance + 'd>(
                     }
                 };
 
-                DEFERRED_MIDI_MSG.signal((expiry, msg.to_owned()));
+                if DEFERRED_MIDI_MSG
+                    .try_send((expiry, msg.to_owned()))
+                    .is_err()
+                {
+                    warn!("Deferred MIDI queue is full; dropping note event");
+                }
             }
-            (true, _) => {
-                state.update(msg);
+            _ => {
+                let msg_operation = state.update(msg);
+                // note events deferred before All Notes Off are dropped, so they can't reactivate notes once it takes
+                // effect; those deferred after it are kept
+                if msg_operation.contains(Operation::ALL_NOTES_OFF) {
+                    discard_deferred_midi_msgs();
+                    chord_cleanup_start = None;
+                }
+                operation |= msg_operation;
             }
         });
 
-        if is_immediate_state_update {
+        if operation.contains(Operation::ALL_NOTES_OFF) {
+            ALL_NOTES_OFF.signal(());
+        }
+
+        // a packet of deferred note events alone leaves the state to `handle_deferred_midi_msg`, but any other change it
+        // carries (e.g., All Notes Off) is published now, as the batch is applied to the published state
+        let is_state_changed = !operation.is_empty() || state.bpm != previous_bpm;
+        if is_state_changed || (is_immediate_state_update && !is_clock_only) {
             midi_state.send(state);
         }
+
+        if operation.contains(Operation::SWITCH_CHANGE) {
+            SWITCHES.signal(state.general_purpose_switches);
+        }
+
+        MIDI_RECEIVED.signal(operation.contains(Operation::ACTIVE_SENSING));
     }
 }
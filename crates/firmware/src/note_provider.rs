@@ -1,11 +1,19 @@
 //! Tasks and types related the configurations which determine which note will sound.
 
-use embassy_stm32::{exti::ExtiInput, gpio::Output};
+use crate::{
+    arpeggiator::ARPEGGIATOR_LATCH,
+    config_change_display::{ConfigChangeType, DISPLAY_CONFIG_CHANGE},
+    debounce::debounced_press,
+};
+use embassy_stm32::{
+    exti::ExtiInput,
+    gpio::{Level, Output},
+};
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex,
     watch::{Receiver, Sender, Watch},
 };
-use embassy_time::Timer;
+use embassy_time::{Duration, Timer, with_timeout};
 use midival_renaissance_lib::configuration::{CycleConfig, NotePriority};
 
 const NOTE_PROVIDER_RECEIVER_CNT: usize = 2;
@@ -20,20 +28,47 @@ pub type NoteProviderSender<'a> =
 pub type NoteProviderReceiver<'a> =
     Receiver<'a, CriticalSectionRawMutex, NotePriority, NOTE_PROVIDER_RECEIVER_CNT>;
 
+/// How long the note priority button must be held to toggle the arpeggiator's latch mode instead.
+const LATCH_PRESS_DURATION: Duration = Duration::from_millis(500);
+
 /// Handles button presses, cycling through the [`NotePriority`] configurations.
+///
+/// When the [`Arpeggiator`][midival_renaissance_lib::configuration::Arpeggiator] is configured, holding the button for
+/// [`LATCH_PRESS_DURATION`] toggles its latch mode instead; note priority then changes on release of a shorter press.
 #[embassy_executor::task]
 pub async fn select_note_provider(
     mut button: ExtiInput<'static>,
     note_provider: NoteProviderSender<'static>,
 ) -> ! {
     loop {
-        button.wait_for_rising_edge().await;
+        debounced_press(&mut button, Level::High).await;
+
+        if crate::ARPEGGIATOR.is_some()
+            && with_timeout(LATCH_PRESS_DURATION, button.wait_for_low())
+                .await
+                .is_err()
+        {
+            let latch = !ARPEGGIATOR_LATCH
+                .try_get()
+                .expect("Arpeggiator latch state should never be uninitialized");
+            ARPEGGIATOR_LATCH.sender().send(latch);
+            DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::ArpeggiatorLatch);
+            continue;
+        }
 
-        let new_state = note_provider
+        let previous_state = note_provider
             .try_get()
-            .expect("Note provider state should never be uninitialized")
-            .cycle();
+            .expect("Note provider state should never be uninitialized");
+        let new_state = previous_state.cycle();
         note_provider.send(new_state);
+        DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::NotePriority);
+        #[cfg(feature = "diagnostics")]
+        crate::diagnostics::record_config_change(
+            midival_renaissance_lib::configuration::ConfigChange::NotePriority {
+                previous: previous_state,
+                new: new_state,
+            },
+        );
     }
 }
 
@@ -0,0 +1,112 @@
+//! Tasks and types related to the general-purpose switch controllers (MIDI CC 80-83).
+
+use crate::{
+    MidiStateSender,
+    arpeggiator::ARPEGGIATOR_ENABLED,
+    chord_cleanup::ChordCleanupSender,
+    config_change_display::{ConfigChangeType, DISPLAY_CONFIG_CHANGE},
+    controller_output::ControllerOutputSender,
+    note_provider::NoteProviderSender,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use midival_renaissance_lib::configuration::{
+    ChordCleanup, CycleConfig, DEFAULT_SWITCH_ASSIGNMENT, SwitchFunction, switch_index,
+};
+
+/// Carries the state of the general-purpose switches whenever MIDI sets any of them.
+pub static SWITCHES: Signal<CriticalSectionRawMutex, [bool; 4]> = Signal::new();
+
+/// Signaled when a switch assigned [`SwitchFunction::StepBack`] is turned on.
+pub static STEP_BACK: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Dispatches changes to the general-purpose switches to the configuration each switch is assigned.
+///
+/// Switches are looked up by controller number, so assignments may be listed in any order; an assignment to a
+/// controller other than MIDI CC 80-83 is ignored.
+#[embassy_executor::task]
+pub async fn handle_switches(
+    note_provider: NoteProviderSender<'static>,
+    chord_cleanup: ChordCleanupSender<'static>,
+    controller_output: ControllerOutputSender<'static>,
+    midi_state: MidiStateSender<'static>,
+) -> ! {
+    let mut previous = [false; 4];
+
+    loop {
+        let switches = SWITCHES.wait().await;
+
+        for &(controller, function) in DEFAULT_SWITCH_ASSIGNMENT.iter() {
+            let Some(i) = switch_index(controller) else {
+                #[cfg(feature = "defmt")]
+                defmt::warn!(
+                    "Ignoring assignment to CC {}, which is not a general-purpose switch",
+                    controller
+                );
+                continue;
+            };
+            let is_on = switches[i];
+            if is_on == previous[i] {
+                continue;
+            }
+
+            match function {
+                SwitchFunction::NotePriorityToggle => {
+                    if is_on {
+                        let new_state = note_provider
+                            .try_get()
+                            .expect("Note provider state should never be uninitialized")
+                            .cycle();
+                        note_provider.send(new_state);
+                        DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::NotePriority);
+                    }
+                }
+                SwitchFunction::ChordCleanupToggle => {
+                    chord_cleanup.send(if is_on {
+                        ChordCleanup::ThirtySecondNote
+                    } else {
+                        ChordCleanup::None
+                    });
+                    DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::ChordCleanup);
+                }
+                SwitchFunction::ControllerOutputToggle => {
+                    if is_on {
+                        let new_state = controller_output
+                            .try_get()
+                            .expect("Controller output state should never be uninitialized")
+                            .cycle();
+                        controller_output.send(new_state);
+                        DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::ControllerOutput);
+                    }
+                }
+                // sending the state wakes `update_voicing`, which applies the change to the next glide
+                SwitchFunction::PortamentoToggle => {
+                    let mut state = midi_state
+                        .try_get()
+                        .expect("MIDI state should never be uninitialized");
+                    state.portamento.set_enabled(is_on);
+                    midi_state.send(state);
+                }
+                // `update_voicing` owns the voice history, and ignores the request while any note is held
+                SwitchFunction::StepBack => {
+                    if is_on {
+                        STEP_BACK.signal(());
+                    }
+                }
+                SwitchFunction::ArpeggiatorToggle => {
+                    if crate::ARPEGGIATOR.is_some() {
+                        ARPEGGIATOR_ENABLED.sender().send(is_on);
+                        DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::Arpeggiator);
+                    } else {
+                        #[cfg(feature = "defmt")]
+                        defmt::warn!(
+                            "Switch assigned to CC {} toggles the arpeggiator, which is not configured",
+                            controller
+                        );
+                    }
+                }
+            }
+        }
+
+        previous = switches;
+    }
+}
@@ -0,0 +1,91 @@
+//! Tasks and types related to the thumbwheel, a potentiometer providing continuous control over a configurable
+//! parameter.
+
+use crate::{
+    MidiStateSender,
+    arpeggiator::ARPEGGIATOR_RATE,
+    chord_cleanup::ChordCleanupSender,
+    config_change_display::{ConfigChangeType, DISPLAY_CONFIG_CHANGE},
+};
+use embassy_stm32::{
+    Peri,
+    adc::{Adc, SampleTime},
+    peripherals::{ADC1, PA3},
+};
+use embassy_time::{Duration, Ticker};
+use midival_renaissance_lib::{
+    configuration::{ChordCleanup, RateSource, ThumbwheelAssignment},
+    thumbwheel::{Thumbwheel, to_transpose},
+};
+
+/// How often the thumbwheel's ADC is read.
+const SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+
+/// Periodically reads the thumbwheel, applying meaningful changes to the parameter given by `assignment`.
+///
+/// The potentiometer's wiper is wired to PA3 (the Nucleo's A0 pin), which is read by ADC1 at 12-bit resolution. As
+/// the first reading is always applied, this task should only run when a potentiometer is fitted; otherwise, the
+/// floating pin's noise would be applied instead. See [`THUMBWHEEL`][crate::THUMBWHEEL].
+#[embassy_executor::task]
+pub async fn thumbwheel(
+    assignment: ThumbwheelAssignment,
+    adc: Peri<'static, ADC1>,
+    mut pin: Peri<'static, PA3>,
+    midi_state: MidiStateSender<'static>,
+    chord_cleanup: ChordCleanupSender<'static>,
+) -> ! {
+    let mut adc = Adc::new(adc);
+    let mut thumbwheel = Thumbwheel::new();
+    let mut ticker = Ticker::every(SAMPLE_PERIOD);
+
+    loop {
+        ticker.next().await;
+
+        let Some(value) = thumbwheel.update(adc.blocking_read(&mut pin, SampleTime::CYCLES480))
+        else {
+            continue;
+        };
+
+        match assignment {
+            ThumbwheelAssignment::PortamentoTime => {
+                let mut state = midi_state
+                    .try_get()
+                    .expect("MIDI state should never be uninitialized");
+                state.portamento.set_time(value);
+                midi_state.send(state);
+            }
+            ThumbwheelAssignment::ChordCleanupDuration => {
+                let previous_state = chord_cleanup
+                    .try_get()
+                    .expect("Chord cleanup state should never be uninitialized");
+                let new_state = ChordCleanup::from_control_value(value);
+                if new_state == previous_state {
+                    continue;
+                }
+                chord_cleanup.send(new_state);
+                DISPLAY_CONFIG_CHANGE.signal(ConfigChangeType::ChordCleanup);
+                #[cfg(feature = "diagnostics")]
+                crate::diagnostics::record_config_change(
+                    midival_renaissance_lib::configuration::ConfigChange::ChordCleanup {
+                        previous: previous_state,
+                        new: new_state,
+                    },
+                );
+            }
+            ThumbwheelAssignment::Transpose => {
+                let mut state = midi_state
+                    .try_get()
+                    .expect("MIDI state should never be uninitialized");
+                let semitones = to_transpose(value);
+                if semitones == state.transpose() {
+                    continue;
+                }
+                state.set_transpose(semitones);
+                midi_state.send(state);
+            }
+            ThumbwheelAssignment::ArpeggiatorRate => {
+                ARPEGGIATOR_RATE.signal(RateSource::from_control_value(value));
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Resets the device if the executor stops making progress, e.g., because a task is stuck in a loop that never yields,
+//! or if a monitored task stops checking in, e.g., because it is deadlocked.
+
+use core::{
+    pin::pin,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use embassy_futures::select::{Either, select};
+use embassy_stm32::{Peri, peripherals::IWDG, wdg::IndependentWatchdog};
+use embassy_time::{Duration, Timer};
+
+/// If the watchdog isn't fed within this long, the microcontroller resets.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the watchdog is fed; comfortably shorter than [`WATCHDOG_TIMEOUT`] so that ordinary scheduling jitter
+/// never triggers a reset.
+const FEED_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a monitored task checks in while idle; shorter than [`FEED_INTERVAL`] so that an idle task never misses a
+/// feeding.
+const CHECK_IN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A task whose liveness is a condition of feeding the watchdog.
+#[derive(Clone, Copy)]
+pub enum Monitored {
+    /// [`midi_task`][crate::midi_task], which receives MIDI over USB.
+    Midi = 1 << 0,
+    /// [`update_voicing`][crate::update_voicing], which voices the received MIDI.
+    Voicing = 1 << 1,
+}
+
+/// The [`Monitored`] tasks which have checked in since the watchdog was last fed.
+static CHECK_INS: AtomicU8 = AtomicU8::new(0);
+
+/// The value of [`CHECK_INS`] once every [`Monitored`] task has checked in.
+const ALL_CHECKED_IN: u8 = Monitored::Midi as u8 | Monitored::Voicing as u8;
+
+/// Records that `task` is still making progress.
+pub fn check_in(task: Monitored) {
+    CHECK_INS.fetch_or(task as u8, Ordering::Relaxed);
+}
+
+/// Awaits `future` on behalf of `task`, checking in every [`CHECK_IN_INTERVAL`] in the meantime.
+///
+/// Meant for waits which may legitimately last indefinitely (e.g., for the next MIDI packet), so that an idle task
+/// isn't mistaken for a stuck one. The future is polled throughout rather than restarted, so it needn't be
+/// cancel-safe.
+pub async fn checking_in<F: Future>(task: Monitored, future: F) -> F::Output {
+    let mut future = pin!(future);
+    loop {
+        check_in(task);
+        match select(future.as_mut(), Timer::after(CHECK_IN_INTERVAL)).await {
+            Either::First(output) => return output,
+            Either::Second(()) => {}
+        }
+    }
+}
+
+/// Task responsible for feeding the Independent Watchdog (IWDG).
+///
+/// The watchdog is fed only once every [`Monitored`] task has checked in since the last feeding, so a task which stops
+/// making progress (e.g., awaiting a channel which is never drained) results in a reset even though the executor
+/// carries on. Monitored tasks legitimately sleep for long stretches (e.g., when no MIDI is arriving), so they check in
+/// while idle via [`checking_in`]. Since Embassy's executor is cooperative, a task that hogs the executor starves this
+/// one too, so a runaway loop anywhere also results in a reset.
+#[embassy_executor::task]
+pub async fn feed_watchdog(mut watchdog: IndependentWatchdog<'static, IWDG>) -> ! {
+    watchdog.unleash();
+    loop {
+        Timer::after(FEED_INTERVAL).await;
+        if CHECK_INS
+            .compare_exchange(ALL_CHECKED_IN, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            watchdog.pet();
+        }
+    }
+}
+
+/// Constructs the [`IndependentWatchdog`] with the device's configured timeout.
+pub fn new(iwdg: Peri<'static, IWDG>) -> IndependentWatchdog<'static, IWDG> {
+    IndependentWatchdog::new(iwdg, WATCHDOG_TIMEOUT.as_micros() as u32)
+}
@@ -0,0 +1,242 @@
+//! Provides [`BeatClock`] for subdividing MIDI clock pulses into musical note values.
+
+use crate::{
+    configuration::{ClockDivision, PULSES_PER_QUARTER_NOTE},
+    timestamp::{NoteEventTimestamp, NowProvider},
+};
+use bitflags::bitflags;
+use core::marker::PhantomData;
+
+/// The number of pulses counted before the clock starts over: one whole note, the longest [`ClockDivision`].
+const PULSES_PER_WHOLE_NOTE: u32 = PULSES_PER_QUARTER_NOTE * 4;
+
+bitflags! {
+    /// A set of note values (i.e., divisions of the beat) which align with a MIDI clock pulse.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct ClockDivisions: u8 {
+        /// Fires every 24 pulses.
+        const QUARTER = 1;
+        /// Fires every 12 pulses.
+        const EIGHTH = 1 << 1;
+        /// Fires every 6 pulses.
+        const SIXTEENTH = 1 << 2;
+        /// Fires every 3 pulses.
+        const THIRTY_SECOND = 1 << 3;
+        /// Fires every 48 pulses.
+        const HALF = 1 << 4;
+        /// Fires every 96 pulses.
+        const WHOLE = 1 << 5;
+    }
+}
+
+impl From<ClockDivision> for ClockDivisions {
+    fn from(division: ClockDivision) -> Self {
+        match division {
+            ClockDivision::ThirtySecond => Self::THIRTY_SECOND,
+            ClockDivision::Sixteenth => Self::SIXTEENTH,
+            ClockDivision::Eighth => Self::EIGHTH,
+            ClockDivision::Quarter => Self::QUARTER,
+            ClockDivision::Half => Self::HALF,
+            ClockDivision::Whole => Self::WHOLE,
+        }
+    }
+}
+
+/// Counts incoming MIDI clock pulses, reporting which [`ClockDivisions`] fire on each one.
+///
+/// Features which need a lower-resolution clock (e.g., an arpeggiator stepping in 16th notes) can subscribe to the
+/// divisions they care about rather than counting pulses themselves.
+///
+/// Pulses are timestamped by the [`NowProvider`] `C`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeatClock<C> {
+    /// Position within the current whole note, from 0 through 95.
+    pulse: u8,
+    /// When the most recent pulse arrived, if one has.
+    last_pulse: Option<NoteEventTimestamp>,
+    clock: PhantomData<C>,
+}
+
+impl<C: NowProvider> Default for BeatClock<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: NowProvider> BeatClock<C> {
+    /// Constructs a new [`BeatClock`], positioned at the start of a beat.
+    pub const fn new() -> Self {
+        Self {
+            pulse: 0,
+            last_pulse: None,
+            clock: PhantomData,
+        }
+    }
+
+    /// Registers a MIDI clock pulse received now, returning the divisions which align with it, if any.
+    pub fn tick(&mut self) -> Option<ClockDivisions> {
+        self.tick_at(C::now())
+    }
+
+    fn tick_at(&mut self, now: NoteEventTimestamp) -> Option<ClockDivisions> {
+        let pulse = u32::from(self.pulse);
+        self.pulse = ((pulse + 1) % PULSES_PER_WHOLE_NOTE) as u8;
+        self.last_pulse = Some(now);
+
+        let divisions = [
+            ClockDivision::Whole,
+            ClockDivision::Half,
+            ClockDivision::Quarter,
+            ClockDivision::Eighth,
+            ClockDivision::Sixteenth,
+            ClockDivision::ThirtySecond,
+        ]
+        .into_iter()
+        .filter(|division| pulse % division.ppqn_divisor() == 0)
+        .fold(ClockDivisions::empty(), |acc, division| {
+            acc | division.into()
+        });
+
+        if divisions.is_empty() {
+            None
+        } else {
+            Some(divisions)
+        }
+    }
+
+    /// Returns when the most recent pulse arrived, if any have.
+    pub fn last_pulse(&self) -> Option<NoteEventTimestamp> {
+        self.last_pulse
+    }
+
+    /// Repositions the clock at the start of a beat, e.g., upon receipt of a MIDI Start message.
+    pub fn reset(&mut self) {
+        self.pulse = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    static MICROS: AtomicU64 = AtomicU64::new(0);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct FakeClock;
+
+    impl NowProvider for FakeClock {
+        fn now() -> NoteEventTimestamp {
+            NoteEventTimestamp::from_micros(MICROS.load(Ordering::Relaxed))
+        }
+    }
+
+    type BeatClock = super::BeatClock<FakeClock>;
+
+    fn pulse(clock: &mut BeatClock) -> Option<ClockDivisions> {
+        clock.tick_at(NoteEventTimestamp::default())
+    }
+
+    #[test]
+    fn tick() {
+        let mut clock = BeatClock::new();
+        let fired: [Option<ClockDivisions>; 24] = core::array::from_fn(|_| pulse(&mut clock));
+
+        assert_eq!(
+            Some(ClockDivisions::all()),
+            fired[0],
+            "Expected all divisions to fire on the downbeat"
+        );
+        assert_eq!(None, fired[1], "Expected left but got right");
+        assert_eq!(
+            Some(ClockDivisions::THIRTY_SECOND),
+            fired[3],
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(ClockDivisions::SIXTEENTH | ClockDivisions::THIRTY_SECOND),
+            fired[6],
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(
+                ClockDivisions::EIGHTH | ClockDivisions::SIXTEENTH | ClockDivisions::THIRTY_SECOND
+            ),
+            fired[12],
+            "Expected left but got right"
+        );
+        assert_eq!(
+            8,
+            fired.iter().flatten().count(),
+            "Expected a 32nd note to fire every third pulse"
+        );
+
+        let beat = ClockDivisions::QUARTER
+            | ClockDivisions::EIGHTH
+            | ClockDivisions::SIXTEENTH
+            | ClockDivisions::THIRTY_SECOND;
+        assert_eq!(
+            Some(beat),
+            pulse(&mut clock),
+            "Expected the 25th pulse to start a new beat"
+        );
+        (25..48).for_each(|_| {
+            pulse(&mut clock);
+        });
+        assert_eq!(
+            Some(beat | ClockDivisions::HALF),
+            pulse(&mut clock),
+            "Expected the 49th pulse to start a new half note"
+        );
+        (49..96).for_each(|_| {
+            pulse(&mut clock);
+        });
+        assert_eq!(
+            Some(ClockDivisions::all()),
+            pulse(&mut clock),
+            "Expected the 97th pulse to start a new whole note"
+        );
+    }
+
+    #[test]
+    fn from_clock_division() {
+        assert_eq!(
+            ClockDivisions::SIXTEENTH,
+            ClockDivisions::from(ClockDivision::Sixteenth),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            ClockDivisions::WHOLE,
+            ClockDivisions::from(ClockDivision::Whole),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut clock = BeatClock::new();
+        pulse(&mut clock);
+        pulse(&mut clock);
+        clock.reset();
+        assert_eq!(
+            Some(ClockDivisions::all()),
+            pulse(&mut clock),
+            "Expected reset to return to the downbeat"
+        );
+    }
+
+    #[test]
+    fn last_pulse() {
+        let mut clock = BeatClock::new();
+        assert_eq!(None, clock.last_pulse(), "Expected no pulse yet");
+
+        MICROS.store(20_000, Ordering::Relaxed);
+        clock.tick();
+        assert_eq!(
+            Some(NoteEventTimestamp::from_micros(20_000)),
+            clock.last_pulse(),
+            "Expected left but got right"
+        );
+    }
+}
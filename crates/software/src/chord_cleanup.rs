@@ -0,0 +1,462 @@
+//! Provides [`ChordCleanupBatch`] for applying the note events of a [chord cleanup][crate::configuration::ChordCleanup]
+//! period atomically.
+
+use crate::{
+    midi_state::MidiState,
+    timestamp::{EmbassyNow, NoteEventTimestamp, NowProvider},
+};
+use embassy_time::Instant;
+use tinyvec::ArrayVec;
+use wmidi::{Channel, MidiMessage, Note, U7};
+
+/// The number of note events a single chord cleanup period can hold: enough to press and release as many notes as
+/// [`ActivatedNotes`][crate::midi_state::ActivatedNotes] can hold.
+const BATCH_CAPACITY: usize = 64;
+
+/// Temporarily caches note events that comprise the performance (or release) of a chord.
+///
+/// The events are replayed through [`MidiState`] when the period ends, so that they are treated just as though they had
+/// been applied on receipt (e.g., a note released while the sustain pedal is down remains activated), and so that
+/// changes made to the state in the meantime (e.g., by the sustain pedal) are kept.
+///
+/// This struct contains no async logic of its own; the caller is expected to wake on either receipt of a deferred
+/// note event or the [expiry][Self::expiry] of the current period, whichever comes first.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChordCleanupBatch {
+    /// Note events received during the current period, in order of receipt.
+    events: ArrayVec<[DeferredNoteEvent; BATCH_CAPACITY]>,
+    /// The end of the current chord cleanup period, if one is active.
+    expiry: Option<Instant>,
+}
+
+/// A NoteOn or NoteOff held back until the end of a chord cleanup period.
+///
+/// Internally, this struct uses the [`U7`] type for the note because [`tinyvec`] requires that `Items` implement
+/// [`Default`], which [`Note`] does not.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct DeferredNoteEvent {
+    /// [`U7`] representation of the note
+    note: U7,
+    /// Index (0-15) of the MIDI channel on which the event was received
+    channel: u8,
+    /// The NoteOn velocity, or `None` for a NoteOff
+    velocity: Option<U7>,
+    /// When the event was received
+    received_at: NoteEventTimestamp,
+}
+
+impl DeferredNoteEvent {
+    /// Applies the event to `state` as though it were being received now.
+    fn apply(&self, state: &mut MidiState) {
+        let channel = Channel::from_index(self.channel)
+            .expect("Channel index should be taken from a Channel");
+        let note = Note::from(self.note);
+        match self.velocity {
+            Some(velocity) => state.press_note_at(channel, note, velocity, self.received_at),
+            None => state.release_note(channel, note),
+        }
+    }
+}
+
+impl ChordCleanupBatch {
+    /// Constructs a new [`ChordCleanupBatch`] with no active chord cleanup period.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the end of the current chord cleanup period, or `None` if no period is active.
+    pub fn expiry(&self) -> Option<Instant> {
+        self.expiry
+    }
+
+    /// Adds a NoteOn or NoteOff event to the batch; other messages are ignored.
+    ///
+    /// If no period is active, a new one is initiated, ending at `expiry`. While a period is active, `expiry` is ignored.
+    /// Events beyond the capacity of the batch are dropped.
+    pub fn defer(&mut self, expiry: Instant, msg: &MidiMessage) {
+        if self.expiry.is_none() {
+            #[cfg(feature = "defmt")]
+            defmt::info!("Initiating chord cleanup period");
+            self.expiry = Some(expiry);
+        }
+
+        let event = match msg {
+            MidiMessage::NoteOff(channel, note, _velocity) => {
+                #[cfg(feature = "defmt")]
+                defmt::info!(
+                    "Batching NoteOff: channel {}, note {}, velocity: {}",
+                    channel.number(),
+                    note.to_str(),
+                    u8::from(*_velocity)
+                );
+                DeferredNoteEvent {
+                    note: U7::from_u8_lossy(*note as u8),
+                    channel: channel.index(),
+                    velocity: None,
+                    received_at: EmbassyNow::now(),
+                }
+            }
+            MidiMessage::NoteOn(channel, note, velocity) => {
+                #[cfg(feature = "defmt")]
+                defmt::info!(
+                    "Batching NoteOn: channel {}, note {}, velocity: {}",
+                    channel.number(),
+                    note.to_str(),
+                    u8::from(*velocity)
+                );
+                DeferredNoteEvent {
+                    note: U7::from_u8_lossy(*note as u8),
+                    channel: channel.index(),
+                    velocity: Some(*velocity),
+                    received_at: EmbassyNow::now(),
+                }
+            }
+            _ => {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("Only NoteOff and NoteOn events may be deferred");
+                return;
+            }
+        };
+
+        if self.events.try_push(event).is_some() {
+            #[cfg(feature = "defmt")]
+            defmt::warn!("Chord cleanup batch is full; dropping note event");
+        }
+    }
+
+    /// Ends the active chord cleanup period, if any, discarding the batched note events rather than applying them.
+    ///
+    /// To be called when all notes are forced off (e.g., upon All Notes Off or loss of connection to the sender), lest
+    /// the batch later activate notes that can no longer be released.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "defmt")]
+        defmt::info!("Discarding chord cleanup batch");
+        *self = Self::new();
+    }
+
+    /// Ends the active chord cleanup period if it has expired as of `now`, applying the batched note events to `state`.
+    ///
+    /// Returns `true` if `state` was updated.
+    pub fn expire(&mut self, now: Instant, state: &mut MidiState) -> bool {
+        match self.expiry {
+            Some(expiry) if now >= expiry => {
+                #[cfg(feature = "defmt")]
+                defmt::info!("Chord cleanup period over; updating state");
+                self.expiry = None;
+                self.events.iter().for_each(|event| event.apply(state));
+                self.events.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::ChordCleanup;
+    use crate::mock_time::mock_driver;
+    use wmidi::{Channel, ControlFunction, Note, U7};
+
+    fn note_on(note: Note) -> MidiMessage<'static> {
+        MidiMessage::NoteOn(Channel::Ch1, note, U7::from_u8_lossy(100))
+    }
+
+    fn note_off(note: Note) -> MidiMessage<'static> {
+        MidiMessage::NoteOff(Channel::Ch1, note, U7::from_u8_lossy(0))
+    }
+
+    #[test]
+    fn batches_notes_within_period() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        let expiry = Instant::now() + period;
+        batch.defer(expiry, &note_on(Note::E4));
+        driver.advance(period / 4);
+        batch.defer(expiry, &note_on(Note::G4));
+        driver.advance(period / 4);
+        batch.defer(expiry, &note_on(Note::C4));
+
+        assert_eq!(Some(expiry), batch.expiry(), "Expected left but got right");
+        assert!(
+            !batch.expire(Instant::now(), &mut state),
+            "Expected period to be ongoing"
+        );
+        assert_eq!(
+            0,
+            state.activated_notes.iter().count(),
+            "Expected state to be untouched before the period ends"
+        );
+
+        driver.advance(period / 2);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected period to be over"
+        );
+        assert_eq!(None, batch.expiry(), "Expected no active period");
+        assert!(
+            state
+                .activated_notes
+                .iter()
+                .eq([Note::E4, Note::G4, Note::C4]),
+            "Expected all batched notes to be applied at once"
+        );
+    }
+
+    #[test]
+    fn same_packet() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        // note events parsed from the same USB packet share a timestamp and thus an expiry
+        let expiry = Instant::now() + period;
+        for note in [Note::C4, Note::E4, Note::G4] {
+            batch.defer(expiry, &note_on(note));
+        }
+
+        driver.advance(period);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected period to be over"
+        );
+        assert!(
+            state
+                .activated_notes
+                .iter()
+                .eq([Note::C4, Note::E4, Note::G4]),
+            "Expected no note from the packet to be lost"
+        );
+    }
+
+    #[test]
+    fn new_period_after_expiry() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        batch.defer(Instant::now() + period, &note_on(Note::C4));
+        driver.advance(period);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected first period to be over"
+        );
+
+        driver.advance(period);
+        let second_expiry = Instant::now() + period;
+        batch.defer(second_expiry, &note_on(Note::E4));
+        assert_eq!(
+            Some(second_expiry),
+            batch.expiry(),
+            "Expected a new period to begin; expected left but got right"
+        );
+        assert!(
+            !batch.expire(Instant::now(), &mut state),
+            "Expected second period to be ongoing"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4]),
+            "Expected only the first period's notes to be applied"
+        );
+
+        driver.advance(period);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected second period to be over"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4, Note::E4]),
+            "Expected the second period's notes to be applied"
+        );
+    }
+
+    #[test]
+    fn release_during_period() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        state.activated_notes.add(Note::C4);
+        state.activated_notes.add(Note::E4);
+        let mut batch = ChordCleanupBatch::new();
+
+        let expiry = Instant::now() + period;
+        batch.defer(expiry, &note_off(Note::E4));
+        batch.defer(expiry, &note_off(Note::C4));
+        batch.defer(expiry, &note_on(Note::F4));
+
+        driver.advance(period);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected period to be over"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::F4]),
+            "Expected released notes to be removed from state"
+        );
+    }
+
+    #[test]
+    fn sustain_during_period() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        state.update(note_on(Note::C4));
+        state.update(MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::DAMPER_PEDAL,
+            U7::MAX,
+        ));
+        let mut batch = ChordCleanupBatch::new();
+
+        let expiry = Instant::now() + period;
+        batch.defer(expiry, &note_off(Note::C4));
+        batch.defer(expiry, &note_on(Note::E4));
+        batch.defer(expiry, &note_off(Note::E4));
+
+        driver.advance(period);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected period to be over"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4, Note::E4]),
+            "Expected notes released during the period to be held by the pedal"
+        );
+
+        state.update(MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::DAMPER_PEDAL,
+            U7::MIN,
+        ));
+        assert!(
+            state.activated_notes.is_empty(),
+            "Expected batched releases to take effect once the pedal is lifted"
+        );
+    }
+
+    #[test]
+    fn clear() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        let expiry = Instant::now() + period;
+        batch.defer(expiry, &note_on(Note::C4));
+        batch.defer(expiry, &note_on(Note::E4));
+        state.all_notes_off();
+        batch.clear();
+        assert_eq!(None, batch.expiry(), "Expected no active period");
+
+        driver.advance(period);
+        assert!(
+            !batch.expire(Instant::now(), &mut state),
+            "Expected nothing to be applied once the batch is cleared"
+        );
+        assert!(
+            state.activated_notes.is_empty(),
+            "Expected notes batched before All Notes Off to stay off"
+        );
+    }
+
+    #[test]
+    fn lose_connection() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        state.apply(note_on(Note::C4));
+        batch.defer(Instant::now() + period, &note_on(Note::E4));
+        // e.g., Active Sensing times out before the period ends
+        state.lose_connection();
+        batch.clear();
+
+        driver.advance(period);
+        assert!(
+            !batch.expire(Instant::now(), &mut state),
+            "Expected nothing to be applied once the batch is cleared"
+        );
+        assert!(
+            state.activated_notes.is_empty(),
+            "Expected notes batched before the connection was lost to stay off"
+        );
+    }
+
+    #[test]
+    fn expires_at_expiry() {
+        use embassy_time::Duration;
+
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let start = Instant::now();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        batch.defer(start + period, &note_on(Note::C4));
+        driver.advance(Duration::from_micros(10_000));
+        batch.defer(Instant::now() + period, &note_on(Note::E4));
+
+        let expiry = batch.expiry().expect("Expected an active period");
+        assert_eq!(
+            start + Duration::from_micros(62_500),
+            expiry,
+            "Expected the period to be anchored to the first note; expected left but got right"
+        );
+
+        // the clock stands at 10_000 µs
+        driver.advance(Duration::from_micros(62_500 - 10_000 - 1));
+        assert!(
+            !batch.expire(Instant::now(), &mut state),
+            "Expected period to be ongoing"
+        );
+
+        driver.advance(Duration::from_micros(1));
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected period to be over"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4, Note::E4]),
+            "Expected both notes of the chord to be applied at expiry"
+        );
+
+        driver.advance(Duration::from_micros(70_000 - 62_500));
+        let second_expiry = Instant::now() + period;
+        batch.defer(second_expiry, &note_on(Note::G4));
+        assert_eq!(
+            Some(second_expiry),
+            batch.expiry(),
+            "Expected a note after expiry to start a new period; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn late_wake() {
+        let (_clock, driver) = mock_driver();
+        let period = ChordCleanup::ThirtySecondNote.duration();
+        let mut state = MidiState::default();
+        let mut batch = ChordCleanupBatch::new();
+
+        let expiry = Instant::now() + period;
+        batch.defer(expiry, &note_on(Note::C4));
+        batch.defer(expiry, &note_on(Note::E4));
+
+        // other tasks hog the executor, so the wake comes well after the period has ended
+        driver.advance(period * 2);
+        assert!(
+            batch.expire(Instant::now(), &mut state),
+            "Expected a late wake still to end the period"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4, Note::E4]),
+            "Expected no note to be lost to a late wake"
+        );
+    }
+}
@@ -1,17 +1,60 @@
 //! This module contains both user-configurable settings (implemented as enums) and traits to make them easier to work with in code.
 
+#[cfg(feature = "diagnostics")]
+mod change_log;
+#[cfg(feature = "diagnostics")]
+pub use change_log::*;
+
+mod arpeggiator;
+pub use arpeggiator::*;
+
 mod chord_cleanup;
 pub use chord_cleanup::*;
 
+mod clock_division;
+pub use clock_division::*;
+
+mod controller_output;
+pub use controller_output::*;
+
 mod envelope_trigger;
 pub use envelope_trigger::*;
 
+mod filter_tracking;
+pub use filter_tracking::*;
+
+#[cfg(feature = "embassy-time")]
+mod gate_behavior;
+#[cfg(feature = "embassy-time")]
+pub use gate_behavior::*;
+
 mod input_mode;
 pub use input_mode::*;
 
 mod keyboard;
 pub use keyboard::*;
 
+mod portamento_time_curve;
+pub use portamento_time_curve::*;
+
+mod scale;
+pub use scale::*;
+
+mod second_voice_output;
+pub use second_voice_output::*;
+
+mod switch_function;
+pub use switch_function::*;
+
+mod thumbwheel_assignment;
+pub use thumbwheel_assignment::*;
+
+mod trigger_edge;
+pub use trigger_edge::*;
+
+mod velocity_glide;
+pub use velocity_glide::*;
+
 use num_traits::{FromPrimitive, ToPrimitive};
 
 /// A trait which allows infinite cycling of an enum's variants.
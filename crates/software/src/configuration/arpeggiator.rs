@@ -0,0 +1,574 @@
+use super::{ClockDivision, ProvideNote};
+use crate::midi_state::ActivatedNotes;
+#[cfg(feature = "embassy-time")]
+use embassy_time::Duration;
+use num_derive::{FromPrimitive, ToPrimitive};
+use tinyvec::ArrayVec;
+use wmidi::{Note, U7};
+
+/// The most activated notes an [`Arpeggiator`] steps through; any beyond this are ignored.
+const ARPEGGIATOR_CAPACITY: usize = 32;
+
+/// The most octaves an [`Arpeggiator`] can span.
+pub const MAX_OCTAVE_RANGE: u8 = 4;
+
+/// The time between steps unless configured otherwise: a 16th note at 120 BPM.
+pub const DEFAULT_ARPEGGIATOR_RATE_MICROS: u64 = 125_000;
+
+/// The increment in the time between steps of each control value mapped by [`RateSource::from_control_value`].
+pub const MANUAL_RATE_STEP_MICROS: u64 = 8_000;
+
+/// The tempo assumed by [`RateSource::MidiClock`] until one is received.
+const FALLBACK_BPM: f32 = 120.0;
+
+/// Determines the order in which an [`Arpeggiator`] steps through the activated notes.
+#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ArpeggiatorMode {
+    /// Steps from the lowest note to the highest, then starts again from the lowest.
+    #[default]
+    Up,
+    /// Steps from the highest note to the lowest, then starts again from the highest.
+    Down,
+    /// Steps from the lowest note to the highest and back down again. The highest and lowest notes are not repeated at
+    /// the turnarounds.
+    UpDown,
+    /// Steps through the notes in an unpredictable order, which may repeat a note.
+    Random,
+}
+impl super::CycleConfig for ArpeggiatorMode {}
+
+/// Determines what paces an [`Arpeggiator`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RateSource {
+    /// Steps at a fixed interval, in microseconds.
+    Manual(u64),
+    /// Steps on the given note value of the tempo received via MIDI clock.
+    ///
+    /// The tempo is supplied by the caller (see [`Arpeggiator::rate`]), e.g., from
+    /// [`MidiState::bpm`][crate::midi_state::MidiState::bpm]; 120 BPM is assumed if none is.
+    MidiClock(ClockDivision),
+}
+
+impl RateSource {
+    /// Maps a control value (e.g., from the thumbwheel) to a [`Manual`][Self::Manual] rate in steps of
+    /// [`MANUAL_RATE_STEP_MICROS`]; the higher the value, the quicker the arpeggio.
+    pub fn from_control_value(value: U7) -> Self {
+        let steps = u64::from(u8::from(U7::MAX) - u8::from(value)) + 1;
+        Self::Manual(steps * MANUAL_RATE_STEP_MICROS)
+    }
+}
+
+impl Default for RateSource {
+    fn default() -> Self {
+        Self::Manual(DEFAULT_ARPEGGIATOR_RATE_MICROS)
+    }
+}
+
+/// A [`ProvideNote`] which voices the activated [`Note`]s one at a time, in order of pitch.
+///
+/// As [`provide_note`][ProvideNote::provide_note] doesn't mutate, the arpeggiator only moves on to the next note when
+/// [`advance`][Self::advance] is called, e.g., each time the interval returned by [`rate`][Self::rate] elapses.
+/// Because the notes are sorted anew each time one is provided, notes activated or released mid-arpeggio are picked up
+/// on the next step.
+///
+/// In latch mode, the arpeggio carries on once all keys are released; see [`LatchedNotes`].
+///
+/// Spanning more than one octave repeats the activated notes an octave higher for each additional octave. Transposed
+/// notes are not limited to the playable range, so callers should expect [`Keyboard::voltage`] to clamp them.
+///
+/// [`Keyboard::voltage`]: super::Keyboard::voltage
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Arpeggiator {
+    mode: ArpeggiatorMode,
+    /// The number of octaves spanned by the arpeggio, from 1 to [`MAX_OCTAVE_RANGE`].
+    octave_range: u8,
+    /// What paces the steps.
+    rate_source: RateSource,
+    /// Whether the notes continue to be arpeggiated once released.
+    latch: bool,
+    /// The number of steps taken since the arpeggio began.
+    step: usize,
+    /// Mixed into the order of [`ArpeggiatorMode::Random`], so that differently seeded arpeggiators play different
+    /// sequences.
+    seed: u64,
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new(ArpeggiatorMode::default())
+    }
+}
+
+impl Arpeggiator {
+    /// Constructs an [`Arpeggiator`] spanning one octave at the [default rate][DEFAULT_ARPEGGIATOR_RATE_MICROS],
+    /// positioned at the start of an arpeggio.
+    pub const fn new(mode: ArpeggiatorMode) -> Self {
+        Self {
+            mode,
+            octave_range: 1,
+            rate_source: RateSource::Manual(DEFAULT_ARPEGGIATOR_RATE_MICROS),
+            latch: false,
+            step: 0,
+            seed: 0,
+        }
+    }
+
+    /// Sets the number of octaves spanned by the arpeggio, clamped to the range 1 to [`MAX_OCTAVE_RANGE`].
+    pub const fn with_octave_range(mut self, octave_range: u8) -> Self {
+        self.octave_range = if octave_range < 1 {
+            1
+        } else if octave_range > MAX_OCTAVE_RANGE {
+            MAX_OCTAVE_RANGE
+        } else {
+            octave_range
+        };
+        self
+    }
+
+    /// Sets a fixed time between steps.
+    #[cfg(feature = "embassy-time")]
+    pub fn with_rate(self, rate: Duration) -> Self {
+        self.with_rate_source(RateSource::Manual(rate.as_micros()))
+    }
+
+    /// Sets what paces the steps.
+    pub const fn with_rate_source(mut self, rate_source: RateSource) -> Self {
+        self.rate_source = rate_source;
+        self
+    }
+
+    /// Sets whether the notes continue to be arpeggiated once released.
+    pub const fn with_latch(mut self, latch: bool) -> Self {
+        self.latch = latch;
+        self
+    }
+
+    /// Sets the seed of [`ArpeggiatorMode::Random`], e.g., from a hardware random number generator. Unless seeded, the
+    /// same notes always play in the same order.
+    pub const fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the [`ArpeggiatorMode`].
+    pub fn mode(&self) -> ArpeggiatorMode {
+        self.mode
+    }
+
+    /// Returns the number of octaves spanned by the arpeggio.
+    pub fn octave_range(&self) -> u8 {
+        self.octave_range
+    }
+
+    /// Returns `true` if the notes continue to be arpeggiated once released.
+    pub const fn is_latched(&self) -> bool {
+        self.latch
+    }
+
+    /// Returns the [`RateSource`].
+    pub fn rate_source(&self) -> RateSource {
+        self.rate_source
+    }
+
+    /// Returns the time between steps at the given tempo (if known), in a format compatible with Embassy's timekeeping
+    /// API.
+    ///
+    /// The tempo only matters when paced by [`RateSource::MidiClock`].
+    #[cfg(feature = "embassy-time")]
+    pub fn rate(&self, bpm: Option<f32>) -> Duration {
+        Duration::from_micros(self.rate_micros(bpm))
+    }
+
+    /// Returns the time between steps at the given tempo (if known) in microseconds, for use without Embassy's
+    /// timekeeping API.
+    pub fn rate_micros(&self, bpm: Option<f32>) -> u64 {
+        match self.rate_source {
+            RateSource::Manual(micros) => micros,
+            RateSource::MidiClock(division) => {
+                division.duration_micros_at_bpm(bpm.unwrap_or(FALLBACK_BPM))
+            }
+        }
+    }
+
+    /// Moves on to the next note of the arpeggio.
+    pub fn advance(&mut self) {
+        self.step = self.step.wrapping_add(1);
+    }
+
+    /// Returns to the start of the arpeggio, e.g., when all notes are released.
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+}
+
+impl ProvideNote for Arpeggiator {
+    fn provide_note(&self, notes: impl Iterator<Item = Note>) -> Option<Note> {
+        let mut sorted: ArrayVec<[U7; ARPEGGIATOR_CAPACITY]> = notes
+            .take(ARPEGGIATOR_CAPACITY)
+            .map(|note| U7::from_u8_lossy(note as u8))
+            .collect();
+        sorted.sort_unstable();
+
+        let note_cnt = sorted.len();
+        if note_cnt == 0 {
+            return None;
+        }
+
+        // the sequence repeats the sorted notes once per octave, so indices beyond the first octave are transposed
+        let len = note_cnt * usize::from(self.octave_range);
+        let index = match self.mode {
+            ArpeggiatorMode::Up => self.step % len,
+            ArpeggiatorMode::Down => len - 1 - self.step % len,
+            ArpeggiatorMode::UpDown => {
+                let period = (2 * len - 2).max(1);
+                let position = self.step % period;
+                if position < len {
+                    position
+                } else {
+                    period - position
+                }
+            }
+            ArpeggiatorMode::Random => scramble(self.step, self.seed) % len,
+        };
+        let octave = (index / note_cnt) as u8;
+        let note = u8::from(sorted[index % note_cnt]).saturating_add(12 * octave);
+        Some(Note::from(U7::from_u8_lossy(note.min(u8::from(U7::MAX)))))
+    }
+}
+
+/// The notes an [`Arpeggiator`] in latch mode steps through, which remain after the keys are released.
+///
+/// Notes pressed while any key is held join the latched set, so a chord released one key at a time isn't whittled down
+/// to its last note. The first press after all keys are released starts a new set.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LatchedNotes {
+    notes: ActivatedNotes,
+    /// Whether all keys have been released since the set was last added to.
+    is_released: bool,
+}
+
+impl LatchedNotes {
+    /// Constructs an empty [`LatchedNotes`].
+    pub const fn new() -> Self {
+        Self {
+            notes: ActivatedNotes::new(),
+            is_released: false,
+        }
+    }
+
+    /// Updates the latched set according to the notes currently `activated`.
+    pub fn update(&mut self, activated: &ActivatedNotes) {
+        if activated.is_empty() {
+            self.is_released = true;
+            return;
+        }
+        if self.is_released {
+            self.notes = ActivatedNotes::new();
+            self.is_released = false;
+        }
+        self.notes.merge(activated);
+    }
+
+    /// Returns the latched notes.
+    pub fn notes(&self) -> &ActivatedNotes {
+        &self.notes
+    }
+
+    /// Releases the latched notes, e.g., when latch mode is switched off.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Returns the output of a SplitMix64 generator seeded with `seed` at position `step`, so that
+/// [`ArpeggiatorMode::Random`] can pick notes without holding any random state (which would require
+/// [`provide_note`][ProvideNote::provide_note] to mutate).
+fn scramble(step: usize, seed: u64) -> usize {
+    let mut z = seed.wrapping_add(
+        (step as u64)
+            .wrapping_add(1)
+            .wrapping_mul(0x9E37_79B9_7F4A_7C15),
+    );
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (z ^ (z >> 31)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{configuration::Keyboard, midi_state::ActivatedNotes};
+    #[cfg(feature = "embassy-time")]
+    use embassy_time::Duration;
+    use measurements::Voltage;
+
+    const CHORD: [Note; 3] = [Note::E4, Note::C4, Note::G4];
+
+    fn arpeggio(mode: ArpeggiatorMode, notes: &[Note], steps: usize) -> impl Iterator<Item = Note> {
+        arpeggio_with(Arpeggiator::new(mode), notes, steps)
+    }
+
+    fn arpeggio_with(
+        mut arpeggiator: Arpeggiator,
+        notes: &[Note],
+        steps: usize,
+    ) -> impl Iterator<Item = Note> {
+        (0..steps).map(move |_| {
+            let note = arpeggiator
+                .provide_note(notes.iter().copied())
+                .expect("Expected a note");
+            arpeggiator.advance();
+            note
+        })
+    }
+
+    #[test]
+    fn up() {
+        assert!(
+            arpeggio(ArpeggiatorMode::Up, &CHORD, 4).eq([Note::C4, Note::E4, Note::G4, Note::C4]),
+            "Expected ascending notes, wrapping around to the lowest"
+        );
+    }
+
+    #[test]
+    fn down() {
+        assert!(
+            arpeggio(ArpeggiatorMode::Down, &CHORD, 4).eq([Note::G4, Note::E4, Note::C4, Note::G4]),
+            "Expected descending notes, wrapping around to the highest"
+        );
+    }
+
+    #[test]
+    fn up_down() {
+        assert!(
+            arpeggio(ArpeggiatorMode::UpDown, &CHORD, 6).eq([
+                Note::C4,
+                Note::E4,
+                Note::G4,
+                Note::E4,
+                Note::C4,
+                Note::E4
+            ]),
+            "Expected notes to reverse at the top and bottom without repeating"
+        );
+        assert!(
+            arpeggio(ArpeggiatorMode::UpDown, &[Note::C4], 2).eq([Note::C4, Note::C4]),
+            "Expected a single note to repeat"
+        );
+    }
+
+    #[test]
+    fn random() {
+        assert!(
+            arpeggio(ArpeggiatorMode::Random, &CHORD, 32).all(|note| CHORD.contains(&note)),
+            "Expected only activated notes"
+        );
+        assert!(
+            arpeggio(ArpeggiatorMode::Random, &CHORD, 32).eq(arpeggio(
+                ArpeggiatorMode::Random,
+                &CHORD,
+                32
+            )),
+            "Expected the same steps to produce the same notes"
+        );
+        assert!(
+            !arpeggio(ArpeggiatorMode::Random, &CHORD, 32).eq(arpeggio(
+                ArpeggiatorMode::Up,
+                &CHORD,
+                32
+            )),
+            "Expected the notes not to simply ascend"
+        );
+    }
+
+    #[test]
+    fn random_seed() {
+        let seeded = |seed| Arpeggiator::new(ArpeggiatorMode::Random).with_seed(seed);
+        assert!(
+            arpeggio_with(seeded(1), &CHORD, 32).all(|note| CHORD.contains(&note)),
+            "Expected only activated notes"
+        );
+        assert!(
+            !arpeggio_with(seeded(1), &CHORD, 32).eq(arpeggio_with(seeded(2), &CHORD, 32)),
+            "Expected different seeds to produce different orders"
+        );
+    }
+
+    #[test]
+    fn octave_range() {
+        let arpeggiator = Arpeggiator::new(ArpeggiatorMode::Up).with_octave_range(2);
+        assert!(
+            arpeggio_with(arpeggiator, &CHORD, 7).eq([
+                Note::C4,
+                Note::E4,
+                Note::G4,
+                Note::C5,
+                Note::E5,
+                Note::G5,
+                Note::C4
+            ]),
+            "Expected the notes to repeat an octave higher before wrapping around"
+        );
+
+        let arpeggiator = Arpeggiator::new(ArpeggiatorMode::Down).with_octave_range(2);
+        assert!(
+            arpeggio_with(arpeggiator, &[Note::C4], 3).eq([Note::C5, Note::C4, Note::C5]),
+            "Expected descent to start from the highest octave"
+        );
+
+        assert_eq!(
+            MAX_OCTAVE_RANGE,
+            Arpeggiator::default().with_octave_range(9).octave_range(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            1,
+            Arpeggiator::default().with_octave_range(0).octave_range(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn octave_range_ceiling() {
+        let arpeggiator = Arpeggiator::new(ArpeggiatorMode::Down).with_octave_range(2);
+        assert_eq!(
+            Some(Note::G9),
+            arpeggiator.provide_note([Note::C9].into_iter()),
+            "Expected transposition to stop at the highest MIDI note; expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn rate() {
+        assert_eq!(
+            DEFAULT_ARPEGGIATOR_RATE_MICROS,
+            Arpeggiator::default().rate_micros(None),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_millis(250),
+            Arpeggiator::default()
+                .with_rate(Duration::from_millis(250))
+                .rate(Some(90.0)),
+            "Expected a manual rate to ignore the tempo; expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn midi_clock_rate() {
+        let arpeggiator =
+            Arpeggiator::default().with_rate_source(RateSource::MidiClock(ClockDivision::Eighth));
+        assert_eq!(
+            Duration::from_millis(200),
+            arpeggiator.rate(Some(150.0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_millis(250),
+            arpeggiator.rate(None),
+            "Expected 120 BPM to be assumed; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn rate_from_control_value() {
+        assert_eq!(
+            RateSource::Manual(1_024_000),
+            RateSource::from_control_value(U7::MIN),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            RateSource::Manual(MANUAL_RATE_STEP_MICROS),
+            RateSource::from_control_value(U7::MAX),
+            "Expected the quickest rate never to be zero; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn latch() {
+        assert!(
+            !Arpeggiator::default().is_latched(),
+            "Expected latch mode to be off by default"
+        );
+        assert!(
+            Arpeggiator::default().with_latch(true).is_latched(),
+            "Expected latch mode to be on"
+        );
+    }
+
+    #[test]
+    fn latched_notes() {
+        let mut activated = ActivatedNotes::new();
+        let mut latched = LatchedNotes::new();
+        CHORD.into_iter().for_each(|note| {
+            activated.add(note);
+            latched.update(&activated);
+        });
+
+        // the chord is released one key at a time
+        CHORD.into_iter().for_each(|note| {
+            activated.remove(note);
+            latched.update(&activated);
+        });
+        assert!(
+            latched.notes().iter().eq(CHORD),
+            "Expected the whole chord to remain latched"
+        );
+
+        activated.add(Note::D4);
+        latched.update(&activated);
+        activated.add(Note::F4);
+        latched.update(&activated);
+        assert!(
+            latched.notes().iter().eq([Note::D4, Note::F4]),
+            "Expected a new press to replace the latched notes, and further presses to join it"
+        );
+
+        latched.clear();
+        assert!(latched.notes().is_empty(), "Expected no latched notes");
+    }
+
+    #[test]
+    fn no_notes() {
+        assert_eq!(
+            None,
+            Arpeggiator::default().provide_note(core::iter::empty()),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn keyboard() {
+        let mut notes = ActivatedNotes::new();
+        CHORD.into_iter().for_each(|note| notes.add(note));
+        notes.add(Note::C7);
+
+        let mut arpeggiator = Arpeggiator::default();
+        arpeggiator.advance();
+        arpeggiator.advance();
+        arpeggiator.advance();
+        let keyboard = Keyboard::new(arpeggiator, Note::F3..=Note::C6, Voltage::from_volts(1.0));
+        assert_eq!(
+            Some(Note::C4),
+            keyboard.provide_note(&notes),
+            "Expected notes outside the playable range to be skipped; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut arpeggiator = Arpeggiator::new(ArpeggiatorMode::Up);
+        arpeggiator.advance();
+        arpeggiator.reset();
+        assert_eq!(
+            Some(Note::C4),
+            arpeggiator.provide_note(CHORD.into_iter()),
+            "Expected left but got right"
+        );
+    }
+}
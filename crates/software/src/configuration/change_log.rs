@@ -0,0 +1,169 @@
+use super::{ChordCleanup, EnvelopeTrigger, InputMode, NotePriority};
+use embassy_time::Instant;
+use tinyvec::ArrayVec;
+
+/// The number of configuration changes retained by a [`ConfigChangeLog`].
+const CONFIG_CHANGE_LOG_CAPACITY: usize = 16;
+
+/// A change to one of the user-configurable settings, capturing both the previous and the new value.
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigChange {
+    /// The [`ChordCleanup`] setting changed.
+    ChordCleanup {
+        /// The value before the change.
+        previous: ChordCleanup,
+        /// The value after the change.
+        new: ChordCleanup,
+    },
+    /// The [`EnvelopeTrigger`] setting changed.
+    EnvelopeTrigger {
+        /// The value before the change.
+        previous: EnvelopeTrigger,
+        /// The value after the change.
+        new: EnvelopeTrigger,
+    },
+    /// The [`InputMode`] setting changed.
+    InputMode {
+        /// The value before the change.
+        previous: InputMode,
+        /// The value after the change.
+        new: InputMode,
+    },
+    /// The [`NotePriority`] setting changed.
+    NotePriority {
+        /// The value before the change.
+        previous: NotePriority,
+        /// The value after the change.
+        new: NotePriority,
+    },
+}
+
+/// A [`ConfigChange`] along with the [`Instant`] at which it was recorded.
+#[derive(Clone, Copy, Debug)]
+pub struct LoggedConfigChange {
+    /// When the change was recorded.
+    pub at: Instant,
+    /// What changed.
+    pub change: ConfigChange,
+}
+
+/// A history of the most recent configuration changes, intended to help diagnose configuration drift during a
+/// performance (e.g., "how did note priority get set to high in the middle of the set?").
+///
+/// Once the log is full, recording a new change drops the oldest one.
+#[derive(Clone, Copy, Debug)]
+pub struct ConfigChangeLog {
+    /// Entries are stored oldest first. [`tinyvec`] requires that `Items` implement [`Default`], hence the [`Option`].
+    entries: ArrayVec<[Option<LoggedConfigChange>; CONFIG_CHANGE_LOG_CAPACITY]>,
+}
+
+impl Default for ConfigChangeLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigChangeLog {
+    /// Constructs an empty [`ConfigChangeLog`].
+    ///
+    /// As a `const fn`, this can be used to initialize a `static` log.
+    pub const fn new() -> Self {
+        Self {
+            entries: ArrayVec::from_array_empty([None; CONFIG_CHANGE_LOG_CAPACITY]),
+        }
+    }
+
+    /// Records a [`ConfigChange`] as having happened just now, dropping the oldest entry if the log is full.
+    pub fn record(&mut self, change: ConfigChange) {
+        if self.entries.len() == self.entries.capacity() {
+            self.entries.remove(0);
+        }
+        self.entries.push(Some(LoggedConfigChange {
+            at: Instant::now(),
+            change,
+        }));
+    }
+
+    /// Returns an [`Iterator`] over the recorded changes, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &LoggedConfigChange> {
+        self.entries.iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_priority_change(previous: NotePriority, new: NotePriority) -> ConfigChange {
+        ConfigChange::NotePriority { previous, new }
+    }
+
+    #[test]
+    fn record() {
+        let mut log = ConfigChangeLog::new();
+        log.record(note_priority_change(NotePriority::Low, NotePriority::High));
+        log.record(ConfigChange::ChordCleanup {
+            previous: ChordCleanup::None,
+            new: ChordCleanup::ThirtySecondNote,
+        });
+
+        let mut iter = log.iter();
+        assert!(matches!(
+            iter.next().map(|entry| entry.change),
+            Some(ConfigChange::NotePriority {
+                previous: NotePriority::Low,
+                new: NotePriority::High
+            })
+        ));
+        assert!(matches!(
+            iter.next().map(|entry| entry.change),
+            Some(ConfigChange::ChordCleanup {
+                previous: ChordCleanup::None,
+                new: ChordCleanup::ThirtySecondNote
+            })
+        ));
+        assert!(iter.next().is_none(), "Expected only two entries");
+    }
+
+    #[test]
+    fn record_drops_oldest_when_full() {
+        let mut log = ConfigChangeLog::new();
+        log.record(note_priority_change(NotePriority::Low, NotePriority::High));
+        for _ in 1..CONFIG_CHANGE_LOG_CAPACITY {
+            log.record(note_priority_change(
+                NotePriority::High,
+                NotePriority::First,
+            ));
+        }
+        log.record(note_priority_change(
+            NotePriority::First,
+            NotePriority::Last,
+        ));
+
+        assert_eq!(
+            CONFIG_CHANGE_LOG_CAPACITY,
+            log.iter().count(),
+            "Expected log length not to exceed capacity"
+        );
+        assert!(
+            matches!(
+                log.iter().next().map(|entry| entry.change),
+                Some(ConfigChange::NotePriority {
+                    previous: NotePriority::High,
+                    new: NotePriority::First
+                })
+            ),
+            "Expected oldest entry to have been dropped"
+        );
+        assert!(
+            matches!(
+                log.iter().last().map(|entry| entry.change),
+                Some(ConfigChange::NotePriority {
+                    previous: NotePriority::First,
+                    new: NotePriority::Last
+                })
+            ),
+            "Expected newest entry to be last"
+        );
+    }
+}
@@ -1,5 +1,15 @@
+use super::ClockDivision;
+#[cfg(feature = "embassy-time")]
 use embassy_time::Duration;
-use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::{FromPrimitive, ToPrimitive};
+use wmidi::ControlValue;
+
+/// The tempo assumed when converting [`ChordCleanup`] to a duration if none is known.
+const ASSUMED_BPM: f32 = 120.0;
+
+/// How much each step of a control value lengthens a [`ChordCleanup::Custom`] batching period, i.e., 2 ms, so that the
+/// full range of a controller spans 0 to 254 ms.
+pub const CUSTOM_STEP_MICROS: u64 = 2_000;
 
 /// Determines how much delay to insert between MIDI input and electrical output to enable "chord cleanup" functionality,
 /// expressed as divisions of a note.
@@ -12,22 +22,75 @@ use num_derive::{FromPrimitive, ToPrimitive};
 ///
 /// As the chord cleanup feature batches and "swallows" notes by design, it should be disabled when driving the synth
 /// from a sequencer or MIDI file.
-#[derive(Debug, Clone, Copy, ToPrimitive, FromPrimitive, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ChordCleanup {
     /// Effectively disables the "chord cleanup" feature.
     None,
     /// Introduces a margin of error of one 32nd note for the performer.
     ThirtySecondNote,
+    /// Introduces a margin of error of the given number of microseconds, e.g., a few milliseconds for a controller which
+    /// already bundles chords, or a couple hundred for a performer who prefers a more forgiving window.
+    ///
+    /// As it carries a value, this variant is skipped when [cycling][super::CycleConfig]; cycling past it returns to
+    /// [`None`][Self::None].
+    Custom(u64),
 }
 
 impl ChordCleanup {
+    /// Returns a [`Custom`][Self::Custom] batching period of the given [`Duration`].
+    #[cfg(feature = "embassy-time")]
+    pub fn with_duration(duration: Duration) -> Self {
+        Self::Custom(duration.as_micros())
+    }
+
+    /// Maps a control value (e.g., from the thumbwheel) to a [`Custom`][Self::Custom] batching period in steps of
+    /// [`CUSTOM_STEP_MICROS`]; zero disables chord cleanup.
+    pub fn from_control_value(value: ControlValue) -> Self {
+        match u8::from(value) {
+            0 => Self::None,
+            steps => Self::Custom(u64::from(steps) * CUSTOM_STEP_MICROS),
+        }
+    }
+
+    /// Returns the note value of the batching period, or `None` if chord cleanup is disabled.
+    pub fn clock_division(&self) -> Option<ClockDivision> {
+        match self {
+            Self::None | Self::Custom(_) => None,
+            Self::ThirtySecondNote => Some(ClockDivision::ThirtySecond),
+        }
+    }
+
     /// Return the duration of the batching period in a format compatible with Embassy's timekeeping API.
     ///
-    /// In some future, this will be tied to BPM (beats per minute). For now, BPM is assumed to be 120.
+    /// BPM (beats per minute) is assumed to be [120][ASSUMED_BPM]; see [`duration_at_bpm`][Self::duration_at_bpm] to
+    /// supply a tempo instead.
+    #[cfg(feature = "embassy-time")]
     pub fn duration(&self) -> Duration {
+        self.duration_at_bpm(ASSUMED_BPM)
+    }
+
+    /// Return the duration of the batching period at the given tempo, in a format compatible with Embassy's timekeeping
+    /// API. A [`Custom`][Self::Custom] period is the same at any tempo.
+    #[cfg(feature = "embassy-time")]
+    pub fn duration_at_bpm(&self, bpm: f32) -> Duration {
+        Duration::from_micros(self.duration_micros_at_bpm(bpm))
+    }
+
+    /// Return the duration of the batching period in microseconds, for use without Embassy's timekeeping API.
+    ///
+    /// As with [`duration`][Self::duration], BPM is assumed to be 120.
+    pub fn duration_micros(&self) -> u64 {
+        self.duration_micros_at_bpm(ASSUMED_BPM)
+    }
+
+    /// Return the duration of the batching period at the given tempo in microseconds, for use without Embassy's
+    /// timekeeping API.
+    pub fn duration_micros_at_bpm(&self, bpm: f32) -> u64 {
         match self {
-            Self::None => Duration::from_micros(0),
-            Self::ThirtySecondNote => Duration::from_micros(62500),
+            Self::Custom(micros) => *micros,
+            _ => self
+                .clock_division()
+                .map_or(0, |division| division.duration_micros_at_bpm(bpm)),
         }
     }
 
@@ -37,11 +100,120 @@ impl ChordCleanup {
     }
 }
 
+// derived implementations aren't available for enums whose variants carry values
+impl ToPrimitive for ChordCleanup {
+    fn to_i64(&self) -> Option<i64> {
+        self.to_u64().map(|n| n as i64)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        Some(match self {
+            Self::None => 0,
+            Self::ThirtySecondNote => 1,
+            Self::Custom(_) => 2,
+        })
+    }
+}
+
+impl FromPrimitive for ChordCleanup {
+    fn from_i64(n: i64) -> Option<Self> {
+        u64::try_from(n).ok().and_then(Self::from_u64)
+    }
+
+    /// There is no way to construct a [`Custom`][Self::Custom] period from its index alone, so only the fixed variants
+    /// are returned.
+    fn from_u64(n: u64) -> Option<Self> {
+        match n {
+            0 => Some(Self::None),
+            1 => Some(Self::ThirtySecondNote),
+            _ => None,
+        }
+    }
+}
+
 impl super::CycleConfig for ChordCleanup {}
 
+impl core::fmt::Display for ChordCleanup {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::None => f.write_str("None"),
+            Self::ThirtySecondNote => f.write_str("32nd note"),
+            Self::Custom(micros) => write!(f, "{} µs", micros),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wmidi::U7;
+
+    #[test]
+    fn display() {
+        extern crate std;
+        use std::string::ToString;
+
+        assert_eq!(
+            "None",
+            ChordCleanup::None.to_string(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            "32nd note",
+            ChordCleanup::ThirtySecondNote.to_string(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            "5000 µs",
+            ChordCleanup::Custom(5_000).to_string(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        use crate::configuration::CycleConfig;
+
+        assert_eq!(
+            ChordCleanup::ThirtySecondNote,
+            ChordCleanup::None.cycle(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            ChordCleanup::None,
+            ChordCleanup::ThirtySecondNote.cycle(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            ChordCleanup::None,
+            ChordCleanup::Custom(5_000).cycle(),
+            "Expected cycling past a custom period to disable chord cleanup; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn from_control_value() {
+        assert_eq!(
+            ChordCleanup::None,
+            ChordCleanup::from_control_value(U7::MIN),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            ChordCleanup::Custom(254_000),
+            ChordCleanup::from_control_value(U7::MAX),
+            "Expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn with_duration() {
+        assert_eq!(
+            ChordCleanup::Custom(200_000),
+            ChordCleanup::with_duration(Duration::from_millis(200)),
+            "Expected left but got right"
+        );
+    }
 
     #[test]
     fn is_enabled() {
@@ -49,6 +221,44 @@ mod tests {
             ChordCleanup::ThirtySecondNote.is_enabled(),
             "Should be enabled"
         );
+        assert!(
+            ChordCleanup::Custom(5_000).is_enabled(),
+            "Should be enabled"
+        );
         assert!(!ChordCleanup::None.is_enabled(), "Should be disabled");
     }
+
+    #[test]
+    fn duration_micros() {
+        assert_eq!(
+            0,
+            ChordCleanup::None.duration_micros(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            62500,
+            ChordCleanup::ThirtySecondNote.duration_micros(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            5_000,
+            ChordCleanup::Custom(5_000).duration_micros(),
+            "Expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn duration_at_bpm() {
+        assert_eq!(
+            Duration::from_micros(125_000),
+            ChordCleanup::ThirtySecondNote.duration_at_bpm(60.0),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_micros(5_000),
+            ChordCleanup::Custom(5_000).duration_at_bpm(60.0),
+            "Expected a custom period to ignore the tempo; expected left but got right"
+        );
+    }
 }
@@ -0,0 +1,92 @@
+#[cfg(feature = "embassy-time")]
+use embassy_time::Duration;
+use num_derive::{FromPrimitive, ToPrimitive};
+
+/// MIDI clock is delivered at 24 pulses per quarter note.
+pub const PULSES_PER_QUARTER_NOTE: u32 = 24;
+
+/// A musical note value, used to express time relative to the tempo rather than in absolute terms.
+#[derive(Debug, Default, Clone, Copy, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockDivision {
+    /// A 32nd note, i.e., an eighth of a beat.
+    ThirtySecond,
+    /// A 16th note, i.e., a quarter of a beat.
+    Sixteenth,
+    /// An eighth note, i.e., half a beat.
+    Eighth,
+    /// A quarter note, i.e., one beat.
+    #[default]
+    Quarter,
+    /// A half note, i.e., two beats.
+    Half,
+    /// A whole note, i.e., four beats.
+    Whole,
+}
+
+impl ClockDivision {
+    /// Returns the number of MIDI clock pulses spanned by the note value; e.g., 3 for a 32nd note.
+    pub fn ppqn_divisor(&self) -> u32 {
+        match self {
+            Self::ThirtySecond => PULSES_PER_QUARTER_NOTE / 8,
+            Self::Sixteenth => PULSES_PER_QUARTER_NOTE / 4,
+            Self::Eighth => PULSES_PER_QUARTER_NOTE / 2,
+            Self::Quarter => PULSES_PER_QUARTER_NOTE,
+            Self::Half => PULSES_PER_QUARTER_NOTE * 2,
+            Self::Whole => PULSES_PER_QUARTER_NOTE * 4,
+        }
+    }
+
+    /// Returns the duration of the note value at the given tempo, in a format compatible with Embassy's timekeeping API.
+    #[cfg(feature = "embassy-time")]
+    pub fn duration_at_bpm(&self, bpm: f32) -> Duration {
+        Duration::from_micros(self.duration_micros_at_bpm(bpm))
+    }
+
+    /// Returns the duration of the note value at the given tempo in microseconds, for use without Embassy's
+    /// timekeeping API.
+    pub fn duration_micros_at_bpm(&self, bpm: f32) -> u64 {
+        let beat_micros = 60_000_000.0 / bpm;
+        (beat_micros * self.ppqn_divisor() as f32 / PULSES_PER_QUARTER_NOTE as f32) as u64
+    }
+}
+
+impl super::CycleConfig for ClockDivision {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppqn_divisor() {
+        assert_eq!(
+            3,
+            ClockDivision::ThirtySecond.ppqn_divisor(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            96,
+            ClockDivision::Whole.ppqn_divisor(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn duration_micros_at_bpm() {
+        assert_eq!(
+            62_500,
+            ClockDivision::ThirtySecond.duration_micros_at_bpm(120.0),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            1_000_000,
+            ClockDivision::Quarter.duration_micros_at_bpm(60.0),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            2_000_000,
+            ClockDivision::Whole.duration_micros_at_bpm(120.0),
+            "Expected left but got right"
+        );
+    }
+}
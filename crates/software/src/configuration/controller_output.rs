@@ -0,0 +1,71 @@
+use crate::midi_state::Operation;
+use num_derive::{FromPrimitive, ToPrimitive};
+
+/// Determines which MIDI controller, if any, is converted to a control voltage on a spare output, e.g., for sweeping the
+/// Micromoog's filter by hand; see [`MidiState::controller_voltage`][crate::midi_state::MidiState::controller_voltage].
+#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControllerOutput {
+    /// No controller is output.
+    #[default]
+    Disabled,
+    /// MIDI CC 1: Modulation Wheel.
+    Modulation,
+    /// Channel Pressure (i.e., aftertouch).
+    Aftertouch,
+    /// MIDI CC 11: Expression (e.g., from a foot controller).
+    Expression,
+    /// MIDI CC 2: Breath Controller.
+    Breath,
+}
+
+impl ControllerOutput {
+    /// Returns true for any value other than [`ControllerOutput::Disabled`].
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::Disabled
+    }
+
+    /// Returns the [`Operation`] which signals a change to the selected controller.
+    pub fn operation(&self) -> Operation {
+        match self {
+            Self::Disabled => Operation::empty(),
+            Self::Modulation => Operation::MODULATION_CHANGE,
+            Self::Aftertouch => Operation::AFTERTOUCH_CHANGE,
+            Self::Expression => Operation::EXPRESSION_CHANGE,
+            Self::Breath => Operation::BREATH_CHANGE,
+        }
+    }
+}
+
+impl super::CycleConfig for ControllerOutput {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_enabled() {
+        assert!(
+            ControllerOutput::Modulation.is_enabled(),
+            "Should be enabled"
+        );
+        assert!(
+            !ControllerOutput::Disabled.is_enabled(),
+            "Should be disabled"
+        );
+    }
+
+    #[test]
+    fn operation() {
+        assert_eq!(
+            Operation::empty(),
+            ControllerOutput::Disabled.operation(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::EXPRESSION_CHANGE,
+            ControllerOutput::Expression.operation(),
+            "Expected left but got right"
+        );
+    }
+}
@@ -3,7 +3,7 @@ use num_derive::{FromPrimitive, ToPrimitive};
 /// Determines when to trigger a new envelope for the attached synthesizer.
 ///
 /// The Micromoog uses the same trigger to initiate both the loudness and filter envelopes.
-#[derive(Debug, Copy, Clone, ToPrimitive, FromPrimitive)]
+#[derive(Debug, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
 pub enum EnvelopeTrigger {
     /// Envelope is triggered each time a break ends. That is, the envelope is triggered when the initial break ends
     /// (i.e., when the first note is played) as well as when any break between notes ends (i.e., at the start of each
@@ -13,3 +13,12 @@ pub enum EnvelopeTrigger {
     NoteChange,
 }
 impl super::CycleConfig for EnvelopeTrigger {}
+
+impl core::fmt::Display for EnvelopeTrigger {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::BreakEnd => "Break end",
+            Self::NoteChange => "Note change",
+        })
+    }
+}
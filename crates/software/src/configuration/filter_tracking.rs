@@ -0,0 +1,126 @@
+use num_traits::{FromPrimitive, ToPrimitive};
+
+/// The tracking amounts visited when [cycling][super::CycleConfig] a [`FilterTracking`], in order.
+const PRESETS: [f32; 3] = [0.0, 0.5, 1.0];
+
+/// Determines how strongly an additional control voltage, patched into the Micromoog's filter cutoff input, tracks the
+/// keyboard; see [`Keyboard::filter_tracking_cv`][super::Keyboard::filter_tracking_cv].
+///
+/// The Micromoog's filter already tracks the keyboard to a degree set by its own hardware; this reinforces that
+/// tracking so that, e.g., at [`FULL`][Self::FULL] the cutoff rises an additional octave for every octave played.
+///
+/// Any amount may be configured, but [cycling][super::CycleConfig] steps through [`OFF`][Self::OFF],
+/// [`HALF`][Self::HALF] and [`FULL`][Self::FULL]; an amount in between cycles to the next of these.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterTracking {
+    tracking_amount: f32,
+}
+
+impl FilterTracking {
+    /// No additional control voltage is produced.
+    pub const OFF: Self = Self::new(PRESETS[0]);
+    /// The cutoff rises an additional half octave for every octave played.
+    pub const HALF: Self = Self::new(PRESETS[1]);
+    /// The cutoff rises an additional octave for every octave played.
+    pub const FULL: Self = Self::new(PRESETS[2]);
+
+    /// Constructs a new [`FilterTracking`] of the given amount, from 0.0 (no tracking) to 1.0 (1 V/octave tracking);
+    /// amounts outside that range are clamped.
+    pub const fn new(tracking_amount: f32) -> Self {
+        Self {
+            tracking_amount: tracking_amount.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Returns the tracking amount, from 0.0 (no tracking) to 1.0 (1 V/octave tracking).
+    pub fn tracking_amount(&self) -> f32 {
+        self.tracking_amount
+    }
+
+    /// Returns true for any amount other than that of [`FilterTracking::OFF`].
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::OFF
+    }
+}
+
+impl ToPrimitive for FilterTracking {
+    fn to_i64(&self) -> Option<i64> {
+        self.to_u64().and_then(|n| i64::try_from(n).ok())
+    }
+
+    /// Returns the index of the last preset not exceeding the tracking amount, so that cycling moves on to the next.
+    fn to_u64(&self) -> Option<u64> {
+        PRESETS
+            .iter()
+            .rposition(|preset| *preset <= self.tracking_amount)
+            .map(|i| i as u64)
+    }
+}
+
+impl FromPrimitive for FilterTracking {
+    fn from_i64(n: i64) -> Option<Self> {
+        u64::try_from(n).ok().and_then(Self::from_u64)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        usize::try_from(n)
+            .ok()
+            .and_then(|i| PRESETS.get(i))
+            .map(|preset| Self::new(*preset))
+    }
+}
+
+impl super::CycleConfig for FilterTracking {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() {
+        assert_eq!(
+            0.25,
+            FilterTracking::new(0.25).tracking_amount(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            1.0,
+            FilterTracking::new(1.5).tracking_amount(),
+            "Expected amounts above 1.0 to be clamped; expected left but got right"
+        );
+        assert_eq!(
+            0.0,
+            FilterTracking::new(-0.5).tracking_amount(),
+            "Expected amounts below 0.0 to be clamped; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        use crate::configuration::CycleConfig;
+
+        assert_eq!(
+            FilterTracking::HALF,
+            FilterTracking::OFF.cycle(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            FilterTracking::OFF,
+            FilterTracking::FULL.cycle(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            FilterTracking::FULL,
+            FilterTracking::new(0.75).cycle(),
+            "Expected an amount between presets to cycle to the next; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn is_enabled() {
+        assert!(FilterTracking::HALF.is_enabled(), "Should be enabled");
+        assert!(FilterTracking::new(0.1).is_enabled(), "Should be enabled");
+        assert!(!FilterTracking::OFF.is_enabled(), "Should be disabled");
+    }
+}
@@ -0,0 +1,50 @@
+use embassy_time::Duration;
+
+/// Determines the shape of the signal sent to the synthesizer's gate (or trigger) input when a note is played.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum GateBehavior {
+    /// The gate is held high for as long as a note is voiced. This is what the Micromoog's S-TRIG input expects.
+    #[default]
+    Sustain,
+    /// Each new note produces a one-shot pulse of the given width, after which the gate is set low regardless of whether
+    /// the note is still held. Suitable for drum machines and percussion sequencers, which generally expect pulses of
+    /// 1–10 ms.
+    Trigger(Duration),
+}
+
+impl GateBehavior {
+    /// A pulse width which suits most trigger inputs.
+    pub const DEFAULT_PULSE_WIDTH: Duration = Duration::from_millis(5);
+
+    /// Returns the width of the pulse to send for each new note, or `None` if the gate should be sustained.
+    pub fn pulse_width(&self) -> Option<Duration> {
+        match self {
+            Self::Sustain => None,
+            Self::Trigger(width) => Some(*width),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_width() {
+        assert_eq!(
+            None,
+            GateBehavior::Sustain.pulse_width(),
+            "Expected a sustained gate to have no pulse width"
+        );
+        assert_eq!(
+            Some(Duration::from_millis(2)),
+            GateBehavior::Trigger(Duration::from_millis(2)).pulse_width(),
+            "Expected pulse width to match configuration; expected left but got right"
+        );
+        assert_eq!(
+            Some(Duration::from_millis(5)),
+            GateBehavior::Trigger(GateBehavior::DEFAULT_PULSE_WIDTH).pulse_width(),
+            "Expected left but got right"
+        );
+    }
+}
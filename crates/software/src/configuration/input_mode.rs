@@ -1,7 +1,7 @@
 use num_derive::{FromPrimitive, ToPrimitive};
 
 /// Determines which of the synthesizer's modules will receive note input.
-#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive)]
+#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
 pub enum InputMode {
     /// Notes are played via the keyboard module, as though a performer were playing the instrument directly, respecting
     /// the synth's octave, frequency, doubling, and fine tune controls. The hardware control for glide is overridden, as this
@@ -10,7 +10,17 @@ pub enum InputMode {
     /// on the filter mode setting.)
     #[default]
     Keyboard,
-    /// TODO
+    /// Notes are played via the external input, bypassing the keyboard module (which is held at the bottom of its range).
+    /// The note voltage is sent from DAC channel 2, so the synth's own keyboard and glide circuitry play no part.
     Oscillator,
 }
 impl super::CycleConfig for InputMode {}
+
+impl core::fmt::Display for InputMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Keyboard => "Keyboard",
+            Self::Oscillator => "Oscillator",
+        })
+    }
+}
@@ -1,9 +1,17 @@
-use crate::midi_state::ActivatedNotes;
+use super::ScaleQuantizer;
+use crate::midi_state::{ActivatedNotes, PitchBend};
 use core::ops::RangeInclusive;
 use measurements::Voltage;
 use num_derive::{FromPrimitive, ToPrimitive};
 use wmidi::Note;
 
+/// The lowest note playable on the Micromoog's keyboard.
+pub const MICROMOOG_LOWEST_NOTE: Note = Note::F3;
+/// The highest note playable on the Micromoog's keyboard.
+pub const MICROMOOG_HIGHEST_NOTE: Note = Note::C6;
+/// The Micromoog's KBD input follows the 1 V/octave standard.
+pub const MICROMOOG_VOLTS_PER_OCTAVE: f64 = 1.0;
+
 /// Configurations relating to the keyboard component of the attached synthesizer.
 ///
 /// Stores performer selections which extend the native capabilities of the synth (e.g, note provider which enables
@@ -11,15 +19,56 @@ use wmidi::Note;
 /// for calculations converting notes to control voltage). It may not seem intuitive to treat fixed properties
 /// like the playable range as configurations, but if this device comes to support more than one synthesizer, the
 /// performer will have controls to select the attached instrument, which amounts to the same thing.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Keyboard<T> {
     note_provider: T,
-    playable_range: RangeInclusive<Note>,
+    playable_range: NoteRange,
     voltage_per_octave: Voltage,
+    /// The highest voltage the synth's keyboard input tolerates; voltages are clamped to this bound.
+    max_safe_voltage: Voltage,
+    /// Locks the provided note to a scale, if set.
+    quantizer: Option<ScaleQuantizer>,
+}
+
+/// An inclusive range of [`Note`]s.
+///
+/// Stands in for [`RangeInclusive`], which doesn't implement [`Copy`] (because it is also an [`Iterator`]), so that
+/// [`Keyboard`] (and in turn [`Portamento`][crate::portamento::Portamento]) can.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NoteRange {
+    start: Note,
+    end: Note,
+}
+
+impl NoteRange {
+    fn contains(&self, note: &Note) -> bool {
+        (self.start..=self.end).contains(note)
+    }
+}
+
+impl From<RangeInclusive<Note>> for NoteRange {
+    fn from(range: RangeInclusive<Note>) -> Self {
+        Self {
+            start: *range.start(),
+            end: *range.end(),
+        }
+    }
+}
+
+/// Error returned when a [`Keyboard`]'s playable range would require voltages beyond what the synth can safely accept.
+#[derive(Debug, PartialEq)]
+pub struct UnsafeVoltageError {
+    /// The voltage required to play the top of the playable range.
+    pub required: Voltage,
+    /// The highest voltage the synth's keyboard input tolerates.
+    pub max_safe_voltage: Voltage,
 }
 
 impl<T: ProvideNote> Keyboard<T> {
-    /// Constructs a [`Keyboard`].
+    /// The upper bound of the Moog Open System, used as the `max_safe_voltage` unless otherwise specified.
+    pub const DEFAULT_MAX_SAFE_VOLTS: f64 = 5.0;
+
+    /// Constructs a [`Keyboard`] whose voltages are bounded by [`DEFAULT_MAX_SAFE_VOLTS`][Self::DEFAULT_MAX_SAFE_VOLTS].
     pub fn new(
         note_provider: T,
         playable_range: RangeInclusive<Note>,
@@ -27,31 +76,187 @@ impl<T: ProvideNote> Keyboard<T> {
     ) -> Self {
         Self {
             note_provider,
-            playable_range,
+            playable_range: playable_range.into(),
             voltage_per_octave,
+            max_safe_voltage: Voltage::from_volts(Self::DEFAULT_MAX_SAFE_VOLTS),
+            quantizer: None,
         }
     }
 
+    /// Constructs a [`Keyboard`], verifying that every note in the playable range can be voiced without exceeding
+    /// `max_safe_voltage`.
+    pub fn try_new_checked(
+        note_provider: T,
+        playable_range: RangeInclusive<Note>,
+        voltage_per_octave: Voltage,
+        max_safe_voltage: Voltage,
+    ) -> Result<Self, UnsafeVoltageError> {
+        let keyboard = Self {
+            note_provider,
+            playable_range: playable_range.into(),
+            voltage_per_octave,
+            max_safe_voltage,
+            quantizer: None,
+        };
+
+        let required = keyboard.unclamped_voltage(keyboard.playable_range.end);
+        if required > max_safe_voltage {
+            Err(UnsafeVoltageError {
+                required,
+                max_safe_voltage,
+            })
+        } else {
+            Ok(keyboard)
+        }
+    }
+
+    /// Returns the [`Keyboard`] with its note provider replaced, e.g., to voice the Micromoog's keyboard (see
+    /// [`Keyboard::default`]) by arpeggio rather than note priority.
+    pub fn with_note_provider<U: ProvideNote>(self, note_provider: U) -> Keyboard<U> {
+        Keyboard {
+            note_provider,
+            playable_range: self.playable_range,
+            voltage_per_octave: self.voltage_per_octave,
+            max_safe_voltage: self.max_safe_voltage,
+            quantizer: self.quantizer,
+        }
+    }
+
+    /// Returns the lowest note in the playable range, which corresponds to 0 V.
+    pub fn lowest_note(&self) -> Note {
+        self.playable_range.start
+    }
+
+    /// Returns the [`Keyboard`] with voiced notes locked to a scale by the given [`ScaleQuantizer`].
+    pub fn with_quantizer(mut self, quantizer: ScaleQuantizer) -> Self {
+        self.quantizer = Some(quantizer);
+        self
+    }
+
     /// Selects the appropriate [`Note`] to play based on configuration and instrument range.
+    ///
+    /// If the keyboard has a [`ScaleQuantizer`], the selected note is quantized before being returned.
     pub fn provide_note(&self, notes: &ActivatedNotes) -> Option<Note> {
-        let filtered_notes = notes.iter().filter(|note| {
-            note >= self.playable_range.start() && note <= self.playable_range.end()
-        });
+        let note = self.note_provider.provide_note(self.notes_in_range(notes));
+        match &self.quantizer {
+            Some(quantizer) => note.map(|n| quantizer.quantize(n)),
+            None => note,
+        }
+    }
 
-        self.note_provider.provide_note(filtered_notes)
+    /// Returns an [`Iterator`] over the activated [`Note`]s which fall within the playable range, in order of activation.
+    ///
+    /// Useful for features which need every playable note rather than the single one selected by
+    /// [`provide_note`][Self::provide_note].
+    pub fn notes_in_range<'a>(
+        &'a self,
+        notes: &'a ActivatedNotes,
+    ) -> impl Iterator<Item = Note> + 'a {
+        notes
+            .iter()
+            .filter(|note| self.playable_range.contains(note))
     }
 
     fn voltage_per_half_step(&self) -> Voltage {
         self.voltage_per_octave / 12.0
     }
 
-    /// Returns the [`Voltage`] required for this particular [`Keyboard`] to play a given [`Note`].
+    /// Returns the [`Voltage`] required for this particular [`Keyboard`] to play a given [`Note`], never exceeding the
+    /// keyboard's max safe voltage.
     pub fn voltage(&self, note: Note) -> Voltage {
-        let nth_key = u8::from(note).saturating_sub(*self.playable_range.start() as u8);
+        let voltage = self.unclamped_voltage(note);
+        if voltage > self.max_safe_voltage {
+            self.max_safe_voltage
+        } else {
+            voltage
+        }
+    }
+
+    /// Returns the [`Voltage`] required to play a given [`Note`] bent by a Pitch Bend Change value (centered at zero;
+    /// see [`PitchBend::value`]) over a range of `semitone_range` semitones (see RPN 0).
+    ///
+    /// Like [`voltage`][Self::voltage], the result never exceeds the keyboard's max safe voltage; nor does it go below
+    /// zero, e.g., when the lowest note is bent down.
+    pub fn voltage_with_pitch_bend(&self, note: Note, bend: i16, semitone_range: u8) -> Voltage {
+        self.clamp_voltage(
+            self.unclamped_voltage(note)
+                + PitchBend::with_value(bend, semitone_range).offset_voltage(self),
+        )
+    }
+
+    /// Bounds an arbitrary [`Voltage`] to what the synth's keyboard input tolerates: no lower than zero and no higher
+    /// than the keyboard's max safe voltage.
+    ///
+    /// Useful for voltages composed outside of [`Keyboard`], e.g., a glide offset by detuning and pitch bend.
+    pub fn clamp_voltage(&self, voltage: Voltage) -> Voltage {
+        if voltage > self.max_safe_voltage {
+            self.max_safe_voltage
+        } else if voltage < Voltage::from_volts(0.0) {
+            Voltage::from_volts(0.0)
+        } else {
+            voltage
+        }
+    }
+
+    /// Returns the [`Voltage`] spanning an interval of `semitones` on this particular [`Keyboard`], which may be
+    /// fractional (e.g., for fine-tuning) or negative (for descending intervals).
+    pub fn interval_voltage(&self, semitones: f64) -> Voltage {
+        semitones * self.voltage_per_half_step()
+    }
+
+    /// Returns the additional [`Voltage`] to send to the synth's filter cutoff input so that the cutoff tracks `note` by
+    /// `tracking_amount`, from 0.0 (no tracking) to 1.0 (1 V/octave tracking); see
+    /// [`FilterTracking`][super::FilterTracking].
+    ///
+    /// Tracking is measured from the bottom of the playable range, where no voltage is produced; the voltage is not
+    /// clamped, as the filter cutoff input has its own bounds.
+    pub fn filter_tracking_cv(&self, note: Note, tracking_amount: f32) -> Voltage {
+        let semitones = i16::from(u8::from(note)) - i16::from(u8::from(self.playable_range.start));
+        self.interval_voltage(f64::from(semitones) * f64::from(tracking_amount))
+    }
+
+    fn unclamped_voltage(&self, note: Note) -> Voltage {
+        let nth_key = u8::from(note).saturating_sub(self.playable_range.start as u8);
         nth_key as f64 * self.voltage_per_half_step()
     }
 }
 
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format + ProvideNote> defmt::Format for Keyboard<T> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "Keyboard {{ note_provider: {}, playable_range: {}..={}, voltage_per_octave: {}, max_safe_voltage: {} }}",
+            self.note_provider,
+            u8::from(self.playable_range.start),
+            u8::from(self.playable_range.end),
+            self.voltage_per_octave.as_volts(),
+            self.max_safe_voltage.as_volts()
+        );
+    }
+}
+
+impl<T: Default + ProvideNote> Keyboard<T> {
+    /// Constructs a [`Keyboard`] whose note provider is `T`'s default, e.g., [`NotePriority::Low`].
+    pub fn with_default_provider(
+        playable_range: RangeInclusive<Note>,
+        voltage_per_octave: Voltage,
+    ) -> Self {
+        Self::new(T::default(), playable_range, voltage_per_octave)
+    }
+}
+
+/// Produces the Micromoog's keyboard: a playable range of `F3..=C6` at 1.0 V/octave, with low note priority (the
+/// Micromoog's native behavior).
+impl Default for Keyboard<NotePriority> {
+    fn default() -> Self {
+        Self::with_default_provider(
+            MICROMOOG_LOWEST_NOTE..=MICROMOOG_HIGHEST_NOTE,
+            Voltage::from_volts(MICROMOOG_VOLTS_PER_OCTAVE),
+        )
+    }
+}
+
 /// Trait for selecting which [`Note`] to play when many have been activated.
 pub trait ProvideNote {
     /// Selects the appropriate [`Note`] to play based on configuration and instrument range.
@@ -60,19 +265,32 @@ pub trait ProvideNote {
 
 /// A [`ProvideNote`] with variants for selecting a single activated [`Note`] from among many,
 /// based on their relative order or position.
-#[derive(Debug, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
+#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum NotePriority {
     /// Prioritizes notes based on the order in which they are received. Notes played earlier will be voiced over later ones.
     First,
     /// Prioritizes notes based on the order in which they are received. Notes played later will be voiced over earlier ones.
     Last,
     /// Prioritizes notes based on pitch. Lower notes (e.g., those on the left side of the keyboard) will be voiced over higher ones.
+    #[default]
     Low,
     /// Prioritizes notes based on pitch. Higher notes (e.g., those on the right side of the keyboard) will be voiced over lower ones.
     High,
 }
 impl super::CycleConfig for NotePriority {}
 
+impl core::fmt::Display for NotePriority {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::First => "First",
+            Self::Last => "Last",
+            Self::Low => "Low",
+            Self::High => "High",
+        })
+    }
+}
+
 impl ProvideNote for NotePriority {
     fn provide_note(&self, mut notes: impl Iterator<Item = Note>) -> Option<Note> {
         match self {
@@ -87,6 +305,7 @@ impl ProvideNote for NotePriority {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::configuration::{Scale, SecondVoiceOutput};
 
     fn chord() -> ActivatedNotes {
         let mut notes = ActivatedNotes::new();
@@ -101,12 +320,33 @@ mod tests {
     mod note_priority {
         use super::*;
 
+        #[test]
+        fn display() {
+            extern crate std;
+            use std::string::ToString;
+
+            for (expected, priority) in [
+                ("First", NotePriority::First),
+                ("Last", NotePriority::Last),
+                ("Low", NotePriority::Low),
+                ("High", NotePriority::High),
+            ] {
+                assert_eq!(
+                    expected,
+                    priority.to_string(),
+                    "Expected left but got right"
+                );
+            }
+        }
+
         #[test]
         fn first() {
             let np = Keyboard {
                 note_provider: NotePriority::First,
-                playable_range: Note::F3..=Note::C6,
+                playable_range: (Note::F3..=Note::C6).into(),
                 voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
             };
             assert_eq!(
                 Some(Note::E4),
@@ -119,8 +359,10 @@ mod tests {
         fn last() {
             let np = Keyboard {
                 note_provider: NotePriority::Last,
-                playable_range: Note::F3..=Note::C6,
+                playable_range: (Note::F3..=Note::C6).into(),
                 voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
             };
             assert_eq!(
                 Some(Note::C4),
@@ -133,8 +375,10 @@ mod tests {
         fn highest() {
             let np = Keyboard {
                 note_provider: NotePriority::High,
-                playable_range: Note::F3..=Note::C6,
+                playable_range: (Note::F3..=Note::C6).into(),
                 voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
             };
             assert_eq!(
                 Some(Note::B4),
@@ -143,12 +387,64 @@ mod tests {
             );
         }
 
+        #[test]
+        fn duplicate_notes() {
+            let mut notes = chord();
+            notes.add(Note::C4);
+            notes.add(Note::B4);
+
+            for (note_provider, expected) in [
+                (NotePriority::First, Note::E4),
+                (NotePriority::Last, Note::C4),
+                (NotePriority::Low, Note::C4),
+                (NotePriority::High, Note::B4),
+            ] {
+                let np = Keyboard {
+                    note_provider,
+                    playable_range: (Note::F3..=Note::C6).into(),
+                    voltage_per_octave: Voltage::from_volts(1.0),
+                    max_safe_voltage: Voltage::from_volts(5.0),
+                    quantizer: None,
+                };
+                assert_eq!(
+                    Some(expected),
+                    np.provide_note(&notes),
+                    "Duplicate note events should not affect {:?} priority; expected left but got right",
+                    note_provider
+                );
+            }
+        }
+
+        #[test]
+        fn single_note() {
+            let mut notes = ActivatedNotes::new();
+            notes.add(Note::A4);
+
+            for note_provider in [NotePriority::High, NotePriority::Low] {
+                let np = Keyboard {
+                    note_provider,
+                    playable_range: (Note::F3..=Note::C6).into(),
+                    voltage_per_octave: Voltage::from_volts(1.0),
+                    max_safe_voltage: Voltage::from_volts(5.0),
+                    quantizer: None,
+                };
+                assert_eq!(
+                    Some(Note::A4),
+                    np.provide_note(&notes),
+                    "With only one note activated, {:?} priority should voice it; expected left but got right",
+                    note_provider
+                );
+            }
+        }
+
         #[test]
         fn lowest() {
             let np = Keyboard {
                 note_provider: NotePriority::Low,
-                playable_range: Note::F3..=Note::C6,
+                playable_range: (Note::F3..=Note::C6).into(),
                 voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
             };
             assert_eq!(
                 Some(Note::C4),
@@ -157,4 +453,256 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn default() {
+        assert_eq!(
+            Keyboard {
+                note_provider: NotePriority::Low,
+                playable_range: (Note::F3..=Note::C6).into(),
+                voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
+            },
+            Keyboard::default(),
+            "Expected the Micromoog's keyboard; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn copy() {
+        let keyboard = Keyboard::default();
+        let copy = keyboard;
+        assert_eq!(
+            keyboard, copy,
+            "Expected a copy to equal the original; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn with_default_provider() {
+        let keyboard: Keyboard<SecondVoiceOutput> =
+            Keyboard::with_default_provider(Note::C2..=Note::C4, Voltage::from_volts(1.2));
+        assert_eq!(
+            Keyboard {
+                note_provider: SecondVoiceOutput::Disabled,
+                playable_range: (Note::C2..=Note::C4).into(),
+                voltage_per_octave: Voltage::from_volts(1.2),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
+            },
+            keyboard,
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn with_note_provider() {
+        let keyboard = Keyboard::default().with_note_provider(SecondVoiceOutput::HighestNote);
+        assert_eq!(
+            Keyboard {
+                note_provider: SecondVoiceOutput::HighestNote,
+                playable_range: (Note::F3..=Note::C6).into(),
+                voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(5.0),
+                quantizer: None,
+            },
+            keyboard,
+            "Expected only the note provider to change; expected left but got right"
+        );
+        assert_eq!(
+            Note::F3,
+            keyboard.lowest_note(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn with_quantizer() {
+        let mut notes = ActivatedNotes::new();
+        notes.add(Note::CSharp4);
+
+        let keyboard = Keyboard::default();
+        assert_eq!(
+            Some(Note::CSharp4),
+            keyboard.provide_note(&notes),
+            "Expected left but got right"
+        );
+
+        let keyboard = keyboard.with_quantizer(ScaleQuantizer::new(Scale::Major, Note::C4));
+        assert_eq!(
+            Some(Note::C4),
+            keyboard.provide_note(&notes),
+            "Expected the provided note to be quantized; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn notes_in_range() {
+        let keyboard = Keyboard {
+            note_provider: NotePriority::Low,
+            playable_range: (Note::F3..=Note::C6).into(),
+            voltage_per_octave: Voltage::from_volts(1.0),
+            max_safe_voltage: Voltage::from_volts(5.0),
+            quantizer: None,
+        };
+        let mut notes = chord();
+        notes.add(Note::C2);
+        notes.add(Note::C6);
+        notes.add(Note::Db6);
+
+        assert!(
+            keyboard
+                .notes_in_range(&notes)
+                .eq([Note::E4, Note::G4, Note::B4, Note::C4, Note::C6]),
+            "Expected only notes within the playable range, in order of activation"
+        );
+    }
+
+    mod voltage {
+        use super::*;
+
+        #[test]
+        fn clamped_to_max_safe_voltage() {
+            let keyboard = Keyboard {
+                note_provider: NotePriority::Low,
+                playable_range: (Note::F3..=Note::C6).into(),
+                voltage_per_octave: Voltage::from_volts(1.0),
+                max_safe_voltage: Voltage::from_volts(1.0),
+                quantizer: None,
+            };
+            assert_eq!(
+                Voltage::from_volts(0.5),
+                keyboard.voltage(Note::B3),
+                "Expected voltages below the bound to be unaffected; expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(1.0),
+                keyboard.voltage(Note::C6),
+                "Expected voltage to be clamped; expected left but got right"
+            );
+        }
+
+        #[test]
+        fn interval_voltage() {
+            let keyboard = Keyboard::default();
+            assert_eq!(
+                Voltage::from_volts(1.0),
+                keyboard.interval_voltage(12.0),
+                "Expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(-1.0 / 24.0),
+                keyboard.interval_voltage(-0.5),
+                "Expected left but got right"
+            );
+        }
+
+        #[test]
+        fn voltage_with_pitch_bend() {
+            let keyboard = Keyboard::default();
+            assert_eq!(
+                keyboard.voltage(Note::C4),
+                keyboard.voltage_with_pitch_bend(Note::C4, 0, 2),
+                "Expected no bend to leave the voltage unaltered; expected left but got right"
+            );
+            assert_eq!(
+                keyboard.voltage(Note::F4),
+                keyboard.voltage_with_pitch_bend(Note::F3, 8191, 12),
+                "Expected a full bend up to span the range; expected left but got right"
+            );
+            assert_eq!(
+                keyboard.voltage(Note::F3),
+                keyboard.voltage_with_pitch_bend(Note::F4, i16::MIN, 12),
+                "Expected an out-of-range bend to be clamped; expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(0.0),
+                keyboard.voltage_with_pitch_bend(Note::F3, -8192, 2),
+                "Expected left but got right"
+            );
+        }
+
+        #[test]
+        fn clamp_voltage() {
+            let keyboard = Keyboard::default();
+            assert_eq!(
+                Voltage::from_volts(1.5),
+                keyboard.clamp_voltage(Voltage::from_volts(1.5)),
+                "Expected voltages within bounds to be unaffected; expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(Keyboard::<NotePriority>::DEFAULT_MAX_SAFE_VOLTS),
+                keyboard
+                    .clamp_voltage(keyboard.voltage(Note::C6) + keyboard.interval_voltage(36.0)),
+                "Expected an offset past the max safe voltage to be clamped; expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(Keyboard::<NotePriority>::DEFAULT_MAX_SAFE_VOLTS),
+                keyboard.clamp_voltage(
+                    keyboard.voltage(Note::C6)
+                        + PitchBend::with_value(8191, 48).offset_voltage(&keyboard)
+                ),
+                "Expected a bend past the max safe voltage to be clamped; expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(0.0),
+                keyboard.clamp_voltage(Voltage::from_volts(-0.25)),
+                "Expected negative voltages to be clamped to zero; expected left but got right"
+            );
+        }
+
+        #[test]
+        fn filter_tracking_cv() {
+            let keyboard = Keyboard::default();
+            assert_eq!(
+                Voltage::from_volts(1.0),
+                keyboard.filter_tracking_cv(Note::F4, 1.0),
+                "Expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(0.5),
+                keyboard.filter_tracking_cv(Note::F4, 0.5),
+                "Expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(0.0),
+                keyboard.filter_tracking_cv(Note::C6, 0.0),
+                "Expected left but got right"
+            );
+            assert_eq!(
+                Voltage::from_volts(-1.0),
+                keyboard.filter_tracking_cv(Note::F2, 1.0),
+                "Expected notes below the playable range to lower the cutoff; expected left but got right"
+            );
+        }
+
+        #[test]
+        fn try_new_checked() {
+            assert!(
+                Keyboard::try_new_checked(
+                    NotePriority::Low,
+                    Note::F3..=Note::C6,
+                    Voltage::from_volts(1.0),
+                    Voltage::from_volts(5.0),
+                )
+                .is_ok(),
+                "Expected the Micromoog's range to be within the Moog Open System's bounds"
+            );
+
+            assert_eq!(
+                Err(UnsafeVoltageError {
+                    required: Voltage::from_volts(2.5),
+                    max_safe_voltage: Voltage::from_volts(2.0),
+                }),
+                Keyboard::try_new_checked(
+                    NotePriority::Low,
+                    Note::F3..=Note::B5,
+                    Voltage::from_volts(1.0),
+                    Voltage::from_volts(2.0),
+                ),
+                "Expected left but got right"
+            );
+        }
+    }
 }
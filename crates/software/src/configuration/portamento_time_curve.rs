@@ -0,0 +1,164 @@
+#[cfg(feature = "embassy-time")]
+use embassy_time::Duration;
+use num_derive::{FromPrimitive, ToPrimitive};
+#[cfg(feature = "embassy-time")]
+use wmidi::ControlValue;
+
+/// Determines how the Portamento Time control value (MIDI CC 5) maps to the duration of a glide.
+#[derive(Debug, Default, Clone, Copy, ToPrimitive, FromPrimitive, PartialEq)]
+pub enum PortamentoTimeCurve {
+    /// The full range of control values maps linearly onto `0..=MAX_GLIDE_TIME`.
+    #[default]
+    Linear,
+    /// The lower half of control values (0–63) maps onto `0..1` second and the upper half (64–127) onto `1..=5` seconds,
+    /// giving finer control over short glides, which tend to be the most musically useful.
+    Fine,
+}
+
+#[cfg(feature = "embassy-time")]
+impl PortamentoTimeCurve {
+    /// The max control value for Portamento Time will have a [`Duration`] of `MAX_GLIDE_TIME`.
+    ///
+    /// The value for this constant was selected to match the built-in behavior of the Micromoog.
+    pub const MAX_GLIDE_TIME: Duration = Duration::from_secs(5);
+
+    /// The [`Duration`] at which [`PortamentoTimeCurve::Fine`] switches from the fine-grained to the coarse-grained range.
+    const FINE_BREAKPOINT_TIME: Duration = Duration::from_secs(1);
+
+    /// The max 14-bit Portamento Time, i.e., CC 5 (MSB) and CC 37 (LSB) both at their max control values.
+    const HIGH_RES_MAX_TIME: u16 = (1 << 14) - 1;
+
+    /// Returns the glide [`Duration`] for a Portamento Time control value.
+    pub fn duration(&self, time: ControlValue) -> Duration {
+        self.scale(u32::from(u8::from(time)), 127)
+    }
+
+    /// Returns the glide [`Duration`] for a 14-bit Portamento Time, i.e., CC 5 (MSB) and CC 37 (LSB) combined, as
+    /// given by [`high_res_time`][crate::midi_state::Portamento::high_res_time].
+    ///
+    /// Follows the same curve as [`duration`][Self::duration], but resolves 128 steps between each of its values.
+    pub fn high_res_duration(&self, time: u16) -> Duration {
+        self.scale(
+            u32::from(time.min(Self::HIGH_RES_MAX_TIME)),
+            u32::from(Self::HIGH_RES_MAX_TIME),
+        )
+    }
+
+    /// Maps `time`, out of a maximum of `max`, onto the curve.
+    ///
+    /// [`PortamentoTimeCurve::Fine`] switches from the fine-grained to the coarse-grained range halfway through the
+    /// values, e.g., at 64 for a 7-bit control value.
+    fn scale(&self, time: u32, max: u32) -> Duration {
+        match self {
+            Self::Linear => Self::MAX_GLIDE_TIME * time / max,
+            Self::Fine => {
+                let breakpoint = max.div_ceil(2);
+                if time < breakpoint {
+                    Self::FINE_BREAKPOINT_TIME * time / breakpoint
+                } else {
+                    Self::FINE_BREAKPOINT_TIME
+                        + (Self::MAX_GLIDE_TIME - Self::FINE_BREAKPOINT_TIME) * (time - breakpoint)
+                            / (max - breakpoint)
+                }
+            }
+        }
+    }
+}
+
+impl super::CycleConfig for PortamentoTimeCurve {}
+
+#[cfg(all(test, feature = "embassy-time"))]
+mod tests {
+    use super::*;
+    use wmidi::U7;
+
+    #[test]
+    fn linear() {
+        let curve = PortamentoTimeCurve::Linear;
+        assert_eq!(
+            Duration::from_secs(0),
+            curve.duration(U7::from_u8_lossy(0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_micros(2_519_685),
+            curve.duration(U7::from_u8_lossy(64)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            curve.duration(U7::from_u8_lossy(127)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn fine() {
+        let curve = PortamentoTimeCurve::Fine;
+        assert_eq!(
+            Duration::from_secs(0),
+            curve.duration(U7::from_u8_lossy(0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_micros(31_250),
+            curve.duration(U7::from_u8_lossy(2)),
+            "Expected lower range to be fine-grained; expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_micros(984_375),
+            curve.duration(U7::from_u8_lossy(63)),
+            "Expected lower range to stay under a second; expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            curve.duration(U7::from_u8_lossy(64)),
+            "Expected upper range to start at one second; expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            curve.duration(U7::from_u8_lossy(127)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn high_res_linear() {
+        let curve = PortamentoTimeCurve::Linear;
+        assert_eq!(
+            Duration::from_secs(0),
+            curve.high_res_duration(0),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_micros(305),
+            curve.high_res_duration(1),
+            "Expected the LSB to resolve steps finer than the MSB; expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            curve.high_res_duration(16383),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn high_res_fine() {
+        let curve = PortamentoTimeCurve::Fine;
+        assert_eq!(
+            Duration::from_micros(122),
+            curve.high_res_duration(1),
+            "Expected lower range to be fine-grained; expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            curve.high_res_duration(64 << 7),
+            "Expected upper range to start at the same control value as the 7-bit curve; expected left but got right"
+        );
+        assert_eq!(
+            Duration::from_secs(5),
+            curve.high_res_duration(16383),
+            "Expected left but got right"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+use wmidi::Note;
+
+/// A set of pitch classes to which voiced notes may be locked; see [`ScaleQuantizer`].
+#[derive(Debug, Default, Clone, Copy, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Scale {
+    /// All twelve pitch classes, i.e., no quantization.
+    #[default]
+    Chromatic,
+    /// The major scale (Ionian mode).
+    Major,
+    /// The natural minor scale (Aeolian mode).
+    NaturalMinor,
+    /// The natural minor scale with a raised seventh.
+    HarmonicMinor,
+    /// The major scale without its fourth and seventh degrees.
+    MajorPentatonic,
+    /// The natural minor scale without its second and sixth degrees.
+    MinorPentatonic,
+}
+
+impl Scale {
+    /// Returns the pitch classes of the scale as semitones above its root, in ascending order.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            Self::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Self::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Self::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            Self::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Self::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Self::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+}
+
+impl super::CycleConfig for Scale {}
+
+/// Locks [`Note`]s to a [`Scale`] rooted at a given tonic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaleQuantizer {
+    scale: Scale,
+    /// Only the pitch class of the root matters; e.g., C4 and C5 yield the same quantizer.
+    root: Note,
+}
+
+impl ScaleQuantizer {
+    /// Constructs a [`ScaleQuantizer`] for the given [`Scale`], rooted at `root`.
+    pub fn new(scale: Scale, root: Note) -> Self {
+        Self { scale, root }
+    }
+
+    /// Returns `true` if the MIDI note number belongs to the scale, in any octave.
+    fn is_in_scale(&self, note_number: u8) -> bool {
+        let interval = (note_number + 12 - u8::from(self.root) % 12) % 12;
+        self.scale.intervals().contains(&interval)
+    }
+
+    /// Returns the in-scale [`Note`] nearest to `note`, breaking ties in favor of the lower note.
+    ///
+    /// The nearest note may lie in an adjacent octave; e.g., B4 in C major pentatonic quantizes to C5.
+    pub fn quantize(&self, note: Note) -> Note {
+        let note_number = u8::from(note);
+        // every scale has a pitch class within a tritone of any note, so the search is bounded
+        (0..=6)
+            .flat_map(|distance| {
+                [
+                    note_number.checked_sub(distance),
+                    note_number
+                        .checked_add(distance)
+                        .filter(|&n| n <= u8::from(Note::HIGHEST_NOTE)),
+                ]
+            })
+            .flatten()
+            .find(|&n| self.is_in_scale(n))
+            .map_or(note, Note::from_u8_lossy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chromatic() {
+        let quantizer = ScaleQuantizer::new(Scale::Chromatic, Note::C4);
+        assert_eq!(
+            Note::CSharp4,
+            quantizer.quantize(Note::CSharp4),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn in_scale() {
+        let quantizer = ScaleQuantizer::new(Scale::Major, Note::C4);
+        assert_eq!(
+            Note::B4,
+            quantizer.quantize(Note::B4),
+            "Expected in-scale notes to be unaffected; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn tie_favors_lower_note() {
+        let quantizer = ScaleQuantizer::new(Scale::Major, Note::C4);
+        assert_eq!(
+            Note::C4,
+            quantizer.quantize(Note::CSharp4),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn across_octave_boundary() {
+        let quantizer = ScaleQuantizer::new(Scale::MajorPentatonic, Note::C4);
+        assert_eq!(
+            Note::C5,
+            quantizer.quantize(Note::B4),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn root() {
+        let quantizer = ScaleQuantizer::new(Scale::MinorPentatonic, Note::A2);
+        assert_eq!(
+            Note::E4,
+            quantizer.quantize(Note::F4),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Note::A4,
+            quantizer.quantize(Note::ASharp4),
+            "Expected the root's octave not to matter; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn extremes() {
+        let quantizer = ScaleQuantizer::new(Scale::MajorPentatonic, Note::D4);
+        assert_eq!(
+            Note::DMinus1,
+            quantizer.quantize(Note::LOWEST_NOTE),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Note::FSharp9,
+            quantizer.quantize(Note::HIGHEST_NOTE),
+            "Expected left but got right"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use super::ProvideNote;
+use num_derive::{FromPrimitive, ToPrimitive};
+use wmidi::Note;
+
+/// Determines which note, if any, to send to a second synthesizer, e.g., to voice the top of a chord while the primary
+/// synth plays the bass.
+#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SecondVoiceOutput {
+    /// Nothing is sent to the second output.
+    #[default]
+    Disabled,
+    /// The highest activated note is sent to the second output.
+    HighestNote,
+    /// The second-highest activated note is sent to the second output, leaving the highest for, e.g., a melody.
+    SecondHighestNote,
+}
+impl super::CycleConfig for SecondVoiceOutput {}
+
+impl ProvideNote for SecondVoiceOutput {
+    fn provide_note(&self, notes: impl Iterator<Item = Note>) -> Option<Note> {
+        match self {
+            Self::Disabled => None,
+            Self::HighestNote => notes.max(),
+            Self::SecondHighestNote => {
+                let (_highest, second_highest) =
+                    notes.fold((None, None), |(highest, second_highest), note| {
+                        if Some(note) > highest {
+                            (Some(note), highest)
+                        } else if Some(note) > second_highest {
+                            (highest, Some(note))
+                        } else {
+                            (highest, second_highest)
+                        }
+                    });
+                second_highest
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHORD: [Note; 4] = [Note::E4, Note::G4, Note::B4, Note::C4];
+
+    #[test]
+    fn disabled() {
+        assert_eq!(
+            None,
+            SecondVoiceOutput::Disabled.provide_note(CHORD.into_iter()),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn highest_note() {
+        assert_eq!(
+            Some(Note::B4),
+            SecondVoiceOutput::HighestNote.provide_note(CHORD.into_iter()),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn second_highest_note() {
+        assert_eq!(
+            Some(Note::G4),
+            SecondVoiceOutput::SecondHighestNote.provide_note(CHORD.into_iter()),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            SecondVoiceOutput::SecondHighestNote.provide_note([Note::C4].into_iter()),
+            "Expected no second voice for a single note"
+        );
+    }
+}
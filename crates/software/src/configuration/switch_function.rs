@@ -0,0 +1,82 @@
+use wmidi::ControlFunction;
+
+/// A configuration option which can be toggled by one of the general-purpose switch controllers (MIDI CC 80-83).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchFunction {
+    /// Advances to the next [`NotePriority`][super::NotePriority] each time the switch is turned on.
+    NotePriorityToggle,
+    /// Enables [`ChordCleanup`][super::ChordCleanup] while the switch is on and disables it while the switch is off.
+    ChordCleanupToggle,
+    /// Enables portamento while the switch is on and disables it while the switch is off.
+    PortamentoToggle,
+    /// Enables arpeggiation while the switch is on and disables it while the switch is off.
+    ArpeggiatorToggle,
+    /// Advances to the next [`ControllerOutput`][super::ControllerOutput] each time the switch is turned on.
+    ControllerOutputToggle,
+    /// Steps the held voltage back to the previously voiced note (see
+    /// [`VoiceHistory::step_back`][crate::voice_history::VoiceHistory::step_back]) each time the switch is turned on
+    /// while all notes are released.
+    StepBack,
+}
+
+/// Maps the general-purpose switch controller numbers (MIDI CC 80-83) to the [`SwitchFunction`] each one toggles; see
+/// [`switch_index`].
+pub type SwitchAssignment = [(u8, SwitchFunction); 4];
+
+/// Assigns the general-purpose switch controllers to functions in ascending order.
+pub const DEFAULT_SWITCH_ASSIGNMENT: SwitchAssignment = [
+    (80, SwitchFunction::NotePriorityToggle),
+    (81, SwitchFunction::ChordCleanupToggle),
+    (82, SwitchFunction::PortamentoToggle),
+    (83, SwitchFunction::ControllerOutputToggle),
+];
+
+/// Returns the index of the general-purpose switch controller numbered `controller` within
+/// [`MidiState::general_purpose_switches`][crate::midi_state::MidiState::general_purpose_switches], or `None` if it isn't
+/// one of MIDI CC 80-83.
+pub fn switch_index(controller: u8) -> Option<usize> {
+    let first = u8::from(ControlFunction::GENERAL_PURPOSE_CONTROLLER_5);
+    let last = u8::from(ControlFunction::GENERAL_PURPOSE_CONTROLLER_8);
+    (first..=last)
+        .contains(&controller)
+        .then(|| usize::from(controller - first))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_index() {
+        assert_eq!(
+            Some(0),
+            super::switch_index(80),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(3),
+            super::switch_index(83),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            super::switch_index(79),
+            "Expected no index for a controller which isn't a switch"
+        );
+        assert_eq!(
+            None,
+            super::switch_index(84),
+            "Expected no index for a controller which isn't a switch"
+        );
+    }
+
+    #[test]
+    fn default_switch_assignment() {
+        assert!(
+            DEFAULT_SWITCH_ASSIGNMENT
+                .iter()
+                .all(|&(controller, _)| super::switch_index(controller).is_some()),
+            "Expected every default assignment to name a general-purpose switch"
+        );
+    }
+}
@@ -0,0 +1,22 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+
+/// Determines which parameter the thumbwheel (a potentiometer read via <abbr title="analog-to-digital
+/// converter">ADC</abbr>) controls.
+///
+/// The thumbwheel is the continuous counterpart to the pushbutton controls, which can only step through values.
+#[derive(Debug, Default, Copy, Clone, ToPrimitive, FromPrimitive, PartialEq)]
+pub enum ThumbwheelAssignment {
+    /// The thumbwheel sets the Portamento Time, as if by MIDI CC 5.
+    #[default]
+    PortamentoTime,
+    /// The thumbwheel sets the length of the [`ChordCleanup`][super::ChordCleanup] batching period.
+    ChordCleanupDuration,
+    /// The thumbwheel transposes the voiced note by up to an octave either way, as if by Channel Coarse Tuning (RPN 2);
+    /// see [`to_transpose`][crate::thumbwheel::to_transpose].
+    Transpose,
+    /// The thumbwheel sets the time between steps of the [`Arpeggiator`][super::Arpeggiator], overriding any
+    /// [`RateSource::MidiClock`][super::RateSource::MidiClock]; see
+    /// [`RateSource::from_control_value`][super::RateSource::from_control_value].
+    ArpeggiatorRate,
+}
+impl super::CycleConfig for ThumbwheelAssignment {}
@@ -0,0 +1,39 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+
+/// Determines which edge of the gate (or trigger) signal starts a note, i.e., whether the signal is inverted.
+#[derive(Debug, Default, Clone, Copy, ToPrimitive, FromPrimitive, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    /// The signal is low during silence and goes high when a note is played.
+    #[default]
+    Rising,
+    /// The signal is high during silence and goes low when a note is played, for synthesizers whose envelopes are
+    /// triggered by a falling edge.
+    Falling,
+}
+
+impl TriggerEdge {
+    /// Returns `true` if the signal is high while a note is played, i.e., if it is not inverted.
+    pub fn is_active_high(&self) -> bool {
+        *self == Self::Rising
+    }
+}
+
+impl super::CycleConfig for TriggerEdge {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_active_high() {
+        assert!(
+            TriggerEdge::Rising.is_active_high(),
+            "Expected a rising edge to start a note"
+        );
+        assert!(
+            !TriggerEdge::Falling.is_active_high(),
+            "Expected a falling edge to start a note"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use num_derive::{FromPrimitive, ToPrimitive};
+use wmidi::U7;
+
+/// Determines whether (and how) the velocity of a note scales the duration of the glide toward it.
+#[derive(Debug, Default, Clone, Copy, ToPrimitive, FromPrimitive, PartialEq)]
+pub enum VelocityGlide {
+    /// Velocity has no effect on the glide.
+    #[default]
+    Off,
+    /// The harder the keystrike, the shorter the glide; a note struck at maximum velocity changes instantly.
+    HarderIsFaster,
+    /// The harder the keystrike, the longer the glide; a note struck at minimum velocity changes instantly.
+    HarderIsSlower,
+}
+
+impl VelocityGlide {
+    /// Returns the factor by which the duration of a glide toward a note of the given velocity is scaled.
+    pub fn factor(&self, velocity: U7) -> f64 {
+        let velocity = f64::from(u8::from(velocity));
+        match self {
+            Self::Off => 1.0,
+            Self::HarderIsFaster => (127.0 - velocity) / 127.0,
+            Self::HarderIsSlower => velocity / 127.0,
+        }
+    }
+}
+
+impl super::CycleConfig for VelocityGlide {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off() {
+        assert_eq!(
+            1.0,
+            VelocityGlide::Off.factor(U7::MAX),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            1.0,
+            VelocityGlide::Off.factor(U7::MIN),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn harder_is_faster() {
+        assert_eq!(
+            0.0,
+            VelocityGlide::HarderIsFaster.factor(U7::MAX),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            1.0,
+            VelocityGlide::HarderIsFaster.factor(U7::MIN),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn harder_is_slower() {
+        assert_eq!(
+            1.0,
+            VelocityGlide::HarderIsSlower.factor(U7::MAX),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0.0,
+            VelocityGlide::HarderIsSlower.factor(U7::MIN),
+            "Expected left but got right"
+        );
+    }
+}
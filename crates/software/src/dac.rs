@@ -0,0 +1,106 @@
+//! Provides [`DacConfig`] for converting [`Voltage`]s into the values a <abbr name="digital-to-analog
+//! converter">DAC</abbr> expects, so that voltages can stay type-safe all the way to the hardware.
+
+use measurements::Voltage;
+
+/// Describes a <abbr name="digital-to-analog converter">DAC</abbr> channel in hardware-independent terms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DacConfig {
+    /// The voltage output at the DAC's maximum value, in volts.
+    reference_volts: f64,
+    /// The largest value the DAC accepts.
+    max_value: u16,
+}
+
+impl DacConfig {
+    /// Constructs a new [`DacConfig`] for a DAC with the given reference voltage (in volts) and resolution (in bits).
+    ///
+    /// The reference voltage is given as a plain `f64` so that the config can be constructed in a `const` context.
+    pub const fn new(reference_volts: f64, resolution_bits: u8) -> Self {
+        Self {
+            reference_volts,
+            max_value: ((1_u32 << resolution_bits) - 1) as u16,
+        }
+    }
+
+    /// Returns the voltage output at the DAC's maximum value.
+    pub fn reference_voltage(&self) -> Voltage {
+        Voltage::from_volts(self.reference_volts)
+    }
+
+    /// Converts a [`Voltage`] to the DAC value which outputs it, saturating at the DAC's range.
+    pub fn to_dac_value(&self, voltage: Voltage) -> u16 {
+        let value = voltage / self.reference_voltage() * f64::from(self.max_value);
+        // float-to-int casts saturate, so negative voltages come out as 0
+        (value as u16).min(self.max_value)
+    }
+
+    /// Converts a [`Voltage`] to the DAC value which outputs it, or returns `None` if the voltage is negative or exceeds
+    /// the reference voltage, i.e., if [`to_dac_value`][Self::to_dac_value] would have to saturate.
+    pub fn to_dac_value_checked(&self, voltage: Voltage) -> Option<u16> {
+        if voltage < Voltage::from_volts(0.0) || voltage > self.reference_voltage() {
+            None
+        } else {
+            Some(self.to_dac_value(voltage))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFERENCE_VOLTAGE: f64 = 10.0 / 3.0;
+
+    #[test]
+    fn to_dac_value() {
+        let dac = DacConfig::new(REFERENCE_VOLTAGE, 12);
+        for volts in [0.0, 0.25, 1.0, 1.5, 2.75, REFERENCE_VOLTAGE] {
+            assert_eq!(
+                (volts / REFERENCE_VOLTAGE * 4095.0) as u16,
+                dac.to_dac_value(Voltage::from_volts(volts)),
+                "Expected conversion to match the float formula for {volts} V"
+            );
+        }
+    }
+
+    #[test]
+    fn to_dac_value_saturates() {
+        let dac = DacConfig::new(REFERENCE_VOLTAGE, 12);
+        assert_eq!(
+            4095,
+            dac.to_dac_value(Voltage::from_volts(5.0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0,
+            dac.to_dac_value(Voltage::from_volts(-1.0)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn to_dac_value_checked() {
+        let dac = DacConfig::new(REFERENCE_VOLTAGE, 12);
+        assert_eq!(
+            Some(0),
+            dac.to_dac_value_checked(Voltage::from_volts(0.0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(4095),
+            dac.to_dac_value_checked(Voltage::from_volts(REFERENCE_VOLTAGE)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            dac.to_dac_value_checked(Voltage::from_volts(5.0)),
+            "Expected voltages above the reference to be rejected"
+        );
+        assert_eq!(
+            None,
+            dac.to_dac_value_checked(Voltage::from_volts(-0.1)),
+            "Expected negative voltages to be rejected"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Provides [`MidiEventLog`] for recording the MIDI events the device has received, enabling post-mortem debugging
+//! (i.e., "what did the controller send?") without a logic analyzer or MIDI monitor.
+
+use crate::timestamp::NoteEventTimestamp;
+use wmidi::MidiMessage;
+
+/// The default number of events retained by a [`MidiEventLog`].
+const DEFAULT_EVENT_LOG_LEN: usize = 256;
+
+/// Manufacturer ID reserved for non-commercial use, under which the log is dumped.
+const NON_COMMERCIAL: u8 = 0x7D;
+/// Identifies a System Exclusive message as an event log dump; shared with [`SysExRouter`][crate::sysex::SysExRouter].
+pub(crate) const EVENT_LOG_DUMP: u8 = 0x01;
+
+/// The number of bytes each event occupies in a dump: status, two data bytes, and a 35-bit timestamp in 7-bit chunks.
+const DUMPED_EVENT_LEN: usize = 8;
+/// The number of 7-bit chunks in a dumped timestamp.
+const DUMPED_TIMESTAMP_LEN: usize = 5;
+
+/// A compact record of a single MIDI message and the time at which it was received.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MidiEvent {
+    /// The status byte, which identifies the type of message (and, for channel messages, the channel).
+    pub status: u8,
+    /// Up to two data bytes; unused bytes are 0.
+    pub data: [u8; 2],
+    /// When the message was received.
+    pub at: NoteEventTimestamp,
+}
+
+impl MidiEvent {
+    /// Constructs a [`MidiEvent`] from a [`MidiMessage`], or returns `None` for System Exclusive messages, which don't
+    /// fit.
+    pub fn new(msg: &MidiMessage, at: NoteEventTimestamp) -> Option<Self> {
+        if let MidiMessage::SysEx(_) = msg {
+            return None;
+        }
+        let mut bytes = [0_u8; 3];
+        msg.copy_to_slice(&mut bytes).ok()?;
+        Some(Self {
+            status: bytes[0],
+            data: [bytes[1], bytes[2]],
+            at,
+        })
+    }
+}
+
+/// A circular buffer of the last `N` [`MidiEvent`]s received; once full, each new event displaces the oldest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiEventLog<const N: usize = DEFAULT_EVENT_LOG_LEN> {
+    /// Storage for the retained events; slots are filled in order, wrapping around once the end is reached.
+    events: [MidiEvent; N],
+    /// Index of the slot the next event will occupy.
+    next: usize,
+    /// The number of slots occupied.
+    len: usize,
+}
+
+impl<const N: usize> Default for MidiEventLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MidiEventLog<N> {
+    /// The length of the System Exclusive message [`write_sysex`][Self::write_sysex] writes once the log is full, i.e.,
+    /// the size of a buffer which always suffices.
+    pub const SYSEX_LEN: usize = 4 + N * DUMPED_EVENT_LEN;
+
+    /// Constructs a new, empty [`MidiEventLog`].
+    pub const fn new() -> Self {
+        Self {
+            events: [MidiEvent {
+                status: 0,
+                data: [0; 2],
+                at: NoteEventTimestamp::from_micros(0),
+            }; N],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Records a [`MidiMessage`] received at the given time. System Exclusive messages are not recorded.
+    pub fn record(&mut self, msg: &MidiMessage, at: NoteEventTimestamp) {
+        if N == 0 {
+            return;
+        }
+        if let Some(event) = MidiEvent::new(msg, at) {
+            self.events[self.next] = event;
+            self.next = (self.next + 1) % N;
+            self.len = (self.len + 1).min(N);
+        }
+    }
+
+    /// Returns an [`Iterator`] over the recorded events, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &MidiEvent> {
+        let (newest, oldest) = self.events.split_at(self.next);
+        oldest.iter().chain(newest.iter()).skip(N - self.len)
+    }
+
+    /// Writes the log to `buf` as a complete System Exclusive message, returning the number of bytes written, or `None`
+    /// if `buf` is too small.
+    ///
+    /// Each event is encoded in eight 7-bit bytes: the status byte (sans its high bit, which is always set), the two
+    /// data bytes, then the low 35 bits of the timestamp in microseconds, least-significant chunk first.
+    pub fn write_sysex(&self, buf: &mut [u8]) -> Option<usize> {
+        let len = 4 + self.len * DUMPED_EVENT_LEN;
+        let buf = buf.get_mut(..len)?;
+
+        buf[..3].copy_from_slice(&[0xF0, NON_COMMERCIAL, EVENT_LOG_DUMP]);
+        for (chunk, event) in buf[3..len - 1]
+            .chunks_exact_mut(DUMPED_EVENT_LEN)
+            .zip(self.iter())
+        {
+            chunk[0] = event.status & 0x7F;
+            chunk[1..3].copy_from_slice(&event.data);
+            let micros = event.at.as_micros();
+            for (i, byte) in chunk[3..3 + DUMPED_TIMESTAMP_LEN].iter_mut().enumerate() {
+                *byte = ((micros >> (7 * i)) & 0x7F) as u8;
+            }
+        }
+        buf[len - 1] = 0xF7;
+
+        Some(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmidi::{Channel, Note, U7};
+
+    fn note_on(note: Note) -> MidiMessage<'static> {
+        MidiMessage::NoteOn(Channel::Ch1, note, U7::from_u8_lossy(100))
+    }
+
+    #[test]
+    fn record() {
+        let mut log = MidiEventLog::<4>::new();
+        log.record(&note_on(Note::C4), NoteEventTimestamp::from_micros(10));
+        log.record(
+            &MidiMessage::SysEx(&[]),
+            NoteEventTimestamp::from_micros(20),
+        );
+
+        assert!(
+            log.iter().eq([&MidiEvent {
+                status: 0x90,
+                data: [60, 100],
+                at: NoteEventTimestamp::from_micros(10),
+            }]),
+            "Expected the NoteOn to be recorded and the SysEx to be skipped"
+        );
+    }
+
+    #[test]
+    fn oldest_event_displaced() {
+        let mut log = MidiEventLog::<2>::new();
+        for (micros, note) in [(1, Note::C4), (2, Note::E4), (3, Note::G4)] {
+            log.record(&note_on(note), NoteEventTimestamp::from_micros(micros));
+        }
+
+        assert!(
+            log.iter().map(|event| event.at.as_micros()).eq([2, 3]),
+            "Expected events oldest first, with the first one displaced"
+        );
+    }
+
+    #[test]
+    fn write_sysex() {
+        let mut log = MidiEventLog::<4>::new();
+        log.record(&note_on(Note::C4), NoteEventTimestamp::from_micros(200));
+
+        let mut buf = [0_u8; 16];
+        assert_eq!(
+            Some(12),
+            log.write_sysex(&mut buf),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            [0xF0, 0x7D, 0x01, 0x10, 60, 100, 0x48, 0x01, 0, 0, 0, 0xF7],
+            buf[..12],
+            "Expected left but got right"
+        );
+
+        assert_eq!(
+            None,
+            log.write_sysex(&mut [0_u8; 11]),
+            "Expected a buffer too small for the dump to be rejected"
+        );
+    }
+}
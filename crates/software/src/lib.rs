@@ -9,10 +9,37 @@
 /// Data structures for tracking MIDI messages the device has received.
 pub mod midi_state;
 
+#[cfg(feature = "embassy-time")]
 pub mod portamento;
 
 pub mod configuration;
 
+pub mod timestamp;
+
+pub mod beat_clock;
+
+#[cfg(feature = "embassy-time")]
+pub mod chord_cleanup;
+
+pub mod sysex;
+
+#[cfg(feature = "event_log")]
+pub mod event_log;
+
+pub mod dac;
+
+pub mod thumbwheel;
+
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
+
+#[cfg(all(test, feature = "embassy-time"))]
+mod mock_time;
+
+pub mod usb_midi;
+
+pub mod voice_history;
+
 /// re-export for the firmware crate
 pub mod voltage {
     pub use measurements::Voltage;
@@ -1,11 +1,36 @@
-use wmidi::{ControlFunction, MidiMessage};
+use crate::configuration::ControllerOutput;
+use crate::timestamp::NoteEventTimestamp;
+#[cfg(feature = "embassy-time")]
+use crate::timestamp::{EmbassyNow, NowProvider};
+use bitflags::bitflags;
+#[cfg(feature = "embassy-time")]
+use embassy_time::Duration;
+use measurements::Voltage;
+use wmidi::{Channel, ControlFunction, ControlValue, MidiMessage, Note, U7};
 
 mod activated_notes;
 pub use activated_notes::*;
 
+#[cfg(feature = "state_replay")]
+mod message_history;
+#[cfg(feature = "state_replay")]
+pub use message_history::*;
+
+mod pitch_bend;
+pub use pitch_bend::*;
+
 mod portamento;
 pub use portamento::*;
 
+mod staccato_detector;
+pub use staccato_detector::*;
+
+mod sustain;
+pub use sustain::*;
+
+mod tempo;
+pub use tempo::*;
+
 /// A straightforward representation of the MIDI messages the device has received.
 ///
 /// Related controllers are grouped together in structs of their own (see `Portamento` for example), as
@@ -25,17 +50,194 @@ pub struct MidiState {
     pub activated_notes: ActivatedNotes,
     /// Contains a representation of MIDI controls related to the Portamento effect.
     pub portamento: Portamento,
+    /// Contains a representation of MIDI controls related to Pitch Bend.
+    pub pitch_bend: PitchBend,
+    /// MIDI CC 101 and 100: the Registered Parameter Number (MSB, LSB) to which Data Entry applies.
+    pub registered_parameter: (u8, u8),
+    /// Contains a representation of the MIDI Sustain (i.e., damper pedal) control.
+    pub sustain: Sustain,
+    /// MIDI CC 80-83: General Purpose Controllers 5-8, treated as on/off switches (indexed from CC 80).
+    pub general_purpose_switches: [bool; 4],
+    /// The most recently received Program Change, if any, so that it can be restored (e.g., on power-up) or reported.
+    pub last_program: Option<u8>,
+    /// When the most recent NoteOn was received, or `None` if no notes are activated.
+    pub note_on_at: Option<NoteEventTimestamp>,
+    /// MIDI CC 94: Celeste (i.e., Detune) Depth, centered at [`DETUNE_CENTER`]; see [`detune`][Self::detune].
+    pub detune_depth: u8,
+    /// RPN 2: Channel Coarse Tuning, centered at [`COARSE_TUNING_CENTER`]; see [`transpose`][Self::transpose].
+    pub coarse_tuning: u8,
+    /// Channel Pressure (i.e., aftertouch), as a `u8` because [`U7`][wmidi::U7] doesn't implement `defmt::Format`; see
+    /// [`controller_voltage`][Self::controller_voltage].
+    pub channel_pressure: u8,
+    /// MIDI CC 1: Modulation Wheel, as a `u8` for the same reason.
+    pub modulation: u8,
+    /// MIDI CC 11: Expression, as a `u8` for the same reason. Defaults to full, per General MIDI.
+    pub expression: u8,
+    /// MIDI CC 2: Breath Controller, as a `u8` for the same reason.
+    pub breath: u8,
+    /// The tempo in beats per minute, or `None` if no Timing Clock has been received within [`CLOCK_TIMEOUT_MICROS`].
+    ///
+    /// Timing Clock arrives 24 times per beat, so it isn't tracked by [`update`][Self::update]; callers estimate the
+    /// tempo with a [`Tempo`] and record it here, so that a steady tempo changes nothing.
+    pub bpm: Option<f32>,
 }
 
-impl Default for MidiState {
-    fn default() -> Self {
-        Self {
-            activated_notes: ActivatedNotes::default(),
-            portamento: Portamento::default(),
+/// A lighter-weight snapshot of a [`MidiState`], holding only the fields most tasks care about; see
+/// [`MidiState::summary`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MidiStateSummary {
+    /// Holds a representation of notes which are currently activated.
+    pub activated_notes: ActivatedNotes,
+    /// MIDI CC 65: Portamento On/Off.
+    pub portamento_enabled: bool,
+    /// MIDI CC 5: Portamento Time, as a `u8` because [`ControlValue`] doesn't implement `defmt::Format`.
+    pub portamento_time: u8,
+    /// Pitch Bend Change, centered at zero; see [`PitchBend::value`].
+    pub pitch_bend: i16,
+}
+
+bitflags! {
+    /// Describes which aspects of [`MidiState`] were affected by a call to [`MidiState::update`], allowing callers to
+    /// react only to the changes they care about.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Operation: u16 {
+        /// A note was activated or released.
+        const NOTE_CHANGE = 1;
+        /// A Portamento control changed.
+        const PORTAMENTO_CHANGE = 1 << 1;
+        /// One of the general-purpose switches (MIDI CC 80-83) was set.
+        const SWITCH_CHANGE = 1 << 2;
+        /// An Active Sensing message was received, indicating that the connection to the sender is still alive.
+        const ACTIVE_SENSING = 1 << 3;
+        /// The selected program changed.
+        const PROGRAM_CHANGE = 1 << 4;
+        /// The Celeste (i.e., Detune) control changed.
+        const DETUNE_CHANGE = 1 << 5;
+        /// Pitch Bend, or its range, changed.
+        const PITCH_BEND_CHANGE = 1 << 6;
+        /// The sustain pedal went down or came up.
+        const SUSTAIN_CHANGE = 1 << 7;
+        /// Channel Pressure (i.e., aftertouch) changed.
+        const AFTERTOUCH_CHANGE = 1 << 8;
+        /// The Modulation Wheel (MIDI CC 1) changed.
+        const MODULATION_CHANGE = 1 << 9;
+        /// The Expression controller (MIDI CC 11) changed.
+        const EXPRESSION_CHANGE = 1 << 10;
+        /// The Breath Controller (MIDI CC 2) changed.
+        const BREATH_CHANGE = 1 << 11;
+        /// The tempo estimated from Timing Clock changed, or became unknown.
+        const TEMPO_CHANGE = 1 << 12;
+        /// All Notes Off (MIDI CC 123) was received, whether or not any notes were activated at the time.
+        const ALL_NOTES_OFF = 1 << 13;
+        /// Channel Coarse Tuning (i.e., transposition) changed.
+        const TUNING_CHANGE = 1 << 14;
+    }
+}
+
+impl Operation {
+    /// Returns `true` if any of the given `flags` are set, e.g., to check for a note change or a portamento change.
+    ///
+    /// Equivalent to [`intersects`][Self::intersects]; contrast with [`contains`][Self::contains], which requires that
+    /// all of the `flags` be set.
+    pub fn contains_any(&self, flags: Operation) -> bool {
+        !(*self & flags).is_empty()
+    }
+}
+
+impl From<&MidiMessage<'_>> for Operation {
+    /// Classifies a [`MidiMessage`] by the aspects of [`MidiState`] it addresses, without regard to any particular
+    /// state, i.e., the changes it can cause rather than the changes it does cause.
+    fn from(msg: &MidiMessage<'_>) -> Self {
+        match msg {
+            MidiMessage::ControlChange(_, control_function, _) => match *control_function {
+                ControlFunction::PORTAMENTO_TIME
+                | ControlFunction::PORTAMENTO_TIME_LSB
+                | ControlFunction::PORTAMENTO_ON_OFF
+                | ControlFunction::PORTAMENTO_CONTROL => Operation::PORTAMENTO_CHANGE,
+                // releasing the pedal releases the notes it sustained
+                ControlFunction::DAMPER_PEDAL => Operation::NOTE_CHANGE | Operation::SUSTAIN_CHANGE,
+                ControlFunction::ALL_NOTES_OFF => {
+                    Operation::NOTE_CHANGE | Operation::SUSTAIN_CHANGE | Operation::ALL_NOTES_OFF
+                }
+                ControlFunction::RESET_ALL_CONTROLLERS => {
+                    Operation::NOTE_CHANGE
+                        | Operation::PORTAMENTO_CHANGE
+                        | Operation::SWITCH_CHANGE
+                        | Operation::PITCH_BEND_CHANGE
+                        | Operation::SUSTAIN_CHANGE
+                        | Operation::AFTERTOUCH_CHANGE
+                        | Operation::MODULATION_CHANGE
+                        | Operation::EXPRESSION_CHANGE
+                        | Operation::BREATH_CHANGE
+                }
+                ControlFunction::GENERAL_PURPOSE_CONTROLLER_5
+                | ControlFunction::GENERAL_PURPOSE_CONTROLLER_6
+                | ControlFunction::GENERAL_PURPOSE_CONTROLLER_7
+                | ControlFunction::GENERAL_PURPOSE_CONTROLLER_8 => Operation::SWITCH_CHANGE,
+                ControlFunction::EFFECTS_4_DEPTH => Operation::DETUNE_CHANGE,
+                ControlFunction::MODULATION_WHEEL => Operation::MODULATION_CHANGE,
+                ControlFunction::EXPRESSION_CONTROLLER => Operation::EXPRESSION_CHANGE,
+                ControlFunction::BREATH_CONTROLLER => Operation::BREATH_CHANGE,
+                // Pitch Bend Sensitivity and Channel Coarse Tuning are the only Registered Parameters the device
+                // implements
+                ControlFunction::DATA_ENTRY_MSB => {
+                    Operation::PITCH_BEND_CHANGE | Operation::TUNING_CHANGE
+                }
+                _ => Operation::empty(),
+            },
+            MidiMessage::NoteOff(..) | MidiMessage::NoteOn(..) => Operation::NOTE_CHANGE,
+            MidiMessage::PitchBendChange(..) => Operation::PITCH_BEND_CHANGE,
+            MidiMessage::ProgramChange(..) => Operation::PROGRAM_CHANGE,
+            MidiMessage::ChannelPressure(..) => Operation::AFTERTOUCH_CHANGE,
+            MidiMessage::ActiveSensing => Operation::ACTIVE_SENSING,
+            MidiMessage::Reset => {
+                Operation::NOTE_CHANGE
+                    | Operation::PORTAMENTO_CHANGE
+                    | Operation::SWITCH_CHANGE
+                    | Operation::PROGRAM_CHANGE
+                    | Operation::DETUNE_CHANGE
+                    | Operation::PITCH_BEND_CHANGE
+                    | Operation::SUSTAIN_CHANGE
+                    | Operation::AFTERTOUCH_CHANGE
+                    | Operation::MODULATION_CHANGE
+                    | Operation::EXPRESSION_CHANGE
+                    | Operation::BREATH_CHANGE
+                    | Operation::TUNING_CHANGE
+            }
+            _ => Operation::empty(),
         }
     }
 }
 
+/// The voltage (in volts) produced at a controller's maximum unless configured otherwise, per the Moog Open System's
+/// 5 V bound; see [`MidiState::controller_voltage`].
+pub const DEFAULT_CONTROLLER_MAX_VOLTS: f64 = 5.0;
+
+/// The value of MIDI CC 11: Expression until one is received (and after Reset All Controllers), per General MIDI.
+const EXPRESSION_DEFAULT: u8 = 127;
+
+/// The number of programs (i.e., presets) the device implements; Program Changes beyond this range are ignored.
+const PROGRAM_CNT: u8 = 16;
+
+/// Control values at or above this threshold turn a switched (i.e., on/off) controller on.
+const SWITCH_ON_THRESHOLD: u8 = 64;
+
+/// The value of the Celeste (i.e., Detune) control which leaves pitch unaltered.
+pub const DETUNE_CENTER: u8 = 64;
+
+/// The value of Channel Coarse Tuning which leaves pitch unaltered.
+pub const COARSE_TUNING_CENTER: u8 = 64;
+
+/// RPN 0: Pitch Bend Sensitivity
+const RPN_PITCH_BEND_SENSITIVITY: (u8, u8) = (0, 0);
+
+/// RPN 2: Channel Coarse Tuning
+const RPN_COARSE_TUNING: (u8, u8) = (0, 2);
+
+/// The RPN Null Function, which deselects any Registered Parameter so that stray Data Entry has no effect.
+const RPN_NULL: (u8, u8) = (127, 127);
+
 /// Given data, returns the MIDI messages contained therein, filtering out errors.
 ///
 /// Data may contain one or more USB-MIDI Event Packets.
@@ -53,9 +255,352 @@ pub fn bytes_to_midi(data: &[u8]) -> impl Iterator<Item = MidiMessage<'_>> {
     })
 }
 
+/// Given a channel [`MidiMessage`], returns the USB-MIDI Event Packet that carries it; the inverse of [`bytes_to_midi`].
+///
+/// Returns `None` for messages which are not channel messages (e.g., System Exclusive), as their packets are formed
+/// differently.
+pub fn midi_to_bytes(msg: &MidiMessage) -> Option<[u8; 4]> {
+    msg.channel()?;
+    let mut packet = [0_u8; 4];
+    msg.copy_to_slice(&mut packet[1..]).ok()?;
+    // for channel messages, the Code Index Number (the lower nibble of the Packet Header) matches the upper nibble of
+    // the status byte; the Cable Number (the upper nibble of the Packet Header) is always 0 for this device
+    packet[0] = packet[1] >> 4;
+    Some(packet)
+}
+
+impl Default for MidiState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MidiState {
-    /// Updates the [`MidiState`] given a [`MidiMessage`].
-    pub fn update(&mut self, msg: MidiMessage) -> () {
+    /// Constructs a new [`MidiState`] reflecting that no MIDI has been received yet.
+    ///
+    /// Being `const`, this allows statics which hold state (e.g., an Embassy `Watch`) to be initialized at compile
+    /// time, so that readers never encounter an uninitialized value.
+    pub const fn new() -> Self {
+        Self {
+            activated_notes: ActivatedNotes::new(),
+            portamento: Portamento::new(),
+            pitch_bend: PitchBend::new(),
+            registered_parameter: RPN_NULL,
+            sustain: Sustain::new(),
+            general_purpose_switches: [false; 4],
+            last_program: None,
+            note_on_at: None,
+            detune_depth: DETUNE_CENTER,
+            coarse_tuning: COARSE_TUNING_CENTER,
+            channel_pressure: 0,
+            modulation: 0,
+            expression: EXPRESSION_DEFAULT,
+            breath: 0,
+            bpm: None,
+        }
+    }
+
+    /// Deactivates all notes, including any held by the sustain pedal, e.g., upon All Notes Off (MIDI CC 123) or loss of
+    /// connection to the sender.
+    pub fn all_notes_off(&mut self) {
+        self.activated_notes = ActivatedNotes::new();
+        self.sustain = Sustain::new();
+        self.note_on_at = None;
+    }
+
+    /// Returns the instrument to rest upon loss of connection to the sender, which can then no longer release the notes
+    /// (or return the controllers) it left held; see [`all_notes_off`][Self::all_notes_off] and
+    /// [`reset_performance_controllers`][Self::reset_performance_controllers].
+    pub fn lose_connection(&mut self) {
+        self.reset_performance_controllers();
+        self.all_notes_off();
+    }
+
+    /// Restores performance controllers to their defaults, as called for by CC 121: Reset All Controllers.
+    ///
+    /// Settings (e.g., Pitch Bend Sensitivity) and effects controllers (e.g., Celeste) are left as they are, but every
+    /// Portamento control, Portamento Time included, is restored; see [`Portamento::reset`]. Releasing the sustain pedal
+    /// releases the notes it sustained.
+    pub fn reset_controllers(&mut self) {
+        self.reset_performance_controllers();
+        self.portamento.reset();
+        self.general_purpose_switches = [false; 4];
+        self.registered_parameter = RPN_NULL;
+    }
+
+    /// Restores the controllers a performer moves while playing (Sustain, Pitch Bend, Channel Pressure, Modulation,
+    /// Breath, and Expression) to their defaults, e.g., upon loss of connection to the sender, which can then no longer
+    /// return them to rest.
+    ///
+    /// Unlike [`reset_controllers`][Self::reset_controllers], this leaves the Portamento controls, the general-purpose
+    /// switches, and the selected Registered Parameter as they are.
+    pub fn reset_performance_controllers(&mut self) {
+        self.pitch_bend.center();
+        self.channel_pressure = 0;
+        self.modulation = 0;
+        self.expression = EXPRESSION_DEFAULT;
+        self.breath = 0;
+        self.release_sustain();
+    }
+
+    /// Disengages the sustain pedal, releasing the notes it sustained.
+    fn release_sustain(&mut self) {
+        if self.sustain.is_engaged() {
+            self.activated_notes.subtract(&self.sustain.disengage());
+            if self.activated_notes.is_empty() {
+                self.note_on_at = None;
+            }
+        }
+    }
+
+    /// Returns a [`MidiStateSummary`] of the state, for tasks which don't need all of it.
+    pub fn summary(&self) -> MidiStateSummary {
+        MidiStateSummary {
+            activated_notes: self.activated_notes,
+            portamento_enabled: self.portamento.is_enabled(),
+            portamento_time: u8::from(self.portamento.time()),
+            pitch_bend: self.pitch_bend.value(),
+        }
+    }
+
+    /// Returns `true` if any note is activated or the sustain pedal is engaged.
+    ///
+    /// Sustain counts even while no notes are activated, as the performer is still in the midst of playing (e.g., a
+    /// sustained note may be re-struck), much as a tape deck between songs is still playing.
+    pub fn is_playing(&self) -> bool {
+        !self.activated_notes.is_empty() || self.sustain.is_engaged()
+    }
+
+    /// Returns how long it has been since the most recent NoteOn, or `None` if no notes are activated.
+    #[cfg(feature = "embassy-time")]
+    pub fn note_duration(&self) -> Option<Duration> {
+        self.note_on_at
+            .map(|timestamp| Duration::from_micros(EmbassyNow::now().micros_since(timestamp)))
+    }
+
+    /// Returns the fine-tuning offset requested via the Celeste (i.e., Detune) control, in semitones.
+    ///
+    /// Ranges from -1.0 (CC value 0) through 0.0 ([`DETUNE_CENTER`]) to +1.0 (CC value 127). As there are more values
+    /// below the center than above it, each step up is slightly larger than each step down.
+    pub fn detune(&self) -> f64 {
+        let depth = self.detune_depth as f64 - DETUNE_CENTER as f64;
+        if depth < 0.0 {
+            depth / DETUNE_CENTER as f64
+        } else {
+            depth / (u8::from(ControlValue::MAX) - DETUNE_CENTER) as f64
+        }
+    }
+
+    /// Returns the transposition requested via Channel Coarse Tuning (RPN 2), in semitones, from -64 (value 0) through 0
+    /// ([`COARSE_TUNING_CENTER`]) to +63 (value 127).
+    pub fn transpose(&self) -> i8 {
+        self.coarse_tuning.min(u8::from(U7::MAX)) as i8 - COARSE_TUNING_CENTER as i8
+    }
+
+    /// Sets the transposition in semitones, as Channel Coarse Tuning would, e.g., from a control on the device itself;
+    /// values beyond the range of [`transpose`][Self::transpose] are clamped.
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.coarse_tuning = (semitones.clamp(-64, 63) + COARSE_TUNING_CENTER as i8) as u8;
+    }
+
+    /// Returns a [`Voltage`] proportional to the controller selected by `output`, from 0 V at the controller's minimum
+    /// to `max_voltage` at its maximum; see [`DEFAULT_CONTROLLER_MAX_VOLTS`]. [`ControllerOutput::Disabled`] always
+    /// produces 0 V.
+    pub fn controller_voltage(&self, output: ControllerOutput, max_voltage: Voltage) -> Voltage {
+        let value = match output {
+            ControllerOutput::Disabled => 0,
+            ControllerOutput::Modulation => self.modulation,
+            ControllerOutput::Aftertouch => self.channel_pressure,
+            ControllerOutput::Expression => self.expression,
+            ControllerOutput::Breath => self.breath,
+        };
+        max_voltage * (value as f64 / u8::from(U7::MAX) as f64)
+    }
+
+    /// Returns the notes activated on the given [`Channel`], e.g., for routing each channel to a different output.
+    ///
+    /// [`activated_notes`][Self::activated_notes] holds the notes of every channel, i.e., omni behavior.
+    pub fn notes_on_channel(&self, channel: Channel) -> ActivatedNotes {
+        self.activated_notes.on_channel(channel)
+    }
+
+    /// Returns the number of distinct MIDI channels on which notes are currently activated.
+    ///
+    /// Useful, e.g., for detecting that the device is receiving polyphonic MIDI from several sources.
+    pub fn channel_count(&self) -> u8 {
+        self.activated_notes.channel_count()
+    }
+
+    /// Returns Control Change messages which, sent to a host, convey every controller whose value differs from its
+    /// default, e.g., to bring a DAW up to date after it reconnects.
+    ///
+    /// Performance controllers, which are reset on disconnect (see
+    /// [`reset_performance_controllers`][Self::reset_performance_controllers]), are omitted, as are Registered Parameters,
+    /// which take several messages to set. Messages are sent on channel 1, as the device doesn't track the channel on
+    /// which each controller was received.
+    pub fn non_default_cc_messages(&self) -> impl Iterator<Item = MidiMessage<'static>> {
+        let switches = self
+            .general_purpose_switches
+            .iter()
+            .enumerate()
+            .filter(|&(_, &on)| on)
+            .map(|(i, _)| {
+                (
+                    ControlFunction::from(ControlValue::from_u8_lossy(
+                        u8::from(ControlFunction::GENERAL_PURPOSE_CONTROLLER_5) + i as u8,
+                    )),
+                    ControlValue::MAX,
+                )
+            });
+
+        [
+            (self.portamento.time() != ControlValue::MIN)
+                .then_some((ControlFunction::PORTAMENTO_TIME, self.portamento.time())),
+            self.portamento
+                .time_lsb()
+                .map(|lsb| (ControlFunction::PORTAMENTO_TIME_LSB, lsb)),
+            (!self.portamento.is_enabled())
+                .then_some((ControlFunction::PORTAMENTO_ON_OFF, ControlValue::MIN)),
+            (self.detune_depth != DETUNE_CENTER).then_some((
+                ControlFunction::EFFECTS_4_DEPTH,
+                ControlValue::from_u8_lossy(self.detune_depth),
+            )),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(switches)
+        .map(|(control_function, control_value)| {
+            MidiMessage::ControlChange(Channel::Ch1, control_function, control_value)
+        })
+    }
+
+    /// Compares `self` to a `previous` snapshot, returning an [`Operation`] describing what changed between the two.
+    ///
+    /// Useful when [`update`][Self::update] is called elsewhere, e.g., for a task which receives [`MidiState`] through
+    /// an Embassy `Watch`, and which may have missed intermediate states. Note that [`Operation::ACTIVE_SENSING`] is
+    /// never set, as Active Sensing leaves no trace in the state.
+    pub fn diff(&self, previous: &MidiState) -> Operation {
+        let mut operation = Operation::empty();
+        operation.set(
+            Operation::NOTE_CHANGE,
+            self.activated_notes != previous.activated_notes,
+        );
+        operation.set(
+            Operation::PORTAMENTO_CHANGE,
+            self.portamento != previous.portamento,
+        );
+        operation.set(
+            Operation::SWITCH_CHANGE,
+            self.general_purpose_switches != previous.general_purpose_switches,
+        );
+        operation.set(
+            Operation::PROGRAM_CHANGE,
+            self.last_program != previous.last_program,
+        );
+        operation.set(
+            Operation::DETUNE_CHANGE,
+            self.detune_depth != previous.detune_depth,
+        );
+        operation.set(
+            Operation::PITCH_BEND_CHANGE,
+            self.pitch_bend != previous.pitch_bend,
+        );
+        operation.set(
+            Operation::TUNING_CHANGE,
+            self.coarse_tuning != previous.coarse_tuning,
+        );
+        operation.set(
+            Operation::SUSTAIN_CHANGE,
+            self.sustain.is_engaged() != previous.sustain.is_engaged(),
+        );
+        operation.set(
+            Operation::AFTERTOUCH_CHANGE,
+            self.channel_pressure != previous.channel_pressure,
+        );
+        operation.set(
+            Operation::MODULATION_CHANGE,
+            self.modulation != previous.modulation,
+        );
+        operation.set(
+            Operation::EXPRESSION_CHANGE,
+            self.expression != previous.expression,
+        );
+        operation.set(Operation::BREATH_CHANGE, self.breath != previous.breath);
+        operation.set(Operation::TEMPO_CHANGE, self.bpm != previous.bpm);
+        operation
+    }
+
+    /// Activates a [`Note`] on the given [`Channel`] as a NoteOn received at `timestamp` would.
+    ///
+    /// [`apply`][Self::apply] does this for each NoteOn as it is received; this method is for callers which hold note
+    /// events back before applying them, e.g., to batch them for [chord cleanup][crate::configuration::ChordCleanup].
+    pub fn press_note_at(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: U7,
+        timestamp: NoteEventTimestamp,
+    ) {
+        self.sustain.press(channel, note);
+        let len = self.activated_notes.len();
+        #[cfg(feature = "counted_notes")]
+        self.activated_notes
+            .add_note_on_counted_at(channel, note, velocity, timestamp);
+        #[cfg(not(feature = "counted_notes"))]
+        self.activated_notes
+            .add_note_on_at(channel, note, velocity, timestamp);
+        // a NoteOn which activates nothing (e.g., one beyond capacity) isn't the most recent as far as the state knows
+        if self.activated_notes.len() > len {
+            self.note_on_at = Some(timestamp);
+        }
+    }
+
+    /// Releases a [`Note`] on the given [`Channel`] as a NoteOff would, unless the sustain pedal holds it; the
+    /// counterpart to [`press_note_at`][Self::press_note_at].
+    ///
+    /// With the `counted_notes` feature, a NoteOff matching one of several NoteOns for the note only counts the release;
+    /// see [`ActivatedNotes::add_note_on_counted_at`].
+    pub fn release_note(&mut self, channel: Channel, note: Note) {
+        // the note remains pressed, so there is nothing for the pedal to hold
+        #[cfg(feature = "counted_notes")]
+        if self.activated_notes.count_of(channel, note) > 1 {
+            self.activated_notes.remove_note_off_counted(channel, note);
+            return;
+        }
+        let is_sustained = self.sustain.hold(channel, note);
+        if !is_sustained {
+            self.activated_notes.remove_note_off(channel, note);
+        }
+        if self.activated_notes.is_empty() {
+            self.note_on_at = None;
+        }
+    }
+
+    /// Updates the [`MidiState`] given a [`MidiMessage`], returning an [`Operation`] describing what was affected.
+    ///
+    /// The [`Operation`] is the intersection of what the message addresses (see [`Operation::from`]) and what actually
+    /// changed (see [`diff`][Self::diff]); e.g., a NoteOff for a note held by the sustain pedal changes nothing.
+    pub fn update(&mut self, msg: MidiMessage) -> Operation {
+        let previous = *self;
+        let operation = Operation::from(&msg);
+        self.apply(msg);
+        // Active Sensing and All Notes Off leave no trace in the state, so they are exempt from the diff
+        operation & (self.diff(&previous) | Operation::ACTIVE_SENSING | Operation::ALL_NOTES_OFF)
+    }
+
+    /// Updates the [`MidiState`] given a [`MidiMessage`]. See [`update`][Self::update] for a version of this method
+    /// which reports what was affected.
+    pub fn apply(&mut self, msg: MidiMessage) {
+        #[cfg(feature = "embassy-time")]
+        self.apply_at(msg, EmbassyNow::now());
+        // without `embassy-time`, the state has no clock, so activations are recorded at the epoch
+        #[cfg(not(feature = "embassy-time"))]
+        self.apply_at(msg, NoteEventTimestamp::default());
+    }
+
+    /// Updates the [`MidiState`] given a [`MidiMessage`] received at `timestamp`, e.g., when replaying messages recorded
+    /// earlier, so that notes are activated as of their receipt rather than their replay.
+    pub fn apply_at(&mut self, msg: MidiMessage, timestamp: NoteEventTimestamp) {
         match msg {
             MidiMessage::ControlChange(_channel, control_function, control_value) => {
                 match control_function {
@@ -68,6 +613,151 @@ impl MidiState {
                             u8::from(control_value)
                         );
                     }
+                    ControlFunction::PORTAMENTO_TIME_LSB => {
+                        self.portamento.set_time_lsb(control_value);
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Portamento Time (LSB) Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            u8::from(control_value)
+                        );
+                    }
+                    ControlFunction::PORTAMENTO_ON_OFF => {
+                        self.portamento
+                            .set_enabled(u8::from(control_value) >= SWITCH_ON_THRESHOLD);
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Portamento On/Off Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            u8::from(control_value)
+                        );
+                    }
+                    ControlFunction::PORTAMENTO_CONTROL => {
+                        self.portamento
+                            .set_origin_override(Note::from(control_value));
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Portamento Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            u8::from(control_value)
+                        );
+                    }
+                    ControlFunction::DAMPER_PEDAL => {
+                        if u8::from(control_value) >= SWITCH_ON_THRESHOLD {
+                            self.sustain.engage();
+                        } else {
+                            self.release_sustain();
+                        }
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Sustain Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            u8::from(control_value)
+                        );
+                    }
+                    ControlFunction::GENERAL_PURPOSE_CONTROLLER_5
+                    | ControlFunction::GENERAL_PURPOSE_CONTROLLER_6
+                    | ControlFunction::GENERAL_PURPOSE_CONTROLLER_7
+                    | ControlFunction::GENERAL_PURPOSE_CONTROLLER_8 => {
+                        let index = usize::from(
+                            u8::from(control_function)
+                                - u8::from(ControlFunction::GENERAL_PURPOSE_CONTROLLER_5),
+                        );
+                        self.general_purpose_switches[index] =
+                            u8::from(control_value) >= SWITCH_ON_THRESHOLD;
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received General Purpose Switch Control Change: channel {}, controller {}, value: {}",
+                            _channel.number(),
+                            u8::from(control_function),
+                            u8::from(control_value)
+                        );
+                    }
+                    ControlFunction::ALL_NOTES_OFF => {
+                        self.all_notes_off();
+                        #[cfg(feature = "defmt")]
+                        defmt::info!("Received All Notes Off: channel {}", _channel.number());
+                    }
+                    ControlFunction::RESET_ALL_CONTROLLERS => {
+                        self.reset_controllers();
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Reset All Controllers: channel {}",
+                            _channel.number()
+                        );
+                    }
+                    ControlFunction::REGISTERED_PARAMETER_NUMBER_MSB => {
+                        self.registered_parameter.0 = u8::from(control_value);
+                    }
+                    ControlFunction::REGISTERED_PARAMETER_NUMBER_LSB => {
+                        self.registered_parameter.1 = u8::from(control_value);
+                    }
+                    // selecting a Non-Registered Parameter means subsequent Data Entry isn't meant for an RPN
+                    ControlFunction::NON_REGISTERED_PARAMETER_NUMBER_MSB
+                    | ControlFunction::NON_REGISTERED_PARAMETER_NUMBER_LSB => {
+                        self.registered_parameter = RPN_NULL;
+                    }
+                    ControlFunction::DATA_ENTRY_MSB => {
+                        if self.registered_parameter == RPN_PITCH_BEND_SENSITIVITY {
+                            self.pitch_bend.set_range_semitones(u8::from(control_value));
+                            #[cfg(feature = "defmt")]
+                            defmt::info!(
+                                "Received Pitch Bend Sensitivity: channel {}, semitones: {}",
+                                _channel.number(),
+                                u8::from(control_value)
+                            );
+                        } else if self.registered_parameter == RPN_COARSE_TUNING {
+                            self.coarse_tuning = u8::from(control_value);
+                            #[cfg(feature = "defmt")]
+                            defmt::info!(
+                                "Received Channel Coarse Tuning: channel {}, value: {}",
+                                _channel.number(),
+                                u8::from(control_value)
+                            );
+                        } else {
+                            #[cfg(feature = "defmt")]
+                            defmt::info!(
+                                "Ignoring Data Entry for unsupported parameter on channel {}",
+                                _channel.number()
+                            );
+                        }
+                    }
+                    ControlFunction::MODULATION_WHEEL => {
+                        self.modulation = u8::from(control_value);
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Modulation Wheel Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            self.modulation
+                        );
+                    }
+                    ControlFunction::EXPRESSION_CONTROLLER => {
+                        self.expression = u8::from(control_value);
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Expression Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            self.expression
+                        );
+                    }
+                    ControlFunction::BREATH_CONTROLLER => {
+                        self.breath = u8::from(control_value);
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Breath Controller Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            self.breath
+                        );
+                    }
+                    ControlFunction::EFFECTS_4_DEPTH => {
+                        self.detune_depth = u8::from(control_value);
+                        #[cfg(feature = "defmt")]
+                        defmt::info!(
+                            "Received Celeste (Detune) Depth Control Change: channel {}, value: {}",
+                            _channel.number(),
+                            u8::from(control_value)
+                        );
+                    }
                     _ => {
                         #[cfg(feature = "defmt")]
                         defmt::info!(
@@ -78,26 +768,78 @@ impl MidiState {
                     }
                 }
             }
-            MidiMessage::NoteOff(_channel, note, _velocity) => {
-                self.activated_notes.remove(note);
+            MidiMessage::NoteOff(channel, note, _velocity) => {
+                self.release_note(channel, note);
+                self.portamento.note_off();
                 #[cfg(feature = "defmt")]
                 defmt::info!(
                     "Received NoteOff: channel {}, note {}, velocity: {}",
-                    _channel.number(),
+                    channel.number(),
                     note.to_str(),
                     u8::from(_velocity)
                 );
             }
-            MidiMessage::NoteOn(_channel, note, _velocity) => {
-                self.activated_notes.add(note);
+            MidiMessage::NoteOn(channel, note, velocity) => {
+                self.press_note_at(channel, note, velocity, timestamp);
+                self.portamento.note_on();
                 #[cfg(feature = "defmt")]
                 defmt::info!(
                     "Received NoteOn: channel {}, note {}, velocity: {}",
-                    _channel.number(),
+                    channel.number(),
                     note.to_str(),
-                    u8::from(_velocity)
+                    u8::from(velocity)
+                );
+            }
+            MidiMessage::PitchBendChange(_channel, bend) => {
+                self.pitch_bend.set_value(bend);
+                #[cfg(feature = "defmt")]
+                defmt::info!(
+                    "Received Pitch Bend Change: channel {}, value: {}",
+                    _channel.number(),
+                    self.pitch_bend.value()
                 );
             }
+            MidiMessage::ChannelPressure(_channel, pressure) => {
+                self.channel_pressure = u8::from(pressure);
+                #[cfg(feature = "defmt")]
+                defmt::info!(
+                    "Received Channel Pressure: channel {}, value: {}",
+                    _channel.number(),
+                    self.channel_pressure
+                );
+            }
+            MidiMessage::ProgramChange(_channel, program) => {
+                let program = u8::from(program);
+                if program < PROGRAM_CNT {
+                    self.last_program = Some(program);
+                    #[cfg(feature = "defmt")]
+                    defmt::info!(
+                        "Received Program Change: channel {}, program {}",
+                        _channel.number(),
+                        program
+                    );
+                } else {
+                    #[cfg(feature = "defmt")]
+                    defmt::warn!(
+                        "Ignoring Program Change to unimplemented program {} on channel {}",
+                        program,
+                        _channel.number()
+                    );
+                }
+            }
+            // nothing to record, but the message is supported (see `Operation::ACTIVE_SENSING`)
+            MidiMessage::ActiveSensing => {}
+            // supported, but tracked by the caller (see `MidiState::bpm`)
+            MidiMessage::TimingClock => {}
+            MidiMessage::Reset => {
+                // the tempo belongs to the clock, which a Reset doesn't stop
+                *self = Self {
+                    bpm: self.bpm,
+                    ..Self::new()
+                };
+                #[cfg(feature = "defmt")]
+                defmt::info!("Received Reset; restoring default state");
+            }
             _ => {
                 #[cfg(feature = "defmt")]
                 {
@@ -107,5 +849,1312 @@ impl MidiState {
                 }
             }
         };
+        if self.activated_notes.is_empty() {
+            self.note_on_at = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmidi::{Channel, Note, U7, U14};
+
+    fn control_change(control_function: ControlFunction, value: u8) -> MidiMessage<'static> {
+        MidiMessage::ControlChange(Channel::Ch1, control_function, U7::from_u8_lossy(value))
+    }
+
+    #[test]
+    fn note_change() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::NOTE_CHANGE,
+            state.update(MidiMessage::NoteOn(
+                Channel::Ch1,
+                Note::C4,
+                U7::from_u8_lossy(100)
+            )),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::NOTE_CHANGE,
+            state.update(MidiMessage::NoteOff(
+                Channel::Ch1,
+                Note::C4,
+                U7::from_u8_lossy(0)
+            )),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn sustain() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::C4,
+            U7::from_u8_lossy(100),
+        ));
+        assert_eq!(
+            Operation::SUSTAIN_CHANGE,
+            state.update(control_change(ControlFunction::DAMPER_PEDAL, 127)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::empty(),
+            state.update(control_change(ControlFunction::DAMPER_PEDAL, 127)),
+            "Expected a repeated pedal down to change nothing"
+        );
+        state.update(MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::E4,
+            U7::from_u8_lossy(100),
+        ));
+
+        assert_eq!(
+            Operation::empty(),
+            state.update(MidiMessage::NoteOff(
+                Channel::Ch1,
+                Note::C4,
+                U7::from_u8_lossy(0)
+            )),
+            "Expected note pressed before the pedal to be sustained"
+        );
+        assert_eq!(
+            Operation::empty(),
+            state.update(MidiMessage::NoteOff(
+                Channel::Ch1,
+                Note::E4,
+                U7::from_u8_lossy(0)
+            )),
+            "Expected note pressed after the pedal to be sustained as well"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4, Note::E4]),
+            "Expected both sustained notes to remain activated"
+        );
+
+        assert_eq!(
+            Operation::NOTE_CHANGE | Operation::SUSTAIN_CHANGE,
+            state.update(control_change(ControlFunction::DAMPER_PEDAL, 0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0,
+            state.activated_notes.iter().count(),
+            "Expected sustained notes to be released with the pedal"
+        );
+    }
+
+    #[test]
+    fn same_note_on_two_channels() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(MidiMessage::NoteOn(Channel::Ch2, Note::C4, U7::MAX));
+        assert_eq!(2, state.channel_count(), "Expected left but got right");
+
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 0));
+        assert_eq!(
+            2,
+            state.channel_count(),
+            "Expected re-pressing C4 on channel 1 to keep it activated past the pedal; expected left but got right"
+        );
+
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        assert!(
+            state.notes_on_channel(Channel::Ch1).iter().next().is_none(),
+            "Expected NoteOff on channel 1 to release C4 there"
+        );
+        assert!(
+            state.notes_on_channel(Channel::Ch2).iter().eq([Note::C4]),
+            "Expected C4 to remain activated on channel 2"
+        );
+    }
+
+    #[cfg(feature = "counted_notes")]
+    #[test]
+    fn repeated_note_on() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+
+        assert_eq!(
+            Operation::empty(),
+            state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN)),
+            "Expected the first NoteOff to leave the note activated"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4]),
+            "Expected the note to remain activated until every NoteOn is matched"
+        );
+
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        assert!(
+            state.activated_notes.iter().eq([Note::C4]),
+            "Expected the final NoteOff to be held by the pedal"
+        );
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 0));
+        assert!(
+            state.activated_notes.is_empty(),
+            "Expected the note to be released with the pedal"
+        );
+    }
+
+    #[test]
+    fn portamento_change() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::PORTAMENTO_CHANGE,
+            state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            U7::from_u8_lossy(100),
+            state.portamento.time(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn detune() {
+        let mut state = MidiState::default();
+        assert_eq!(0.0, state.detune(), "Expected left but got right");
+
+        assert_eq!(
+            Operation::DETUNE_CHANGE,
+            state.update(control_change(ControlFunction::EFFECTS_4_DEPTH, 0)),
+            "Expected left but got right"
+        );
+        assert_eq!(-1.0, state.detune(), "Expected left but got right");
+
+        state.update(control_change(ControlFunction::EFFECTS_4_DEPTH, 32));
+        assert_eq!(-0.5, state.detune(), "Expected left but got right");
+
+        state.update(control_change(ControlFunction::EFFECTS_4_DEPTH, 127));
+        assert_eq!(1.0, state.detune(), "Expected left but got right");
+    }
+
+    #[test]
+    fn pitch_bend() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::PITCH_BEND_CHANGE,
+            state.update(MidiMessage::PitchBendChange(
+                Channel::Ch1,
+                U14::try_from(0x3FFF).unwrap()
+            )),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            8191,
+            state.pitch_bend.value(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn pitch_bend_sensitivity() {
+        let packet = [
+            0x0B, 0xB0, 101, 0, // CC 101: RPN (MSB)
+            0x0B, 0xB0, 100, 0, // CC 100: RPN (LSB)
+            0x0B, 0xB0, 6, 12, // CC 6: Data Entry (MSB)
+            0x0B, 0xB0, 101, 127, // CC 101: RPN Null (MSB)
+            0x0B, 0xB0, 100, 127, // CC 100: RPN Null (LSB)
+        ];
+        let mut state = MidiState::default();
+        let operation = bytes_to_midi(&packet).fold(Operation::empty(), |operation, msg| {
+            operation | state.update(msg)
+        });
+        assert_eq!(
+            Operation::PITCH_BEND_CHANGE,
+            operation,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            12,
+            state.pitch_bend.range_semitones(),
+            "Expected left but got right"
+        );
+
+        assert_eq!(
+            Operation::empty(),
+            state.update(control_change(ControlFunction::DATA_ENTRY_MSB, 24)),
+            "Expected Data Entry to be ignored once the RPN is deselected; expected left but got right"
+        );
+        assert_eq!(
+            12,
+            state.pitch_bend.range_semitones(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn coarse_tuning() {
+        let mut state = MidiState::default();
+        assert_eq!(0, state.transpose(), "Expected left but got right");
+
+        state.update(control_change(
+            ControlFunction::REGISTERED_PARAMETER_NUMBER_MSB,
+            0,
+        ));
+        state.update(control_change(
+            ControlFunction::REGISTERED_PARAMETER_NUMBER_LSB,
+            2,
+        ));
+        assert_eq!(
+            Operation::TUNING_CHANGE,
+            state.update(control_change(ControlFunction::DATA_ENTRY_MSB, 52)),
+            "Expected left but got right"
+        );
+        assert_eq!(-12, state.transpose(), "Expected left but got right");
+        assert_eq!(
+            DEFAULT_PITCH_BEND_RANGE,
+            state.pitch_bend.range_semitones(),
+            "Expected Pitch Bend Sensitivity to be left alone; expected left but got right"
+        );
+
+        state.set_transpose(i8::MAX);
+        assert_eq!(
+            (127, 63),
+            (state.coarse_tuning, state.transpose()),
+            "Expected the transposition to be clamped; expected left but got right"
+        );
+        state.set_transpose(i8::MIN);
+        assert_eq!(-64, state.transpose(), "Expected left but got right");
+    }
+
+    #[test]
+    fn nrpn_deselects_rpn() {
+        let mut state = MidiState::default();
+        state.update(control_change(
+            ControlFunction::REGISTERED_PARAMETER_NUMBER_MSB,
+            0,
+        ));
+        state.update(control_change(
+            ControlFunction::REGISTERED_PARAMETER_NUMBER_LSB,
+            0,
+        ));
+        state.update(control_change(
+            ControlFunction::NON_REGISTERED_PARAMETER_NUMBER_MSB,
+            0,
+        ));
+        state.update(control_change(ControlFunction::DATA_ENTRY_MSB, 12));
+        assert_eq!(
+            DEFAULT_PITCH_BEND_RANGE,
+            state.pitch_bend.range_semitones(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn reset_performance_controllers() {
+        let mut state = MidiState::default();
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100));
+        state.update(control_change(ControlFunction::PORTAMENTO_ON_OFF, 0));
+        state.update(control_change(
+            ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+            127,
+        ));
+        state.update(control_change(ControlFunction::MODULATION_WHEEL, 127));
+        state.update(control_change(ControlFunction::EXPRESSION_CONTROLLER, 0));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(MidiMessage::PitchBendChange(Channel::Ch1, U14::MIN));
+
+        state.reset_performance_controllers();
+        assert_eq!(
+            (0, EXPRESSION_DEFAULT, 0),
+            (state.modulation, state.expression, state.pitch_bend.value()),
+            "Expected performance controllers to be reset; expected left but got right"
+        );
+        assert!(
+            !state.sustain.is_engaged(),
+            "Expected sustain to be disengaged"
+        );
+        assert_eq!(
+            (U7::from_u8_lossy(100), false),
+            (state.portamento.time(), state.portamento.is_enabled()),
+            "Expected the Portamento controls to be left alone; expected left but got right"
+        );
+        assert_eq!(
+            [true, false, false, false],
+            state.general_purpose_switches,
+            "Expected the switches to be left alone; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn reset_all_controllers() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::E4, U7::MAX));
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100));
+        state.update(control_change(ControlFunction::PORTAMENTO_ON_OFF, 0));
+        state.update(control_change(
+            ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+            127,
+        ));
+        state.update(control_change(ControlFunction::EFFECTS_4_DEPTH, 0));
+        state.update(MidiMessage::PitchBendChange(Channel::Ch1, U14::MIN));
+        state.update(MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX));
+
+        assert_eq!(
+            Operation::NOTE_CHANGE
+                | Operation::PORTAMENTO_CHANGE
+                | Operation::SWITCH_CHANGE
+                | Operation::PITCH_BEND_CHANGE
+                | Operation::SUSTAIN_CHANGE
+                | Operation::AFTERTOUCH_CHANGE,
+            state.update(control_change(ControlFunction::RESET_ALL_CONTROLLERS, 0)),
+            "Expected left but got right"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::E4]),
+            "Expected only the sustained note to be released"
+        );
+        assert!(
+            !state.sustain.is_engaged(),
+            "Expected sustain to be disengaged"
+        );
+        assert_eq!(
+            Portamento::default(),
+            state.portamento,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            [false; 4], state.general_purpose_switches,
+            "Expected left but got right"
+        );
+        assert_eq!(0, state.pitch_bend.value(), "Expected left but got right");
+        assert_eq!(
+            -1.0,
+            state.detune(),
+            "Expected effects controllers to be left alone; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn is_playing() {
+        let mut state = MidiState::default();
+        assert!(!state.is_playing(), "Expected not to be playing initially");
+
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        assert!(state.is_playing(), "Expected to be playing a note");
+
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 0));
+        assert!(
+            !state.is_playing(),
+            "Expected playing to stop upon release of the pedal"
+        );
+
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        assert!(
+            state.is_playing(),
+            "Expected an engaged pedal to count as playing even without notes"
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100));
+        state.update(control_change(ControlFunction::PORTAMENTO_ON_OFF, 0));
+        state.update(MidiMessage::PitchBendChange(
+            Channel::Ch1,
+            wmidi::U14::try_from(0x3000).unwrap(),
+        ));
+
+        assert_eq!(
+            MidiStateSummary {
+                activated_notes: state.activated_notes,
+                portamento_enabled: false,
+                portamento_time: 100,
+                pitch_bend: 0x1000,
+            },
+            state.summary(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn non_default_cc_messages() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            0,
+            state.non_default_cc_messages().count(),
+            "Expected no messages for the default state; expected left but got right"
+        );
+
+        let messages = [
+            control_change(ControlFunction::PORTAMENTO_TIME, 3),
+            control_change(ControlFunction::PORTAMENTO_ON_OFF, 0),
+            control_change(ControlFunction::EFFECTS_4_DEPTH, 100),
+            control_change(ControlFunction::GENERAL_PURPOSE_CONTROLLER_6, 127),
+        ];
+        messages.iter().for_each(|msg| {
+            state.update(msg.clone());
+        });
+        // not a controller the host needs to be reminded of
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+
+        assert!(
+            state.non_default_cc_messages().eq(messages),
+            "Expected a message for each non-default controller"
+        );
+
+        let mut replayed = MidiState::default();
+        state.non_default_cc_messages().for_each(|msg| {
+            replayed.update(msg);
+        });
+        assert_eq!(
+            Operation::empty(),
+            replayed.diff(&MidiState {
+                sustain: replayed.sustain,
+                ..state
+            }),
+            "Expected the messages to reproduce the state; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn portamento_time_lsb() {
+        let packet = [
+            0x0B, 0xB0, 5, 3, // CC 5: Portamento Time
+            0x0B, 0xB0, 37, 64, // CC 37: Portamento Time (LSB)
+        ];
+        let mut state = MidiState::default();
+        let operation = bytes_to_midi(&packet).fold(Operation::empty(), |operation, msg| {
+            operation | state.update(msg)
+        });
+        assert_eq!(
+            Operation::PORTAMENTO_CHANGE,
+            operation,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            U7::from_u8_lossy(3),
+            state.portamento.time(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(U7::from_u8_lossy(64)),
+            state.portamento.time_lsb(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            3 << 7 | 64,
+            state.portamento.high_res_time(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn portamento_on_off() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::PORTAMENTO_CHANGE,
+            state.update(control_change(ControlFunction::PORTAMENTO_ON_OFF, 0)),
+            "Expected left but got right"
+        );
+        assert!(
+            !state.portamento.is_enabled(),
+            "Expected Portamento to be disabled"
+        );
+        assert_eq!(
+            Operation::PORTAMENTO_CHANGE,
+            state.update(control_change(ControlFunction::PORTAMENTO_ON_OFF, 127)),
+            "Expected left but got right"
+        );
+        assert!(
+            state.portamento.is_enabled(),
+            "Expected Portamento to be enabled"
+        );
+    }
+
+    #[test]
+    fn portamento_control() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::PORTAMENTO_CHANGE,
+            state.update(control_change(
+                ControlFunction::PORTAMENTO_CONTROL,
+                u8::from(Note::C3)
+            )),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            state.portamento.glide_origin(),
+            "Expected CC 84 to await the next NoteOn; expected left but got right"
+        );
+
+        state.update(MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::E4,
+            U7::from_u8_lossy(100),
+        ));
+        assert_eq!(
+            Some(Note::C3),
+            state.portamento.glide_origin(),
+            "Expected left but got right"
+        );
+
+        state.update(MidiMessage::NoteOff(
+            Channel::Ch1,
+            Note::E4,
+            U7::from_u8_lossy(0),
+        ));
+        assert_eq!(
+            None,
+            state.portamento.glide_origin(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn switch_change() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::SWITCH_CHANGE,
+            state.update(control_change(
+                ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+                64
+            )),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::SWITCH_CHANGE,
+            state.update(control_change(
+                ControlFunction::GENERAL_PURPOSE_CONTROLLER_8,
+                127
+            )),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            [true, false, false, true],
+            state.general_purpose_switches,
+            "Expected values of at least 64 to turn switches on; expected left but got right"
+        );
+
+        state.update(control_change(
+            ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+            63,
+        ));
+        assert_eq!(
+            [false, false, false, true],
+            state.general_purpose_switches,
+            "Expected values below 64 to turn switches off; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn midi_to_bytes_round_trip() {
+        let msg = control_change(ControlFunction::MODULATION_WHEEL, 42);
+        let packet = midi_to_bytes(&msg).expect("Expected a packet for a channel message");
+        assert_eq!(
+            [0x0B, 0xB0, 0x01, 42],
+            packet,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(msg),
+            bytes_to_midi(&packet).next(),
+            "Expected left but got right"
+        );
+
+        let msg = MidiMessage::ChannelPressure(Channel::Ch2, U7::from_u8_lossy(99));
+        assert_eq!(
+            Some([0x0D, 0xD1, 99, 0]),
+            midi_to_bytes(&msg),
+            "Expected two-byte messages to be padded; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn midi_to_bytes_system_message() {
+        assert_eq!(
+            None,
+            midi_to_bytes(&MidiMessage::TimingClock),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn const_new() {
+        const STATE: MidiState = MidiState::new();
+        assert_eq!(
+            0,
+            STATE.activated_notes.iter().count(),
+            "Expected no activated notes"
+        );
+        assert_eq!(
+            Portamento::default(),
+            STATE.portamento,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            [false; 4], STATE.general_purpose_switches,
+            "Expected all switches to be off"
+        );
+    }
+
+    #[test]
+    fn program_change() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::PROGRAM_CHANGE,
+            state.update(MidiMessage::ProgramChange(
+                Channel::Ch1,
+                U7::from_u8_lossy(15)
+            )),
+            "Expected left but got right"
+        );
+        assert_eq!(Some(15), state.last_program, "Expected left but got right");
+
+        assert_eq!(
+            Operation::empty(),
+            state.update(MidiMessage::ProgramChange(
+                Channel::Ch1,
+                U7::from_u8_lossy(16)
+            )),
+            "Expected out-of-range program to be ignored"
+        );
+        assert_eq!(
+            Some(15),
+            state.last_program,
+            "Expected out-of-range program not to replace the last one"
+        );
+    }
+
+    #[test]
+    fn active_sensing() {
+        let packet = [0x0F, 0xFE, 0x00, 0x00];
+        let msg = bytes_to_midi(&packet)
+            .next()
+            .expect("Expected single-byte packet to contain a message");
+        assert_eq!(
+            MidiMessage::ActiveSensing,
+            msg,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::ACTIVE_SENSING,
+            MidiState::default().update(msg),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::C4,
+            U7::from_u8_lossy(100),
+        ));
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(control_change(
+            ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+            127,
+        ));
+
+        let packet = [0x0F, 0xFF, 0x00, 0x00];
+        let msg = bytes_to_midi(&packet)
+            .next()
+            .expect("Expected single-byte packet to contain a message");
+        assert_eq!(
+            Operation::NOTE_CHANGE
+                | Operation::PORTAMENTO_CHANGE
+                | Operation::SWITCH_CHANGE
+                | Operation::SUSTAIN_CHANGE,
+            state.update(msg),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0,
+            state.activated_notes.iter().count(),
+            "Expected all notes to be cleared"
+        );
+        assert_eq!(
+            Portamento::default(),
+            state.portamento,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Sustain::default(),
+            state.sustain,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            [false; 4], state.general_purpose_switches,
+            "Expected all switches to be off"
+        );
+    }
+
+    #[test]
+    fn all_notes_off() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(
+            Channel::Ch1,
+            Note::C4,
+            U7::from_u8_lossy(100),
+        ));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100));
+        state.all_notes_off();
+        assert_eq!(
+            0,
+            state.activated_notes.iter().count(),
+            "Expected all notes to be cleared"
+        );
+        assert!(
+            !state.sustain.is_engaged(),
+            "Expected sustain to be released"
+        );
+        assert_eq!(
+            U7::from_u8_lossy(100),
+            state.portamento.time(),
+            "Expected controllers to be untouched"
+        );
+    }
+
+    #[test]
+    fn all_notes_off_message() {
+        let mut state = MidiState::default();
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        assert_eq!(
+            Operation::NOTE_CHANGE | Operation::SUSTAIN_CHANGE | Operation::ALL_NOTES_OFF,
+            state.update(control_change(ControlFunction::ALL_NOTES_OFF, 0)),
+            "Expected left but got right"
+        );
+        assert!(
+            state.activated_notes.is_empty(),
+            "Expected all notes to be cleared"
+        );
+        assert_eq!(
+            Operation::ALL_NOTES_OFF,
+            state.update(control_change(ControlFunction::ALL_NOTES_OFF, 0)),
+            "Expected All Notes Off to be reported even with no notes activated; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn unsupported_message() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::empty(),
+            state.update(control_change(ControlFunction::BANK_SELECT, 1)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn contains_any() {
+        let operation = Operation::NOTE_CHANGE | Operation::ACTIVE_SENSING;
+        assert!(
+            operation.contains_any(Operation::NOTE_CHANGE | Operation::PORTAMENTO_CHANGE),
+            "Expected a match on one of the flags"
+        );
+        assert!(
+            !operation.contains_any(Operation::PORTAMENTO_CHANGE | Operation::SWITCH_CHANGE),
+            "Expected no match when none of the flags are set"
+        );
+        assert!(
+            !operation.contains_any(Operation::empty()),
+            "Expected no match on no flags"
+        );
+    }
+
+    #[test]
+    fn operation_from_note_on_message() {
+        assert_eq!(
+            Operation::NOTE_CHANGE,
+            Operation::from(&MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn operation_from_note_off_message() {
+        assert_eq!(
+            Operation::NOTE_CHANGE,
+            Operation::from(&MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn operation_from_control_change_message() {
+        for (control_function, expected) in [
+            (
+                ControlFunction::PORTAMENTO_TIME,
+                Operation::PORTAMENTO_CHANGE,
+            ),
+            (
+                ControlFunction::PORTAMENTO_TIME_LSB,
+                Operation::PORTAMENTO_CHANGE,
+            ),
+            (
+                ControlFunction::PORTAMENTO_ON_OFF,
+                Operation::PORTAMENTO_CHANGE,
+            ),
+            (
+                ControlFunction::DAMPER_PEDAL,
+                Operation::NOTE_CHANGE | Operation::SUSTAIN_CHANGE,
+            ),
+            (
+                ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+                Operation::SWITCH_CHANGE,
+            ),
+            (
+                ControlFunction::GENERAL_PURPOSE_CONTROLLER_8,
+                Operation::SWITCH_CHANGE,
+            ),
+            (ControlFunction::EFFECTS_4_DEPTH, Operation::DETUNE_CHANGE),
+            (
+                ControlFunction::MODULATION_WHEEL,
+                Operation::MODULATION_CHANGE,
+            ),
+            (
+                ControlFunction::EXPRESSION_CONTROLLER,
+                Operation::EXPRESSION_CHANGE,
+            ),
+            (ControlFunction::BREATH_CONTROLLER, Operation::BREATH_CHANGE),
+            (
+                ControlFunction::RESET_ALL_CONTROLLERS,
+                Operation::NOTE_CHANGE
+                    | Operation::PORTAMENTO_CHANGE
+                    | Operation::SWITCH_CHANGE
+                    | Operation::PITCH_BEND_CHANGE
+                    | Operation::SUSTAIN_CHANGE
+                    | Operation::AFTERTOUCH_CHANGE
+                    | Operation::MODULATION_CHANGE
+                    | Operation::EXPRESSION_CHANGE
+                    | Operation::BREATH_CHANGE,
+            ),
+            (
+                ControlFunction::DATA_ENTRY_MSB,
+                Operation::PITCH_BEND_CHANGE | Operation::TUNING_CHANGE,
+            ),
+            (
+                ControlFunction::REGISTERED_PARAMETER_NUMBER_MSB,
+                Operation::empty(),
+            ),
+            (ControlFunction::BANK_SELECT, Operation::empty()),
+        ] {
+            assert_eq!(
+                expected,
+                Operation::from(&control_change(control_function, 127)),
+                "Unexpected classification of CC {}; expected left but got right",
+                u8::from(control_function)
+            );
+        }
+    }
+
+    #[test]
+    fn channel_pressure() {
+        let mut state = MidiState::default();
+        assert_eq!(
+            Operation::AFTERTOUCH_CHANGE,
+            state.update(MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::empty(),
+            state.update(MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX)),
+            "Expected unchanged pressure to change nothing"
+        );
+        assert_eq!(
+            Voltage::from_volts(DEFAULT_CONTROLLER_MAX_VOLTS),
+            state.controller_voltage(
+                ControllerOutput::Aftertouch,
+                Voltage::from_volts(DEFAULT_CONTROLLER_MAX_VOLTS)
+            ),
+            "Expected full pressure to produce the max voltage; expected left but got right"
+        );
+
+        state.update(MidiMessage::ChannelPressure(Channel::Ch1, U7::MIN));
+        assert_eq!(
+            Voltage::from_volts(0.0),
+            state.controller_voltage(
+                ControllerOutput::Aftertouch,
+                Voltage::from_volts(DEFAULT_CONTROLLER_MAX_VOLTS)
+            ),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn modulation_and_expression() {
+        let mut state = MidiState::default();
+        let max_voltage = Voltage::from_volts(DEFAULT_CONTROLLER_MAX_VOLTS);
+        assert_eq!(
+            max_voltage,
+            state.controller_voltage(ControllerOutput::Expression, max_voltage),
+            "Expected expression to default to full; expected left but got right"
+        );
+
+        assert_eq!(
+            Operation::MODULATION_CHANGE,
+            state.update(control_change(ControlFunction::MODULATION_WHEEL, 127)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::EXPRESSION_CHANGE,
+            state.update(control_change(ControlFunction::EXPRESSION_CONTROLLER, 0)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            max_voltage,
+            state.controller_voltage(ControllerOutput::Modulation, max_voltage),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Voltage::from_volts(0.0),
+            state.controller_voltage(ControllerOutput::Expression, max_voltage),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Voltage::from_volts(0.0),
+            state.controller_voltage(ControllerOutput::Disabled, max_voltage),
+            "Expected left but got right"
+        );
+
+        assert_eq!(
+            Operation::BREATH_CHANGE,
+            state.update(control_change(ControlFunction::BREATH_CONTROLLER, 127)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            max_voltage,
+            state.controller_voltage(ControllerOutput::Breath, max_voltage),
+            "Expected left but got right"
+        );
+
+        state.reset_controllers();
+        assert_eq!(
+            (0, EXPRESSION_DEFAULT, 0),
+            (state.modulation, state.expression, state.breath),
+            "Expected controllers to be reset; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn operation_from_program_change_message() {
+        assert_eq!(
+            Operation::PROGRAM_CHANGE,
+            Operation::from(&MidiMessage::ProgramChange(Channel::Ch1, U7::MIN)),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn operation_from_system_message() {
+        assert_eq!(
+            Operation::ACTIVE_SENSING,
+            Operation::from(&MidiMessage::ActiveSensing),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::NOTE_CHANGE
+                | Operation::PORTAMENTO_CHANGE
+                | Operation::SWITCH_CHANGE
+                | Operation::PROGRAM_CHANGE
+                | Operation::DETUNE_CHANGE
+                | Operation::PITCH_BEND_CHANGE
+                | Operation::SUSTAIN_CHANGE
+                | Operation::AFTERTOUCH_CHANGE
+                | Operation::MODULATION_CHANGE
+                | Operation::EXPRESSION_CHANGE
+                | Operation::BREATH_CHANGE
+                | Operation::TUNING_CHANGE,
+            Operation::from(&MidiMessage::Reset),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::empty(),
+            Operation::from(&MidiMessage::TimingClock),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Operation::empty(),
+            Operation::from(&MidiMessage::Start),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn bpm() {
+        let mut state = MidiState::new();
+        let previous = state;
+        assert_eq!(
+            Operation::empty(),
+            state.update(MidiMessage::TimingClock),
+            "Expected Timing Clock to change nothing; expected left but got right"
+        );
+
+        state.bpm = Some(125.0);
+        assert_eq!(
+            Operation::TEMPO_CHANGE,
+            state.diff(&previous),
+            "Expected left but got right"
+        );
+
+        state.update(MidiMessage::Reset);
+        assert_eq!(
+            Some(125.0),
+            state.bpm,
+            "Expected Reset to keep the tempo; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn update_ignores_redundant_message() {
+        let mut state = MidiState::new();
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100));
+        assert_eq!(
+            Operation::empty(),
+            state.update(control_change(ControlFunction::PORTAMENTO_TIME, 100)),
+            "Expected a message which changes nothing to affect nothing; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn diff() {
+        let previous = MidiState::new();
+        assert_eq!(
+            Operation::empty(),
+            MidiState::new().diff(&previous),
+            "Expected left but got right"
+        );
+
+        let mut state = MidiState::new();
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        state.update(control_change(ControlFunction::PORTAMENTO_TIME, 10));
+        assert_eq!(
+            Operation::NOTE_CHANGE | Operation::PORTAMENTO_CHANGE,
+            state.diff(&previous),
+            "Should report changes made by several messages; expected left but got right"
+        );
+
+        let previous = state;
+        state.update(control_change(
+            ControlFunction::GENERAL_PURPOSE_CONTROLLER_5,
+            127,
+        ));
+        state.update(MidiMessage::ProgramChange(
+            Channel::Ch1,
+            U7::from_u8_lossy(3),
+        ));
+        state.update(MidiMessage::ActiveSensing);
+        assert_eq!(
+            Operation::SWITCH_CHANGE | Operation::PROGRAM_CHANGE,
+            state.diff(&previous),
+            "Expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn note_duration() {
+        let (_clock, driver) = crate::mock_time::mock_driver();
+
+        let mut state = MidiState::new();
+        assert_eq!(
+            None,
+            state.note_duration(),
+            "Expected no duration before any NoteOn"
+        );
+
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX));
+        driver.advance(Duration::from_millis(100));
+        state.update(MidiMessage::NoteOn(Channel::Ch1, Note::E4, U7::MAX));
+        driver.advance(Duration::from_millis(50));
+        assert_eq!(
+            Some(Duration::from_millis(50)),
+            state.note_duration(),
+            "Expected duration since the most recent NoteOn; expected left but got right"
+        );
+
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::E4, U7::MIN));
+        assert!(
+            state.note_duration().is_some(),
+            "Expected a duration while notes remain activated"
+        );
+
+        state.update(MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN));
+        assert_eq!(
+            None,
+            state.note_duration(),
+            "Expected no duration once all notes are released"
+        );
+    }
+
+    #[test]
+    fn note_on_at() {
+        let at = NoteEventTimestamp::from_micros;
+        let mut state = MidiState::new();
+        state.press_note_at(Channel::Ch1, Note::C4, U7::MAX, at(1_000));
+        state.press_note_at(Channel::Ch2, Note::C4, U7::MAX, at(2_000));
+        assert_eq!(
+            Some(at(2_000)),
+            state.note_on_at,
+            "Expected the most recent NoteOn, even for a note held on another channel; expected left but got right"
+        );
+
+        (0..30).for_each(|n| {
+            state.press_note_at(Channel::Ch1, Note::from_u8_lossy(n), U7::MAX, at(3_000))
+        });
+        state.press_note_at(Channel::Ch1, Note::G9, U7::MAX, at(4_000));
+        assert_eq!(
+            Some(at(3_000)),
+            state.note_on_at,
+            "Expected a NoteOn dropped at capacity to be ignored; expected left but got right"
+        );
+
+        state.update(control_change(ControlFunction::DAMPER_PEDAL, 127));
+        state.release_note(Channel::Ch1, Note::C4);
+        state.release_note(Channel::Ch2, Note::C4);
+        (0..30).for_each(|n| state.release_note(Channel::Ch1, Note::from_u8_lossy(n)));
+        state.reset_performance_controllers();
+        assert_eq!(
+            None, state.note_on_at,
+            "Expected no NoteOn time once the pedal releases the last notes; expected left but got right"
+        );
+    }
+
+    /// Generates USB-MIDI Event Packets carrying channel voice messages (or Active Sensing), most of which parse.
+    fn packet() -> impl proptest::strategy::Strategy<Value = [u8; 4]> {
+        use proptest::prelude::*;
+        (
+            prop_oneof![0x80_u8..=0xEF, Just(0xFE)],
+            0_u8..=127,
+            0_u8..=127,
+        )
+            .prop_map(|(status, data1, data2)| [status >> 4, status, data1, data2])
+    }
+
+    /// Asserts that every public field of the two states matches. As other tests advance the mock clock concurrently,
+    /// only the presence of [`MidiState::note_on_at`] is compared, not the time itself.
+    fn prop_assert_same_state(
+        left: &MidiState,
+        right: &MidiState,
+    ) -> Result<(), proptest::test_runner::TestCaseError> {
+        proptest::prop_assert_eq!(left.activated_notes, right.activated_notes);
+        for note in left.activated_notes.iter() {
+            proptest::prop_assert_eq!(
+                left.activated_notes.velocity_of(note),
+                right.activated_notes.velocity_of(note)
+            );
+        }
+        proptest::prop_assert_eq!(
+            left.activated_notes.channel_count(),
+            right.activated_notes.channel_count()
+        );
+        proptest::prop_assert_eq!(left.portamento, right.portamento);
+        proptest::prop_assert_eq!(left.pitch_bend, right.pitch_bend);
+        proptest::prop_assert_eq!(left.registered_parameter, right.registered_parameter);
+        proptest::prop_assert_eq!(left.sustain, right.sustain);
+        proptest::prop_assert_eq!(
+            left.general_purpose_switches,
+            right.general_purpose_switches
+        );
+        proptest::prop_assert_eq!(left.last_program, right.last_program);
+        proptest::prop_assert_eq!(left.note_on_at.is_some(), right.note_on_at.is_some());
+        proptest::prop_assert_eq!(left.detune_depth, right.detune_depth);
+        proptest::prop_assert_eq!(left.coarse_tuning, right.coarse_tuning);
+        proptest::prop_assert_eq!(left.channel_pressure, right.channel_pressure);
+        proptest::prop_assert_eq!(left.modulation, right.modulation);
+        proptest::prop_assert_eq!(left.expression, right.expression);
+        proptest::prop_assert_eq!(left.breath, right.breath);
+        proptest::prop_assert_eq!(left.bpm, right.bpm);
+        Ok(())
+    }
+
+    fn run(packets: &[[u8; 4]]) -> MidiState {
+        let mut state = MidiState::default();
+        packets
+            .iter()
+            .flat_map(|packet| bytes_to_midi(packet))
+            .for_each(|msg| state.apply(msg));
+        state
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn update_arbitrary_packets(packets in proptest::collection::vec(packet(), 0..256)) {
+            let mut state = MidiState::default();
+            // a reference model of Portamento Time: a new MSB discards any LSB, and only Reset All Controllers resets
+            // both
+            let mut time = ControlValue::MIN;
+            let mut time_lsb = None;
+            for msg in packets.iter().flat_map(|packet| bytes_to_midi(packet)) {
+                match msg {
+                    MidiMessage::ControlChange(_, ControlFunction::PORTAMENTO_TIME, value) => {
+                        time = value;
+                        time_lsb = None;
+                    }
+                    MidiMessage::ControlChange(_, ControlFunction::PORTAMENTO_TIME_LSB, value) => {
+                        time_lsb = Some(value);
+                    }
+                    MidiMessage::ControlChange(_, ControlFunction::RESET_ALL_CONTROLLERS, _) => {
+                        time = ControlValue::MIN;
+                        time_lsb = None;
+                    }
+                    _ => {}
+                }
+
+                let addressed = Operation::from(&msg);
+                let operation = state.update(msg);
+                proptest::prop_assert!(
+                    addressed.contains(operation),
+                    "Expected {:?} to be within the addressed {:?}",
+                    operation,
+                    addressed
+                );
+                // GM2 devices are required to support at least 32 simultaneous notes, which is all this one tracks
+                proptest::prop_assert!(
+                    state.activated_notes.len() <= 32,
+                    "Expected at most 32 activated notes but got {}",
+                    state.activated_notes.len()
+                );
+                proptest::prop_assert_eq!(
+                    (time, time_lsb),
+                    (state.portamento.time(), state.portamento.time_lsb()),
+                    "Expected Portamento Time to follow the most recent CC 5, CC 37, and CC 121"
+                );
+            }
+        }
+
+        #[test]
+        fn update_deterministic(packets in proptest::collection::vec(packet(), 0..256)) {
+            prop_assert_same_state(&run(&packets), &run(&packets))?;
+        }
+
+        #[test]
+        fn extra_note_off(note in 0_u8..=127, repeats in 1_usize..4) {
+            let note = Note::from(U7::from_u8_lossy(note));
+            let mut state = MidiState::default();
+            state.update(MidiMessage::NoteOn(Channel::Ch1, note, U7::MAX));
+            for _ in 0..repeats {
+                state.update(MidiMessage::NoteOff(Channel::Ch1, note, U7::MIN));
+            }
+            proptest::prop_assert!(
+                state.activated_notes.is_empty(),
+                "Expected repeated NoteOffs to leave no notes activated"
+            );
+        }
     }
 }
@@ -3,8 +3,17 @@
 //! those notes are actually voiced. (On a monophonic instrument, many keys might be depressed, but only one will
 //! sound.)
 
-use tinyvec::{ArrayVec, array_vec};
-use wmidi::{Note, U7};
+#[cfg(feature = "embassy-time")]
+use crate::timestamp::EmbassyNow;
+use crate::timestamp::{NoteEventTimestamp, NowProvider};
+use core::{
+    cmp::{Ordering, Reverse},
+    ops::RangeInclusive,
+};
+#[cfg(feature = "embassy-time")]
+use embassy_time::Duration;
+use tinyvec::ArrayVec;
+use wmidi::{Channel, Note, U7};
 
 /// Per the General MIDI Level 2 specification, compliant devices "must be capable of supplying polyphony of
 /// 32 or more allocated notes simultaneously." Thus, this will be the default size of an ActivatedNotes instance.
@@ -14,10 +23,57 @@ const GM2_SIMUL_NOTE_NUM: usize = 32;
 ///
 /// Internally, this struct uses the [`U7`] type because [`tinyvec`] requires that `Items` implement [`Default`].
 /// However, [`U7`] can be a bit unwieldy, so public interfaces will deal with the related [`Note`] type instead.
-#[derive(Clone, Copy, Debug, PartialEq)]
+///
+/// A [`Note`] is activated per MIDI channel, just as a NoteOn and its NoteOff are matched per channel: the same pitch
+/// can be held on several channels at once, and releasing it on one channel leaves it activated on the others.
+///
+/// Equality considers only which notes are activated on which channels (and in what order), not when or how hard they
+/// were activated.
+///
+/// Ordering is by the number of activated notes, e.g., a triad is greater than a dyad. To remain consistent with
+/// equality, instances with the same number of notes are comparable only if they are equal; otherwise,
+/// [`partial_cmp`][PartialOrd::partial_cmp] returns `None`.
+#[derive(Clone, Copy, Debug)]
 pub struct ActivatedNotes<const N: usize = GM2_SIMUL_NOTE_NUM> {
-    /// [`U7`] representations of the currently activated notes
-    data: ArrayVec<[U7; N]>,
+    /// The currently activated notes, in order of activation
+    data: ArrayVec<[ActivatedNote; N]>,
+}
+
+/// A single activated note, along with the details of its activation.
+#[derive(Clone, Copy, Debug, Default)]
+struct ActivatedNote {
+    /// [`U7`] representation of the note
+    note: U7,
+    /// When the note was activated
+    activated_at: NoteEventTimestamp,
+    /// The NoteOn velocity with which the note was activated
+    velocity: U7,
+    /// Index (0-15) of the MIDI channel on which the note was activated
+    channel: u8,
+    /// The number of NoteOns received for the note, less the NoteOffs; see [`ActivatedNotes::add_counted`]
+    #[cfg(feature = "counted_notes")]
+    count: u8,
+}
+
+/// Per the MIDI specification, devices which don't sense velocity should send this value.
+const DEFAULT_VELOCITY: U7 = U7::from_u8_lossy(64);
+
+impl<const N: usize> PartialEq for ActivatedNotes<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.data
+            .iter()
+            .map(|n| (n.channel, n.note))
+            .eq(other.data.iter().map(|n| (n.channel, n.note)))
+    }
+}
+
+impl<const N: usize> PartialOrd for ActivatedNotes<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.data.len().cmp(&other.data.len()) {
+            Ordering::Equal => (self == other).then_some(Ordering::Equal),
+            ordering => Some(ordering),
+        }
+    }
 }
 
 impl Default for ActivatedNotes {
@@ -31,7 +87,7 @@ impl<const N: usize> defmt::Format for ActivatedNotes<N> {
     fn format(&self, fmt: defmt::Formatter) {
         defmt::write!(fmt, "ActivatedNotes {{ ");
         defmt::write!(fmt, "data: [");
-        for (i, &note) in self.data.iter().enumerate() {
+        for (i, &ActivatedNote { note, .. }) in self.data.iter().enumerate() {
             if i == 0 {
                 defmt::write!(fmt, " ");
             } else {
@@ -46,48 +102,453 @@ impl<const N: usize> defmt::Format for ActivatedNotes<N> {
 
 impl ActivatedNotes {
     /// Construct a new `ActivatedNotes`.
-    pub fn new() -> Self {
-        Self { data: array_vec!() }
+    pub const fn new() -> Self {
+        Self {
+            data: ArrayVec::from_array_empty(
+                [ActivatedNote {
+                    note: U7::MIN,
+                    activated_at: NoteEventTimestamp::from_micros(0),
+                    velocity: U7::MIN,
+                    channel: 0,
+                    #[cfg(feature = "counted_notes")]
+                    count: 0,
+                }; GM2_SIMUL_NOTE_NUM],
+            ),
+        }
+    }
+
+    /// Construct an `ActivatedNotes` from a bitmask in which bit `n` is set if MIDI note number `n` is activated; see
+    /// [`to_bitmask`][Self::to_bitmask].
+    ///
+    /// Each note is recorded as having been activated on channel 1 with the given velocity, at the epoch. Notes are
+    /// added in ascending order of pitch, as the bitmask doesn't preserve order of activation; as with
+    /// [`add`][Self::add], notes beyond capacity are ignored.
+    pub fn from_bitmask_with_velocity(mask: u128, velocity: U7) -> Self {
+        let mut notes = Self::new();
+        let mut remaining = mask;
+        while remaining != 0 {
+            let note_index = remaining.trailing_zeros();
+            notes.add_note_on_at(
+                Channel::Ch1,
+                Note::from_u8_lossy(note_index as u8),
+                velocity,
+                NoteEventTimestamp::default(),
+            );
+            remaining &= remaining - 1;
+        }
+        notes
     }
 
     /// Add a [`Note`] to the list of those currently activated. Equivalent to depressing a key on a keyboard.
+    ///
+    /// The note is recorded as having been activated on channel 1 with the default velocity of 64, at the epoch; use
+    /// [`add_note_on`][Self::add_note_on] or [`add_at`][Self::add_at] where the time of activation matters.
     pub fn add(&mut self, note: Note) {
-        let u7 = U7::from_u8_lossy(note as u8);
-        // only add if space allows and if the note isn't (somehow) already registered as active; otherwise, ignore input
-        if self.data.len() != self.data.capacity() && !self.data.contains(&u7) {
-            self.data.push(u7);
+        self.add_at(note, NoteEventTimestamp::default());
+    }
+
+    /// Add a [`Note`] to the list of those currently activated, recording the details of the NoteOn which activated it.
+    ///
+    /// The time of activation is read from the [`NowProvider`] `C`.
+    pub fn add_note_on<C: NowProvider>(&mut self, channel: Channel, note: Note, velocity: U7) {
+        self.add_note_on_at(channel, note, velocity, C::now());
+    }
+
+    /// Add a [`Note`] to the list of those currently activated, recording the details of the NoteOn which activated it
+    /// and that it was activated at `timestamp`.
+    pub fn add_note_on_at(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: U7,
+        timestamp: NoteEventTimestamp,
+    ) {
+        self.push(ActivatedNote {
+            note: U7::from_u8_lossy(note as u8),
+            activated_at: timestamp,
+            velocity,
+            channel: channel.index(),
+            #[cfg(feature = "counted_notes")]
+            count: 1,
+        });
+    }
+
+    /// Add a [`Note`] to the list of those currently activated, recording that it was activated at `timestamp`.
+    pub fn add_at(&mut self, note: Note, timestamp: NoteEventTimestamp) {
+        self.add_note_on_at(Channel::Ch1, note, DEFAULT_VELOCITY, timestamp);
+    }
+
+    fn push(&mut self, activated_note: ActivatedNote) {
+        // only add if space allows and if the note isn't (somehow) already registered as active on its channel; otherwise,
+        // ignore input
+        if self.data.len() != self.data.capacity()
+            && self
+                .position(activated_note.channel, activated_note.note)
+                .is_none()
+        {
+            self.data.push(activated_note);
         }
     }
 
-    /// Remove a [`Note`] from the list of those currently activated. Equivalent to releasing a depressed key on a keyboard.
+    /// Returns the index of the [`Note`] activated on the channel with the given index, if any.
+    fn position(&self, channel: u8, note: U7) -> Option<usize> {
+        self.data
+            .iter()
+            .position(|n| n.channel == channel && n.note == note)
+    }
+
+    /// Remove a [`Note`] from the list of those currently activated, on every channel. Equivalent to releasing a
+    /// depressed key on a keyboard.
+    ///
+    /// Use [`remove_note_off`][Self::remove_note_off] to release the note on a single channel.
     pub fn remove(&mut self, note: Note) {
-        self.data.retain(|&n| n != U7::from_u8_lossy(note as u8));
+        self.data
+            .retain(|n| n.note != U7::from_u8_lossy(note as u8));
+    }
+
+    /// Remove a [`Note`] activated on the given [`Channel`] from the list of those currently activated, leaving it
+    /// activated on any other channel. The counterpart to [`add_note_on`][Self::add_note_on].
+    pub fn remove_note_off(&mut self, channel: Channel, note: Note) {
+        if let Some(i) = self.position(channel.index(), U7::from_u8_lossy(note as u8)) {
+            self.data.remove(i);
+        }
+    }
+
+    /// Add a [`Note`] to the list of those currently activated, or, if it is already activated, count the repeat.
+    ///
+    /// Some senders (e.g., buggy DAW scripts) send several NoteOns for a note, followed by as many NoteOffs. Whereas
+    /// [`add`][Self::add] ignores the repeats, so that the first NoteOff releases the note, counting them keeps the note
+    /// activated until [`remove_counted`][Self::remove_counted] has been called as many times.
+    ///
+    /// As with [`add`][Self::add], the note is activated on channel 1; see
+    /// [`add_note_on_counted_at`][Self::add_note_on_counted_at].
+    #[cfg(feature = "counted_notes")]
+    pub fn add_counted(&mut self, note: Note) {
+        self.add_note_on_counted_at(
+            Channel::Ch1,
+            note,
+            DEFAULT_VELOCITY,
+            NoteEventTimestamp::default(),
+        );
+    }
+
+    /// Counts a release of a [`Note`] added by [`add_counted`][Self::add_counted], removing it from the list of those
+    /// currently activated once every NoteOn has been matched.
+    #[cfg(feature = "counted_notes")]
+    pub fn remove_counted(&mut self, note: Note) {
+        self.remove_note_off_counted(Channel::Ch1, note);
+    }
+
+    /// The counterpart to [`add_note_on_at`][Self::add_note_on_at] which counts repeated NoteOns on the same
+    /// [`Channel`]; see [`add_counted`][Self::add_counted]. A repeat leaves the details of the activation as they were.
+    #[cfg(feature = "counted_notes")]
+    pub fn add_note_on_counted_at(
+        &mut self,
+        channel: Channel,
+        note: Note,
+        velocity: U7,
+        timestamp: NoteEventTimestamp,
+    ) {
+        match self.find_mut(channel, note) {
+            Some(n) => n.count = n.count.saturating_add(1),
+            None => self.add_note_on_at(channel, note, velocity, timestamp),
+        }
+    }
+
+    /// The counterpart to [`remove_note_off`][Self::remove_note_off] which counts releases of a [`Note`] added by
+    /// [`add_note_on_counted_at`][Self::add_note_on_counted_at], leaving it activated on the given [`Channel`] until
+    /// every NoteOn there has been matched.
+    #[cfg(feature = "counted_notes")]
+    pub fn remove_note_off_counted(&mut self, channel: Channel, note: Note) {
+        match self.find_mut(channel, note) {
+            Some(n) if n.count > 1 => n.count -= 1,
+            Some(_) => self.remove_note_off(channel, note),
+            None => {}
+        }
+    }
+
+    /// Returns the number of NoteOns for a [`Note`] on the given [`Channel`] yet to be matched by a NoteOff, or 0 if
+    /// the note isn't activated there; see [`add_note_on_counted_at`][Self::add_note_on_counted_at].
+    #[cfg(feature = "counted_notes")]
+    pub fn count_of(&self, channel: Channel, note: Note) -> u8 {
+        self.position(channel.index(), U7::from_u8_lossy(note as u8))
+            .map_or(0, |i| self.data[i].count)
+    }
+
+    /// Finds a [`Note`] activated on the given [`Channel`].
+    #[cfg(feature = "counted_notes")]
+    fn find_mut(&mut self, channel: Channel, note: Note) -> Option<&mut ActivatedNote> {
+        let i = self.position(channel.index(), U7::from_u8_lossy(note as u8))?;
+        Some(&mut self.data[i])
+    }
+
+    /// Returns the number of activated [`Note`]s.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if no [`Note`]s are activated.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns `true` if the [`Note`] is currently activated on any channel.
+    pub fn contains(&self, note: Note) -> bool {
+        self.find(note).is_some()
+    }
+
+    fn find(&self, note: Note) -> Option<&ActivatedNote> {
+        let u7 = U7::from_u8_lossy(note as u8);
+        self.data.iter().find(|n| n.note == u7)
+    }
+
+    /// Returns the earliest activation of the [`Note`] on any channel, regardless of the order in which activations are
+    /// stored (which, e.g., [`swap`][Self::swap] disturbs).
+    fn find_earliest(&self, note: Note) -> Option<&ActivatedNote> {
+        let u7 = U7::from_u8_lossy(note as u8);
+        self.data
+            .iter()
+            .filter(|n| n.note == u7)
+            .min_by_key(|n| n.activated_at)
+    }
+
+    /// Returns the time at which the [`Note`] was activated, or `None` if it is not currently activated.
+    ///
+    /// If the note is activated on several channels, the earliest activation is reported.
+    pub fn activated_at(&self, note: Note) -> Option<NoteEventTimestamp> {
+        self.find_earliest(note).map(|n| n.activated_at)
+    }
+
+    /// Returns the velocity with which the [`Note`] was activated, or `None` if it is not currently activated.
+    ///
+    /// If the note is activated on several channels, the velocity of the earliest activation is reported.
+    pub fn velocity_of(&self, note: Note) -> Option<U7> {
+        self.find_earliest(note).map(|n| n.velocity)
+    }
+
+    /// Returns the number of distinct MIDI channels on which [`Note`]s are activated.
+    pub fn channel_count(&self) -> u8 {
+        let channels = self
+            .data
+            .iter()
+            .fold(0_u16, |channels, n| channels | 1 << n.channel);
+        channels.count_ones() as u8
+    }
+
+    /// Returns the number of activated [`Note`]s higher in pitch than `threshold` (which itself is not counted).
+    pub fn count_above(&self, threshold: Note) -> usize {
+        let threshold = U7::from_u8_lossy(threshold as u8);
+        self.data.iter().filter(|n| n.note > threshold).count()
+    }
+
+    /// Returns the number of activated [`Note`]s lower in pitch than `threshold` (which itself is not counted).
+    pub fn count_below(&self, threshold: Note) -> usize {
+        let threshold = U7::from_u8_lossy(threshold as u8);
+        self.data.iter().filter(|n| n.note < threshold).count()
+    }
+
+    /// Returns a copy containing only the [`Note`]s activated on the given [`Channel`], in order of activation.
+    pub fn on_channel(&self, channel: Channel) -> Self {
+        let mut notes = *self;
+        notes.data.retain(|n| n.channel == channel.index());
+        notes
+    }
+
+    /// Returns how long the [`Note`] has been activated, or `None` if it is not currently activated.
+    ///
+    /// Useful for strategies that depend on the age of a note, e.g., stealing the oldest held note.
+    #[cfg(feature = "embassy-time")]
+    pub fn age_of(&self, note: Note) -> Option<Duration> {
+        self.activated_at(note)
+            .map(|timestamp| Duration::from_micros(EmbassyNow::now().micros_since(timestamp)))
+    }
+
+    /// Removes any [`Note`] not also activated on the same channel in `held`, i.e., updates `self` in place to the
+    /// intersection of the two.
+    ///
+    /// Useful for keeping an accumulator of notes (e.g., those deferred by chord cleanup) in step with the keys that
+    /// are actually still depressed. The relative order of the retained notes is preserved.
+    pub fn retain_if_pressed(&mut self, held: &ActivatedNotes) {
+        self.data
+            .retain(|n| held.position(n.channel, n.note).is_some());
+    }
+
+    /// Adds any [`Note`] activated in `other` but not on the same channel in `self`, keeping the details of its
+    /// activation, i.e., updates `self` in place to the union of the two.
+    ///
+    /// The counterpart to [`retain_if_pressed`][Self::retain_if_pressed]; useful for accumulating notes across key
+    /// presses (e.g., for an arpeggiator's latch). Added notes follow those already present, and, as with
+    /// [`add`][Self::add], notes beyond capacity are ignored.
+    pub fn merge(&mut self, other: &ActivatedNotes) {
+        other.data.iter().for_each(|&n| self.push(n));
+    }
+
+    /// Removes any [`Note`] activated on the same channel in `released`, i.e., updates `self` in place to the
+    /// difference of the two.
+    ///
+    /// Useful for releasing several notes at once, e.g., those held by the sustain pedal when it is lifted.
+    pub fn subtract(&mut self, released: &ActivatedNotes) {
+        self.data
+            .retain(|n| released.position(n.channel, n.note).is_none());
+    }
+
+    /// Removes any [`Note`] outside of `range`, returning the number of notes removed.
+    ///
+    /// The in-place counterpart to filtering with [`Keyboard::notes_in_range`][crate::configuration::Keyboard::notes_in_range].
+    pub fn retain_range(&mut self, range: &RangeInclusive<Note>) -> usize {
+        let len = self.data.len();
+        self.data.retain(|n| range.contains(&Note::from(n.note)));
+        len - self.data.len()
+    }
+
+    /// Swaps the positions of two activated [`Note`]s, each given with the [`Channel`] on which it was activated, without
+    /// otherwise disturbing the order of activation.
+    ///
+    /// Returns `true` if both notes were found (and swapped), otherwise `false`, in which case nothing changes. As the
+    /// swapped notes keep their timestamps, [`sort_by_timestamp`][Self::sort_by_timestamp] undoes the swap.
+    pub fn swap(&mut self, a: (Channel, Note), b: (Channel, Note)) -> bool {
+        let position = |(channel, note): (Channel, Note)| {
+            self.position(channel.index(), U7::from_u8_lossy(note as u8))
+        };
+        match (position(a), position(b)) {
+            (Some(i), Some(j)) => {
+                self.data.swap(i, j);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns a bitmask in which bit `n` is set if MIDI note number `n` is activated. Order of activation is discarded.
+    pub fn to_bitmask(&self) -> u128 {
+        self.data
+            .iter()
+            .fold(0, |mask, n| mask | 1 << u8::from(n.note))
+    }
+
+    /// Orders the activated [`Note`]s by ascending time of activation, so that [`NotePriority::First`] and
+    /// [`NotePriority::Last`] select by time even if the order has been disturbed, e.g., by [`swap`][Self::swap] or by
+    /// [`add_at`][Self::add_at] with a timestamp earlier than that of a note already activated.
+    ///
+    /// The sort is stable, so notes with equal timestamps keep their relative order. It is an insertion sort, as the
+    /// stable sort in the standard library requires an allocator; with at most a few dozen notes, O(n²) is no concern.
+    ///
+    /// [`NotePriority::First`]: crate::configuration::NotePriority::First
+    /// [`NotePriority::Last`]: crate::configuration::NotePriority::Last
+    pub fn sort_by_timestamp(&mut self) {
+        self.sort_by_key_stable(|n| n.activated_at);
+    }
+
+    /// Stably orders the activated [`Note`]s by the given key; see [`sort_by_timestamp`][Self::sort_by_timestamp] for
+    /// why this is an insertion sort.
+    fn sort_by_key_stable<K: PartialOrd>(&mut self, key: impl Fn(&ActivatedNote) -> K) {
+        for i in 1..self.data.len() {
+            let mut j = i;
+            while j > 0 && key(&self.data[j - 1]) > key(&self.data[j]) {
+                self.data.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+
+    /// Returns a copy of the activated [`Note`]s ordered by pitch (i.e., ascending MIDI note number) rather than by
+    /// order of activation. The sort is stable, so a [`Note`] activated on several channels keeps its order of
+    /// activation.
+    ///
+    /// A copy of [`ActivatedNotes`] is returned rather than a collection of [`Note`]s because [`tinyvec`] requires that
+    /// `Items` implement [`Default`], which [`Note`] does not. Iterate over the result to get the notes in pitch order.
+    pub fn to_sorted(&self) -> Self {
+        let mut sorted = *self;
+        sorted.sort_by_key_stable(|n| n.note);
+        sorted
+    }
+
+    /// Returns a copy of the `n` highest activated [`Note`]s in descending order of pitch, or all of them if fewer than
+    /// `n` are activated.
+    ///
+    /// As with [`to_sorted`][Self::to_sorted], a copy of [`ActivatedNotes`] is returned rather than a collection of
+    /// [`Note`]s. A [`Note`] activated on several channels is included once per channel, in order of activation.
+    pub fn highest_n(&self, n: usize) -> Self {
+        let mut highest = *self;
+        highest.sort_by_key_stable(|n| Reverse(n.note));
+        highest.data.truncate(n);
+        highest
+    }
+
+    /// Returns a copy of the `n` lowest activated [`Note`]s in ascending order of pitch, or all of them if fewer than `n`
+    /// are activated.
+    ///
+    /// See [`highest_n`][Self::highest_n].
+    pub fn lowest_n(&self, n: usize) -> Self {
+        let mut lowest = self.to_sorted();
+        lowest.data.truncate(n);
+        lowest
+    }
+
+    /// Returns an [`Iterator`] over the activated [`Note`]s in order of activation, each paired with how long it has
+    /// been held.
+    ///
+    /// The clock is read once, so every duration is measured from the same instant; see [`age_of`][Self::age_of].
+    #[cfg(feature = "embassy-time")]
+    pub fn iter_with_hold_duration(&self) -> impl Iterator<Item = (Note, Duration)> + '_ {
+        let now = EmbassyNow::now();
+        self.data.iter().map(move |n| {
+            (
+                Note::from(n.note),
+                Duration::from_micros(now.micros_since(n.activated_at)),
+            )
+        })
     }
 
     /// Returns an [`Iterator`] over the activated [`Note`]s.
     ///
     /// Order is preserved; e.g., the first performed `Note` can be accessed via the first call to `.next()`, and the
     /// last performed `Note` is accessible via `.last()`.
+    ///
+    /// Because [`add`][Self::add] ignores a `Note` that is already activated on the same channel, the iterator yields a
+    /// `Note` more than once only if it is activated on several channels. Pitch-based selection (e.g., `.min()` or
+    /// `.max()`) is deterministic regardless: [`Iterator::min`] keeps the first of several equal elements and
+    /// [`Iterator::max`] keeps the last, but equal elements are the same `Note`, so either choice voices the same pitch.
     pub fn iter(&self) -> impl Iterator<Item = Note> {
-        self.data.iter().map(|&i| Note::from(i))
+        self.data.iter().map(|n| Note::from(n.note))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tinyvec::array_vec;
+
+    /// A [`NowProvider`] for tests which don't depend on the time of activation.
+    struct Epoch;
+
+    impl NowProvider for Epoch {
+        fn now() -> NoteEventTimestamp {
+            NoteEventTimestamp::default()
+        }
+    }
 
     const C_NOTE: U7 = U7::from_u8_lossy(60);
     const D_NOTE: U7 = U7::from_u8_lossy(62);
     const E_NOTE: U7 = U7::from_u8_lossy(64);
     const G_NOTE: U7 = U7::from_u8_lossy(67);
 
-    fn chord() -> ActivatedNotes<GM2_SIMUL_NOTE_NUM> {
+    fn activated(notes: &[U7]) -> ActivatedNotes<GM2_SIMUL_NOTE_NUM> {
         ActivatedNotes::<GM2_SIMUL_NOTE_NUM> {
-            data: array_vec!([U7; 32] => E_NOTE, C_NOTE, G_NOTE),
+            data: notes
+                .iter()
+                .map(|&note| ActivatedNote {
+                    note,
+                    ..Default::default()
+                })
+                .collect(),
         }
     }
 
+    fn chord() -> ActivatedNotes<GM2_SIMUL_NOTE_NUM> {
+        activated(&[E_NOTE, C_NOTE, G_NOTE])
+    }
+
     #[test]
     fn new() {
         let expected: ActivatedNotes<32> = ActivatedNotes { data: array_vec!() };
@@ -95,11 +556,271 @@ mod tests {
         assert_eq!(expected, actual, "Expected left but got right");
     }
 
+    #[test]
+    fn len() {
+        assert_eq!(3, chord().len(), "Expected left but got right");
+        assert!(ActivatedNotes::new().is_empty(), "Expected no notes");
+        assert!(!chord().is_empty(), "Expected some notes");
+    }
+
+    #[test]
+    fn partial_cmp() {
+        let dyad = activated(&[C_NOTE, E_NOTE]);
+        assert!(chord() > dyad, "Expected more notes to be greater");
+        assert!(dyad < chord(), "Expected fewer notes to be less");
+        assert!(
+            ActivatedNotes::new() < dyad,
+            "Expected no notes to be least"
+        );
+        assert_eq!(
+            Some(Ordering::Equal),
+            chord().partial_cmp(&chord()),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            chord().partial_cmp(&activated(&[C_NOTE, D_NOTE, E_NOTE])),
+            "Expected different notes of the same count to be incomparable; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn contains() {
+        let notes = chord();
+        assert!(notes.contains(C_NOTE.into()), "Expected C to be activated");
+        assert!(
+            !notes.contains(D_NOTE.into()),
+            "Expected D not to be activated"
+        );
+    }
+
+    #[test]
+    fn activated_at() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_at(Note::C4, NoteEventTimestamp::from_micros(1_000));
+        assert_eq!(
+            Some(NoteEventTimestamp::from_micros(1_000)),
+            notes.activated_at(Note::C4),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            notes.activated_at(Note::D4),
+            "Expected no timestamp for a note that isn't activated"
+        );
+    }
+
+    #[test]
+    fn activated_at_several_channels() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on_at(
+            Channel::Ch1,
+            Note::C4,
+            U7::from_u8_lossy(100),
+            NoteEventTimestamp::from_micros(2_000),
+        );
+        notes.add_note_on_at(
+            Channel::Ch2,
+            Note::C4,
+            U7::from_u8_lossy(50),
+            NoteEventTimestamp::from_micros(1_000),
+        );
+        assert_eq!(
+            Some(NoteEventTimestamp::from_micros(1_000)),
+            notes.activated_at(Note::C4),
+            "Expected the earliest activation, though it is stored after the later one; expected left but got right"
+        );
+        assert_eq!(
+            Some(U7::from_u8_lossy(50)),
+            notes.velocity_of(Note::C4),
+            "Expected the velocity of the earliest activation; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn velocity_of() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::from_u8_lossy(100));
+        notes.add(Note::E4);
+        assert_eq!(
+            Some(U7::from_u8_lossy(100)),
+            notes.velocity_of(Note::C4),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(DEFAULT_VELOCITY),
+            notes.velocity_of(Note::E4),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            notes.velocity_of(Note::G4),
+            "Expected no velocity for a note that isn't activated"
+        );
+    }
+
+    #[test]
+    fn channel_count() {
+        let mut notes = ActivatedNotes::new();
+        assert_eq!(0, notes.channel_count(), "Expected left but got right");
+
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::E4, U7::MAX);
+        assert_eq!(1, notes.channel_count(), "Expected left but got right");
+
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::G4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch16, Note::B4, U7::MAX);
+        assert_eq!(3, notes.channel_count(), "Expected left but got right");
+
+        notes.remove(Note::G4);
+        assert_eq!(
+            2,
+            notes.channel_count(),
+            "Expected released notes not to count; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn channel_count_same_note() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+        assert_eq!(
+            2,
+            notes.channel_count(),
+            "Expected a note held on two channels to count toward both; expected left but got right"
+        );
+
+        notes.remove_note_off(Channel::Ch1, Note::C4);
+        assert_eq!(1, notes.channel_count(), "Expected left but got right");
+    }
+
+    #[test]
+    fn on_channel() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::E4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::G4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+
+        assert!(
+            notes
+                .on_channel(Channel::Ch1)
+                .iter()
+                .eq([Note::C4, Note::G4]),
+            "Expected only channel 1's notes"
+        );
+        assert!(
+            notes
+                .on_channel(Channel::Ch2)
+                .iter()
+                .eq([Note::E4, Note::C4]),
+            "Expected a note held on two channels to belong to both"
+        );
+        assert!(
+            notes.on_channel(Channel::Ch16).is_empty(),
+            "Expected no notes on an unused channel"
+        );
+    }
+
+    #[test]
+    fn count_above() {
+        let notes = chord();
+        assert_eq!(
+            3,
+            notes.count_above(Note::B3),
+            "Expected all notes to be above; expected left but got right"
+        );
+        assert_eq!(
+            0,
+            notes.count_above(Note::A4),
+            "Expected no notes to be above; expected left but got right"
+        );
+        assert_eq!(
+            1,
+            notes.count_above(E_NOTE.into()),
+            "Expected the threshold note not to be counted; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn count_below() {
+        let notes = chord();
+        assert_eq!(
+            3,
+            notes.count_below(Note::A4),
+            "Expected all notes to be below; expected left but got right"
+        );
+        assert_eq!(
+            0,
+            notes.count_below(Note::B3),
+            "Expected no notes to be below; expected left but got right"
+        );
+        assert_eq!(
+            1,
+            notes.count_below(E_NOTE.into()),
+            "Expected the threshold note not to be counted; expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn iter_with_hold_duration() {
+        let (_clock, driver) = crate::mock_time::mock_driver();
+
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on::<EmbassyNow>(Channel::Ch1, Note::C4, U7::MAX);
+        driver.advance(Duration::from_millis(100));
+        notes.add_note_on::<EmbassyNow>(Channel::Ch1, Note::E4, U7::MAX);
+        driver.advance(Duration::from_millis(50));
+
+        assert!(
+            notes.iter_with_hold_duration().eq([
+                (Note::C4, Duration::from_millis(150)),
+                (Note::E4, Duration::from_millis(50)),
+            ]),
+            "Expected each note paired with its hold duration, in order of activation"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn age_of() {
+        let (_clock, driver) = crate::mock_time::mock_driver();
+
+        let mut notes = ActivatedNotes::new();
+        assert_eq!(
+            None,
+            notes.age_of(Note::C4),
+            "Expected no age for a note that isn't activated"
+        );
+
+        notes.add_note_on::<EmbassyNow>(Channel::Ch1, Note::C4, U7::MAX);
+        assert_eq!(
+            Some(Duration::from_millis(0)),
+            notes.age_of(Note::C4),
+            "Expected left but got right"
+        );
+
+        driver.advance(Duration::from_millis(250));
+        assert_eq!(
+            Some(Duration::from_millis(250)),
+            notes.age_of(Note::C4),
+            "Expected age to increase with time; expected left but got right"
+        );
+
+        notes.remove(Note::C4);
+        notes.add_note_on::<EmbassyNow>(Channel::Ch1, Note::C4, U7::MAX);
+        assert_eq!(
+            Some(Duration::from_millis(0)),
+            notes.age_of(Note::C4),
+            "Expected age to reset when the note is re-pressed; expected left but got right"
+        );
+    }
+
     #[test]
     fn add_appends() {
-        let expected = ActivatedNotes::<GM2_SIMUL_NOTE_NUM> {
-            data: array_vec!([U7; 32] => E_NOTE, C_NOTE, G_NOTE, D_NOTE),
-        };
+        let expected = activated(&[E_NOTE, C_NOTE, G_NOTE, D_NOTE]);
 
         let mut actual = chord();
         actual.add(D_NOTE.into());
@@ -116,10 +837,82 @@ mod tests {
         assert_eq!(expected, actual, "Expected left but got right");
     }
 
+    #[cfg(feature = "counted_notes")]
+    #[test]
+    fn counted() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_counted(Note::C4);
+        notes.add_counted(Note::C4);
+
+        notes.remove_counted(Note::C4);
+        assert!(
+            notes.contains(Note::C4),
+            "Expected the note to remain activated until every NoteOn is matched"
+        );
+
+        notes.remove_counted(Note::C4);
+        assert!(
+            notes.is_empty(),
+            "Expected the note to be released once every NoteOn is matched"
+        );
+
+        notes.remove_counted(Note::C4);
+        assert!(notes.is_empty(), "Expected a stray release to be ignored");
+    }
+
+    #[cfg(feature = "counted_notes")]
+    #[test]
+    fn counted_per_channel() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on_counted_at(
+            Channel::Ch1,
+            Note::C4,
+            U7::MAX,
+            NoteEventTimestamp::default(),
+        );
+        notes.add_note_on_counted_at(
+            Channel::Ch2,
+            Note::C4,
+            U7::MAX,
+            NoteEventTimestamp::default(),
+        );
+        notes.add_note_on_counted_at(
+            Channel::Ch2,
+            Note::C4,
+            U7::MAX,
+            NoteEventTimestamp::default(),
+        );
+        assert_eq!(
+            (1, 2),
+            (
+                notes.count_of(Channel::Ch1, Note::C4),
+                notes.count_of(Channel::Ch2, Note::C4)
+            ),
+            "Expected left but got right"
+        );
+
+        notes.remove_counted(Note::C4);
+        assert!(
+            notes.iter().eq([Note::C4]),
+            "Expected the final release on channel 1 to leave the note activated on channel 2"
+        );
+        notes.remove_note_off_counted(Channel::Ch2, Note::C4);
+        assert_eq!(
+            1,
+            notes.count_of(Channel::Ch2, Note::C4),
+            "Expected left but got right"
+        );
+    }
+
     #[test]
     fn add_ignores_rather_than_overflow() {
         let mut activated_notes = ActivatedNotes::<GM2_SIMUL_NOTE_NUM> {
-            data: ArrayVec::from([C_NOTE; GM2_SIMUL_NOTE_NUM]),
+            data: ArrayVec::from(
+                [ActivatedNote {
+                    note: C_NOTE,
+                    ..Default::default()
+                }; GM2_SIMUL_NOTE_NUM],
+            ),
         };
         assert_eq!(
             activated_notes.data.len(),
@@ -138,16 +931,14 @@ mod tests {
             activated_notes
                 .data
                 .iter()
-                .find(|&&n| n == D_NOTE.into())
+                .find(|n| n.note == D_NOTE)
                 .is_none()
         );
     }
 
     #[test]
     fn remove() {
-        let expected = ActivatedNotes::<GM2_SIMUL_NOTE_NUM> {
-            data: array_vec!([U7; 32] => E_NOTE, G_NOTE),
-        };
+        let expected = activated(&[E_NOTE, G_NOTE]);
 
         let mut actual = chord();
         actual.remove(C_NOTE.into());
@@ -155,6 +946,413 @@ mod tests {
         assert_eq!(expected, actual, "Expected left but got right");
     }
 
+    #[test]
+    fn same_note_on_two_channels() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MIN);
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MIN);
+        assert_eq!(
+            2,
+            notes.len(),
+            "Expected the note to be activated once per channel; expected left but got right"
+        );
+        assert_eq!(
+            Some(U7::MAX),
+            notes.velocity_of(Note::C4),
+            "Expected a repeated NoteOn on the same channel to be ignored; expected left but got right"
+        );
+
+        notes.remove_note_off(Channel::Ch1, Note::C4);
+        assert!(
+            notes.contains(Note::C4),
+            "Expected the note to remain activated on channel 2"
+        );
+        assert_eq!(
+            Some(U7::MIN),
+            notes.velocity_of(Note::C4),
+            "Expected channel 2's activation to remain; expected left but got right"
+        );
+
+        notes.remove_note_off(Channel::Ch1, Note::C4);
+        assert!(
+            notes.contains(Note::C4),
+            "Expected a NoteOff on a channel without the note to be ignored"
+        );
+
+        notes.remove_note_off(Channel::Ch2, Note::C4);
+        assert!(notes.is_empty(), "Expected no notes");
+    }
+
+    #[test]
+    fn remove_on_every_channel() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+        notes.add_note_on::<Epoch>(Channel::Ch2, Note::E4, U7::MAX);
+
+        notes.remove(Note::C4);
+        assert!(
+            notes.iter().eq([Note::E4]),
+            "Expected the note to be released on every channel"
+        );
+    }
+
+    #[test]
+    fn retain_if_pressed_all() {
+        let expected = chord();
+        let mut actual = chord();
+        let mut held = chord();
+        held.add(D_NOTE.into());
+
+        actual.retain_if_pressed(&held);
+
+        assert_eq!(
+            expected, actual,
+            "Expected all notes to be retained when held is a superset; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn retain_if_pressed_some() {
+        let expected = activated(&[E_NOTE, G_NOTE]);
+        let mut actual = chord();
+        let held = activated(&[G_NOTE, D_NOTE, E_NOTE]);
+
+        actual.retain_if_pressed(&held);
+
+        assert_eq!(
+            expected, actual,
+            "Expected only held notes to be retained, in their original order; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn retain_if_pressed_per_channel() {
+        let mut actual = ActivatedNotes::new();
+        actual.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        actual.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+        let mut held = ActivatedNotes::new();
+        held.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+
+        actual.retain_if_pressed(&held);
+
+        assert_eq!(
+            held, actual,
+            "Expected the note to be retained only on the channel on which it is held; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn retain_if_pressed_none() {
+        let mut actual = chord();
+
+        actual.retain_if_pressed(&ActivatedNotes::new());
+
+        assert_eq!(
+            ActivatedNotes::new(),
+            actual,
+            "Expected no notes to be retained when none are held; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut actual = chord();
+        actual.merge(&activated(&[G_NOTE, D_NOTE]));
+        assert_eq!(
+            activated(&[E_NOTE, C_NOTE, G_NOTE, D_NOTE]),
+            actual,
+            "Expected only new notes to be added, after the existing ones; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn merge_per_channel() {
+        let mut actual = ActivatedNotes::new();
+        actual.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        let mut other = ActivatedNotes::new();
+        other.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        other.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+
+        actual.merge(&other);
+
+        assert_eq!(
+            other, actual,
+            "Expected the note to be added on the channel where it was missing; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn subtract() {
+        let mut actual = ActivatedNotes::new();
+        actual.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        actual.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+        actual.add_note_on::<Epoch>(Channel::Ch1, Note::E4, U7::MAX);
+        let mut released = ActivatedNotes::new();
+        released.add_note_on::<Epoch>(Channel::Ch1, Note::C4, U7::MAX);
+        released.add_note_on::<Epoch>(Channel::Ch1, Note::G4, U7::MAX);
+
+        actual.subtract(&released);
+
+        let mut expected = ActivatedNotes::new();
+        expected.add_note_on::<Epoch>(Channel::Ch2, Note::C4, U7::MAX);
+        expected.add_note_on::<Epoch>(Channel::Ch1, Note::E4, U7::MAX);
+        assert_eq!(
+            expected, actual,
+            "Expected only the released channel's note to be removed; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn retain_range() {
+        let mut actual = chord();
+        assert_eq!(
+            0,
+            actual.retain_range(&(Note::C4..=Note::G4)),
+            "Expected notes at the boundaries to be kept; expected left but got right"
+        );
+        assert_eq!(chord(), actual, "Expected left but got right");
+
+        assert_eq!(
+            2,
+            actual.retain_range(&(Note::D4..=Note::F4)),
+            "Expected left but got right"
+        );
+        assert_eq!(activated(&[E_NOTE]), actual, "Expected left but got right");
+    }
+
+    #[test]
+    fn to_bitmask() {
+        assert_eq!(
+            1 << 60 | 1 << 64 | 1 << 67,
+            chord().to_bitmask(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0,
+            ActivatedNotes::new().to_bitmask(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn from_bitmask_with_velocity() {
+        let velocity = U7::from_u8_lossy(100);
+        let notes = ActivatedNotes::from_bitmask_with_velocity(1 << 67 | 1 << 60, velocity);
+        assert!(
+            notes.iter().eq([Note::C4, Note::G4]),
+            "Expected notes in ascending order of pitch"
+        );
+        assert_eq!(
+            Some(velocity),
+            notes.velocity_of(Note::G4),
+            "Expected left but got right"
+        );
+        assert!(
+            ActivatedNotes::from_bitmask_with_velocity(1 << 127, velocity)
+                .contains(Note::HIGHEST_NOTE),
+            "Expected the highest bit to map to the highest note"
+        );
+    }
+
+    #[test]
+    fn bitmask_round_trip() {
+        let notes = activated(&[G_NOTE, C_NOTE, E_NOTE]);
+        let actual =
+            ActivatedNotes::from_bitmask_with_velocity(notes.to_bitmask(), DEFAULT_VELOCITY);
+        assert_eq!(
+            notes.to_sorted(),
+            actual,
+            "Expected the same notes, albeit in pitch order; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn highest_n() {
+        assert!(
+            chord().highest_n(2).iter().eq([Note::G4, Note::E4]),
+            "Expected the highest notes, highest first"
+        );
+        assert!(
+            chord().highest_n(1).iter().eq(chord().iter().max()),
+            "Expected the single highest note"
+        );
+        assert!(
+            chord()
+                .highest_n(4)
+                .iter()
+                .eq([Note::G4, Note::E4, Note::C4]),
+            "Expected all notes when fewer than requested are activated"
+        );
+        assert!(chord().highest_n(0).is_empty(), "Expected no notes");
+    }
+
+    #[test]
+    fn lowest_n() {
+        assert!(
+            chord().lowest_n(2).iter().eq([Note::C4, Note::E4]),
+            "Expected the lowest notes, lowest first"
+        );
+        assert!(
+            chord().lowest_n(1).iter().eq(chord().iter().min()),
+            "Expected the single lowest note"
+        );
+        assert!(
+            chord()
+                .lowest_n(4)
+                .iter()
+                .eq([Note::C4, Note::E4, Note::G4]),
+            "Expected all notes when fewer than requested are activated"
+        );
+        assert!(chord().lowest_n(0).is_empty(), "Expected no notes");
+    }
+
+    #[test]
+    fn highest_n_and_lowest_n_equal_pitch() {
+        let mut notes = ActivatedNotes::new();
+        notes.add_note_on_at(
+            Channel::Ch3,
+            Note::C4,
+            U7::MAX,
+            NoteEventTimestamp::from_micros(0),
+        );
+        notes.add_note_on_at(
+            Channel::Ch1,
+            Note::E4,
+            U7::MAX,
+            NoteEventTimestamp::from_micros(100),
+        );
+        notes.add_note_on_at(
+            Channel::Ch2,
+            Note::C4,
+            U7::MAX,
+            NoteEventTimestamp::from_micros(200),
+        );
+        notes.add_note_on_at(
+            Channel::Ch1,
+            Note::C4,
+            U7::MAX,
+            NoteEventTimestamp::from_micros(300),
+        );
+
+        let channels = |notes: ActivatedNotes| {
+            notes
+                .data
+                .iter()
+                .map(|n| n.channel)
+                .collect::<ArrayVec<[u8; 4]>>()
+        };
+        assert_eq!(
+            [2, 1, 0],
+            channels(notes.lowest_n(3)).as_slice(),
+            "Expected a note activated on several channels to keep its order of activation; expected left but got right"
+        );
+        assert_eq!(
+            [0, 2, 1],
+            channels(notes.highest_n(3)).as_slice(),
+            "Expected a note activated on several channels to keep its order of activation; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn sort_by_timestamp() {
+        let mut actual = ActivatedNotes::new();
+        actual.add_at(Note::G4, NoteEventTimestamp::from_micros(300));
+        actual.add_at(Note::C4, NoteEventTimestamp::from_micros(100));
+        actual.add_at(Note::E4, NoteEventTimestamp::from_micros(200));
+        actual.sort_by_timestamp();
+        assert!(
+            actual.iter().eq([Note::C4, Note::E4, Note::G4]),
+            "Expected notes in order of activation"
+        );
+
+        actual.swap((Channel::Ch1, Note::C4), (Channel::Ch1, Note::G4));
+        actual.sort_by_timestamp();
+        assert!(
+            actual.iter().eq([Note::C4, Note::E4, Note::G4]),
+            "Expected the swap to be undone"
+        );
+    }
+
+    #[test]
+    fn sort_by_timestamp_is_stable() {
+        let mut actual = ActivatedNotes::new();
+        actual.add_at(Note::G4, NoteEventTimestamp::from_micros(100));
+        actual.add_at(Note::C4, NoteEventTimestamp::from_micros(100));
+        actual.add_at(Note::E4, NoteEventTimestamp::from_micros(0));
+        actual.sort_by_timestamp();
+        assert!(
+            actual.iter().eq([Note::E4, Note::G4, Note::C4]),
+            "Expected notes with equal timestamps to keep their relative order"
+        );
+    }
+
+    #[test]
+    fn swap() {
+        let expected = activated(&[G_NOTE, C_NOTE, E_NOTE]);
+
+        let mut actual = chord();
+        assert!(
+            actual.swap((Channel::Ch1, E_NOTE.into()), (Channel::Ch1, G_NOTE.into())),
+            "Expected swap to report success"
+        );
+
+        assert_eq!(expected, actual, "Expected left but got right");
+    }
+
+    #[test]
+    fn swap_missing_note() {
+        let expected = chord();
+
+        let mut actual = chord();
+        assert!(
+            !actual.swap((Channel::Ch1, E_NOTE.into()), (Channel::Ch1, D_NOTE.into())),
+            "Expected swap to report failure"
+        );
+
+        assert_eq!(
+            expected, actual,
+            "Expected notes not to be reordered; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn swap_per_channel() {
+        let mut actual = chord();
+        assert!(
+            !actual.swap((Channel::Ch1, E_NOTE.into()), (Channel::Ch2, G_NOTE.into())),
+            "Expected swap to report failure for a note not activated on the given channel"
+        );
+        assert_eq!(chord(), actual, "Expected left but got right");
+    }
+
+    #[test]
+    fn to_sorted() {
+        let mut notes = ActivatedNotes::new();
+        notes.add(Note::E4);
+        notes.add(Note::C4);
+        notes.add(Note::G4);
+        notes.add(Note::B4);
+
+        let sorted = notes.to_sorted();
+        let mut iter = sorted.iter();
+        assert_eq!(Some(Note::C4), iter.next());
+        assert_eq!(Some(Note::E4), iter.next());
+        assert_eq!(Some(Note::G4), iter.next());
+        assert_eq!(Some(Note::B4), iter.next());
+        assert_eq!(None, iter.next());
+    }
+
+    #[test]
+    fn to_sorted_empty() {
+        assert_eq!(
+            ActivatedNotes::new(),
+            ActivatedNotes::new().to_sorted(),
+            "Expected left but got right"
+        );
+    }
+
     #[test]
     fn iter() {
         let chord = chord();
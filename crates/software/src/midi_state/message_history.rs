@@ -0,0 +1,232 @@
+//! Provides [`MessageHistory`], an alternative to the eager updates of [`MidiState`] which retains the raw MIDI
+//! messages the device has received and derives state from them on demand.
+//!
+//! Trading processor time for simplicity of state transitions makes replay, undo, and debugging trivial.
+
+use super::{ActivatedNotes, MidiState};
+use crate::timestamp::NoteEventTimestamp;
+#[cfg(feature = "embassy-time")]
+use crate::timestamp::{EmbassyNow, NowProvider};
+use wmidi::MidiMessage;
+
+/// The default number of messages retained by a [`MessageHistory`].
+const DEFAULT_HISTORY_LEN: usize = 64;
+
+/// A ring buffer of the last `N` MIDI messages received, along with the time at which each was received.
+///
+/// Once full, each new message displaces the oldest. Derived state is accurate only as long as the messages it depends
+/// on are retained; e.g., a note held while more than `N` subsequent messages arrive will be forgotten.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageHistory<const N: usize = DEFAULT_HISTORY_LEN> {
+    /// Storage for the retained messages and their timestamps; slots are filled in order, wrapping around once the end
+    /// is reached.
+    messages: [Option<(MidiMessage<'static>, NoteEventTimestamp)>; N],
+    /// Index of the slot the next message will occupy.
+    next: usize,
+}
+
+impl<const N: usize> Default for MessageHistory<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MessageHistory<N> {
+    /// Constructs a new, empty [`MessageHistory`].
+    pub const fn new() -> Self {
+        Self {
+            messages: [const { None }; N],
+            next: 0,
+        }
+    }
+
+    /// Records a [`MidiMessage`] received just now, displacing the oldest one if the history is full; see
+    /// [`push_at`][Self::push_at].
+    pub fn push(&mut self, msg: MidiMessage) {
+        #[cfg(feature = "embassy-time")]
+        self.push_at(msg, EmbassyNow::now());
+        // without `embassy-time`, there is no clock, so messages are recorded at the epoch, as by `MidiState::apply`
+        #[cfg(not(feature = "embassy-time"))]
+        self.push_at(msg, NoteEventTimestamp::default());
+    }
+
+    /// Records a [`MidiMessage`] received at `timestamp`, displacing the oldest one if the history is full.
+    ///
+    /// System Exclusive messages are not retained, as they have no bearing on [`MidiState`].
+    pub fn push_at(&mut self, msg: MidiMessage, timestamp: NoteEventTimestamp) {
+        if N == 0 {
+            return;
+        }
+        if let Some(msg) = msg.drop_unowned_sysex() {
+            self.messages[self.next] = Some((msg, timestamp));
+            self.next = (self.next + 1) % N;
+        }
+    }
+
+    /// Forgets all retained messages.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Returns an [`Iterator`] over the retained messages, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &MidiMessage<'static>> {
+        self.iter_with_timestamps().map(|(msg, _)| msg)
+    }
+
+    /// Returns an [`Iterator`] over the retained messages, each paired with the time at which it was received, from
+    /// oldest to newest.
+    pub fn iter_with_timestamps(
+        &self,
+    ) -> impl Iterator<Item = (&MidiMessage<'static>, NoteEventTimestamp)> {
+        let (newest, oldest) = self.messages.split_at(self.next);
+        oldest
+            .iter()
+            .chain(newest.iter())
+            .flatten()
+            .map(|(msg, timestamp)| (msg, *timestamp))
+    }
+
+    /// Derives a [`MidiState`] by replaying the retained messages, oldest first, each as of the time it was received, so
+    /// that anything which depends on timing (e.g., [`ActivatedNotes::activated_at`]) matches the eager model.
+    pub fn midi_state(&self) -> MidiState {
+        let mut state = MidiState::new();
+        self.iter_with_timestamps().for_each(|(msg, timestamp)| {
+            state.apply_at(msg.clone(), timestamp);
+        });
+        state
+    }
+
+    /// Derives the currently activated notes, preserving the order in which they were activated.
+    pub fn activated_notes(&self) -> ActivatedNotes {
+        self.midi_state().activated_notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmidi::{Channel, ControlFunction, Note, U7};
+
+    fn note_on(note: Note) -> MidiMessage<'static> {
+        MidiMessage::NoteOn(Channel::Ch1, note, U7::from_u8_lossy(100))
+    }
+
+    fn note_off(note: Note) -> MidiMessage<'static> {
+        MidiMessage::NoteOff(Channel::Ch1, note, U7::from_u8_lossy(0))
+    }
+
+    #[test]
+    fn matches_eager_state() {
+        let messages = [
+            note_on(Note::C4),
+            note_on(Note::E4),
+            MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlFunction::PORTAMENTO_TIME,
+                U7::from_u8_lossy(100),
+            ),
+            note_on(Note::G4),
+            note_off(Note::E4),
+            MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlFunction::GENERAL_PURPOSE_CONTROLLER_6,
+                U7::from_u8_lossy(127),
+            ),
+        ];
+
+        let mut eager = MidiState::new();
+        let mut history = MessageHistory::<16>::new();
+        for msg in messages {
+            eager.update(msg.clone());
+            history.push(msg);
+        }
+
+        let replayed = history.midi_state();
+        assert!(
+            replayed
+                .activated_notes
+                .iter()
+                .eq(eager.activated_notes.iter()),
+            "Expected replayed notes to match eagerly updated notes"
+        );
+        assert_eq!(
+            eager.portamento, replayed.portamento,
+            "Expected left but got right"
+        );
+        assert_eq!(
+            eager.general_purpose_switches, replayed.general_purpose_switches,
+            "Expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn matches_eager_timestamps() {
+        use embassy_time::Duration;
+
+        let (_clock, driver) = crate::mock_time::mock_driver();
+        let mut eager = MidiState::new();
+        let mut history = MessageHistory::<16>::new();
+        for msg in [
+            note_on(Note::C4),
+            note_on(Note::E4),
+            note_off(Note::C4),
+            note_on(Note::G4),
+        ] {
+            driver.advance(Duration::from_millis(10));
+            eager.update(msg.clone());
+            history.push(msg);
+        }
+
+        // replaying later must not make the notes look newer than they are
+        driver.advance(Duration::from_millis(100));
+        let replayed = history.midi_state();
+        for note in [Note::E4, Note::G4] {
+            assert_eq!(
+                eager.activated_notes.activated_at(note),
+                replayed.activated_notes.activated_at(note),
+                "Expected the replayed activation to keep the time of receipt; expected left but got right"
+            );
+        }
+        assert_eq!(
+            eager.note_on_at, replayed.note_on_at,
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn oldest_message_displaced() {
+        let mut history = MessageHistory::<2>::new();
+        history.push(note_on(Note::C4));
+        history.push(note_on(Note::E4));
+        history.push(note_on(Note::G4));
+
+        assert!(
+            history.iter().eq([&note_on(Note::E4), &note_on(Note::G4)]),
+            "Expected messages oldest first, with the first one displaced"
+        );
+        assert!(
+            history.activated_notes().iter().eq([Note::E4, Note::G4]),
+            "Expected displaced NoteOn to be forgotten"
+        );
+    }
+
+    #[test]
+    fn sysex_not_retained() {
+        let mut history = MessageHistory::<2>::new();
+        history.push(MidiMessage::SysEx(&[]));
+        assert_eq!(0, history.iter().count(), "Expected no messages");
+    }
+
+    #[test]
+    fn clear() {
+        let mut history = MessageHistory::<2>::new();
+        history.push(note_on(Note::C4));
+        history.clear();
+        assert_eq!(
+            0,
+            history.activated_notes().iter().count(),
+            "Expected no activated notes"
+        );
+    }
+}
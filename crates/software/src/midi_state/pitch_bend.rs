@@ -0,0 +1,154 @@
+//! Provides a data structure for managing the MIDI Pitch Bend controls of an instrument.
+
+use crate::configuration::{Keyboard, ProvideNote};
+use measurements::Voltage;
+
+/// The Pitch Bend Sensitivity assumed until RPN 0 says otherwise, per General MIDI.
+pub const DEFAULT_PITCH_BEND_RANGE: u8 = 2;
+
+/// The raw Pitch Bend Change value which leaves pitch unaltered.
+const PITCH_BEND_CENTER: u16 = 0x2000;
+
+/// A struct for managing the Pitch Bend controls of an instrument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PitchBend {
+    /// Pitch Bend Change, centered at zero, i.e., ranging from -8192 to +8191.
+    value: i16,
+    /// RPN 0: Pitch Bend Sensitivity, i.e., how far (in semitones) a full bend alters pitch.
+    range_semitones: u8,
+}
+
+impl PitchBend {
+    /// Constructs a new [`PitchBend`] with no bend and the [default range][DEFAULT_PITCH_BEND_RANGE].
+    pub const fn new() -> Self {
+        Self {
+            value: 0,
+            range_semitones: DEFAULT_PITCH_BEND_RANGE,
+        }
+    }
+
+    /// Constructs a [`PitchBend`] from a Pitch Bend Change value centered at zero, clamped to -8192 to +8191, and a
+    /// range in semitones.
+    pub const fn with_value(value: i16, range_semitones: u8) -> Self {
+        let max = (PITCH_BEND_CENTER - 1) as i16;
+        let min = -(PITCH_BEND_CENTER as i16);
+        Self {
+            value: if value > max {
+                max
+            } else if value < min {
+                min
+            } else {
+                value
+            },
+            range_semitones,
+        }
+    }
+
+    /// Returns the Pitch Bend Change value, centered at zero.
+    pub fn value(&self) -> i16 {
+        self.value
+    }
+
+    /// Sets the Pitch Bend Change value from the 14-bit value carried by the MIDI message.
+    pub fn set_value(&mut self, bend: wmidi::PitchBend) {
+        self.value = (u16::from(bend) as i32 - PITCH_BEND_CENTER as i32) as i16;
+    }
+
+    /// Restores the bend to center, leaving the range untouched (as Pitch Bend Sensitivity is a setting rather than a
+    /// performance control).
+    pub fn center(&mut self) {
+        self.value = 0;
+    }
+
+    /// Returns RPN 0: Pitch Bend Sensitivity, in semitones.
+    pub fn range_semitones(&self) -> u8 {
+        self.range_semitones
+    }
+
+    /// Sets RPN 0: Pitch Bend Sensitivity, in semitones.
+    pub fn set_range_semitones(&mut self, range_semitones: u8) {
+        self.range_semitones = range_semitones;
+    }
+
+    /// Returns the pitch offset of the current bend, in semitones.
+    ///
+    /// As there is one more value below the center than above it, a full bend in either direction spans exactly
+    /// [`range_semitones`][Self::range_semitones].
+    pub fn offset_semitones(&self) -> f64 {
+        let max = if self.value < 0 {
+            PITCH_BEND_CENTER
+        } else {
+            PITCH_BEND_CENTER - 1
+        };
+        self.value as f64 / max as f64 * self.range_semitones as f64
+    }
+
+    /// Returns the [`Voltage`] by which the current bend alters the pitch voiced by the [`Keyboard`].
+    pub fn offset_voltage<T: ProvideNote>(&self, keyboard: &Keyboard<T>) -> Voltage {
+        keyboard.interval_voltage(self.offset_semitones())
+    }
+}
+
+impl Default for PitchBend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmidi::U14;
+
+    fn bent(value: u16) -> PitchBend {
+        let mut pitch_bend = PitchBend::default();
+        pitch_bend.set_value(U14::try_from(value).unwrap());
+        pitch_bend
+    }
+
+    #[test]
+    fn set_value() {
+        assert_eq!(-8192, bent(0).value(), "Expected left but got right");
+        assert_eq!(0, bent(0x2000).value(), "Expected left but got right");
+        assert_eq!(8191, bent(0x3FFF).value(), "Expected left but got right");
+    }
+
+    #[test]
+    fn offset_semitones() {
+        assert_eq!(
+            -2.0,
+            bent(0).offset_semitones(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0.0,
+            bent(0x2000).offset_semitones(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            2.0,
+            bent(0x3FFF).offset_semitones(),
+            "Expected left but got right"
+        );
+
+        let mut pitch_bend = bent(0x1000);
+        pitch_bend.set_range_semitones(12);
+        assert_eq!(
+            -6.0,
+            pitch_bend.offset_semitones(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn offset_voltage() {
+        let mut pitch_bend = bent(0x3FFF);
+        pitch_bend.set_range_semitones(12);
+        assert_eq!(
+            Voltage::from_volts(1.0),
+            pitch_bend.offset_voltage(&Keyboard::default()),
+            "Expected a full bend up of an octave to add 1 V; expected left but got right"
+        );
+    }
+}
@@ -5,10 +5,12 @@ use wmidi::{ControlValue, Note};
 /// A struct for managing the Portamento controls of an instrument.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Portamento {
-    /// MIDI CC 4: Portamento On/Off
+    /// MIDI CC 65: Portamento On/Off
     enabled: bool,
-    /// MIDI CC 84: Portamento Control (glide from this note instead of the last one performed)
+    /// MIDI CC 84: Portamento Control (glide from this note instead of the last one performed), pending the next NoteOn
     origin_override: Option<Note>,
+    /// The note from which the most recent NoteOn glides, if CC 84 preceded it
+    glide_origin: Option<Note>,
     /// MIDI CC 5: Portamento Time
     time: ControlValue,
     /// MIDI CC 37: Portamento Time (Least-Significant Bits)
@@ -16,25 +18,92 @@ pub struct Portamento {
 }
 
 impl Portamento {
+    /// Constructs a new [`Portamento`] with the effect enabled and a Portamento Time of zero.
+    pub const fn new() -> Self {
+        Self {
+            enabled: true,
+            origin_override: None,
+            glide_origin: None,
+            time: ControlValue::MIN,
+            time_lsb: None,
+        }
+    }
+
+    /// Returns `true` if CC 65: Portamento On/Off is on.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Sets CC 65: Portamento On/Off
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Sets CC 84: Portamento Control, naming the note from which the next NoteOn glides.
+    pub fn set_origin_override(&mut self, note: Note) {
+        self.origin_override = Some(note);
+    }
+
+    /// Returns the note from which the most recent NoteOn glides in place of the one last performed, if CC 84:
+    /// Portamento Control preceded it.
+    ///
+    /// Per the MIDI specification, CC 84 applies to the next NoteOn alone; the origin is forgotten upon any other
+    /// NoteOn or NoteOff, so that only that note glides from it.
+    pub fn glide_origin(&self) -> Option<Note> {
+        self.glide_origin
+    }
+
+    /// Registers a NoteOn, which consumes any pending CC 84: Portamento Control; see
+    /// [`glide_origin`][Self::glide_origin].
+    pub fn note_on(&mut self) {
+        self.glide_origin = self.origin_override.take();
+    }
+
+    /// Registers a NoteOff, after which the most recent NoteOn no longer glides from a CC 84 origin.
+    pub fn note_off(&mut self) {
+        self.glide_origin = None;
+    }
+
     /// Returns the control value for CC 5: Portamento Time.
     pub fn time(&self) -> ControlValue {
         self.time
     }
 
     /// Sets the control value for CC 5: Portamento Time
+    ///
+    /// Per the MIDI specification, a new MSB invalidates any previously received LSB (CC 37), which must be sent again
+    /// (after the MSB) for high-resolution control.
     pub fn set_time(&mut self, time: ControlValue) {
         self.time = time;
+        self.time_lsb = None;
+    }
+
+    /// Returns the control value for CC 37: Portamento Time (Least-Significant Bits), if any.
+    pub fn time_lsb(&self) -> Option<ControlValue> {
+        self.time_lsb
+    }
+
+    /// Sets the control value for CC 37: Portamento Time (Least-Significant Bits)
+    pub fn set_time_lsb(&mut self, time_lsb: ControlValue) {
+        self.time_lsb = Some(time_lsb);
+    }
+
+    /// Restores every Portamento control to its default, e.g., upon CC 121: Reset All Controllers.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Returns the 14-bit Portamento Time formed by CC 5 (MSB) and CC 37 (LSB), treating a missing LSB as zero.
+    pub fn high_res_time(&self) -> u16 {
+        let msb = u16::from(u8::from(self.time));
+        let lsb = self.time_lsb.map_or(0, |lsb| u16::from(u8::from(lsb)));
+        msb << 7 | lsb
     }
 }
 
 impl Default for Portamento {
     fn default() -> Self {
-        Self {
-            enabled: true,
-            origin_override: Default::default(),
-            time: Default::default(),
-            time_lsb: Default::default(),
-        }
+        Self::new()
     }
 }
 
@@ -44,16 +113,18 @@ impl defmt::Format for Portamento {
         let Portamento {
             enabled,
             origin_override,
+            glide_origin,
             time,
             time_lsb,
         } = *self;
         defmt::write!(
             fmt,
-            "Portamento {{ enabled: {}, origin_override: {}, time: {}, time_lsb: {} }}",
+            "Portamento {{ enabled: {}, origin_override: {}, glide_origin: {}, time: {}, time_lsb: {} }}",
             enabled,
-            origin_override.map(|v| u8::from(v)),
+            origin_override.map(u8::from),
+            glide_origin.map(u8::from),
             u8::from(time),
-            time_lsb.map(|v| u8::from(v))
+            time_lsb.map(u8::from)
         );
     }
 }
@@ -68,6 +139,7 @@ mod tests {
         let p = Portamento {
             enabled: true,
             origin_override: None,
+            glide_origin: None,
             time: U7::from_u8_lossy(100),
             time_lsb: None,
         };
@@ -86,6 +158,7 @@ mod tests {
             Portamento {
                 enabled: true,
                 origin_override: None,
+                glide_origin: None,
                 time: U7::from_u8_lossy(111),
                 time_lsb: None,
             },
@@ -93,4 +166,78 @@ mod tests {
             "Expected left but got right"
         );
     }
+
+    #[test]
+    fn high_res_time() {
+        let mut p = Portamento::default();
+        p.set_time(U7::from_u8_lossy(1));
+        assert_eq!(128, p.high_res_time(), "Expected left but got right");
+
+        p.set_time_lsb(U7::from_u8_lossy(5));
+        assert_eq!(133, p.high_res_time(), "Expected left but got right");
+
+        p.set_time(U7::from_u8_lossy(2));
+        assert_eq!(
+            None,
+            p.time_lsb(),
+            "Expected a new MSB to invalidate the LSB; expected left but got right"
+        );
+        assert_eq!(256, p.high_res_time(), "Expected left but got right");
+    }
+
+    #[test]
+    fn reset() {
+        let mut p = Portamento::default();
+        p.set_enabled(false);
+        p.set_time(U7::from_u8_lossy(100));
+        p.set_time_lsb(U7::from_u8_lossy(5));
+
+        p.reset();
+        assert_eq!(Portamento::default(), p, "Expected left but got right");
+    }
+
+    #[test]
+    fn set_enabled() {
+        let mut p = Portamento::default();
+        assert!(
+            p.is_enabled(),
+            "Expected Portamento to be enabled by default"
+        );
+        p.set_enabled(false);
+        assert!(!p.is_enabled(), "Expected Portamento to be disabled");
+    }
+
+    #[test]
+    fn origin_override() {
+        let mut p = Portamento::default();
+        p.set_origin_override(Note::C3);
+        assert_eq!(
+            None,
+            p.glide_origin(),
+            "Expected CC 84 to await the next NoteOn; expected left but got right"
+        );
+
+        p.note_on();
+        assert_eq!(
+            Some(Note::C3),
+            p.glide_origin(),
+            "Expected left but got right"
+        );
+
+        p.note_on();
+        assert_eq!(
+            None,
+            p.glide_origin(),
+            "Expected CC 84 to apply to one NoteOn only; expected left but got right"
+        );
+
+        p.set_origin_override(Note::C3);
+        p.note_on();
+        p.note_off();
+        assert_eq!(
+            None,
+            p.glide_origin(),
+            "Expected a NoteOff to forget the origin; expected left but got right"
+        );
+    }
 }
@@ -0,0 +1,132 @@
+//! Provides a data structure for detecting whether a performance is legato or staccato.
+
+use super::ActivatedNotes;
+use crate::timestamp::{NoteEventTimestamp, NowProvider};
+use core::marker::PhantomData;
+
+/// Classifies each transition between notes as legato (connected) or staccato (detached), e.g., so that portamento
+/// can be applied only to legato passages.
+///
+/// A NoteOn is legato if another note is still held, or if the previous NoteOff came less than `legato_threshold`
+/// earlier; otherwise, it is staccato. Note events are timestamped by the [`NowProvider`] `C`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StaccatoDetector<C> {
+    /// Gaps between notes shorter than this many microseconds are considered legato.
+    legato_threshold_micros: u64,
+    /// When the most recent NoteOff was received.
+    last_note_off: Option<NoteEventTimestamp>,
+    /// The classification of the most recent NoteOn.
+    is_legato: bool,
+    clock: PhantomData<C>,
+}
+
+impl<C: NowProvider> StaccatoDetector<C> {
+    /// The default `legato_threshold`, in microseconds: short enough that only keypresses which are effectively
+    /// simultaneous (e.g., within a chord) count as connected.
+    pub const DEFAULT_LEGATO_THRESHOLD_MICROS: u64 = 10_000;
+
+    /// Constructs a new [`StaccatoDetector`] with the
+    /// [`DEFAULT_LEGATO_THRESHOLD_MICROS`][Self::DEFAULT_LEGATO_THRESHOLD_MICROS].
+    pub const fn new() -> Self {
+        Self::with_legato_threshold(Self::DEFAULT_LEGATO_THRESHOLD_MICROS)
+    }
+
+    /// Constructs a new [`StaccatoDetector`] with the given `legato_threshold`, in microseconds.
+    pub const fn with_legato_threshold(legato_threshold_micros: u64) -> Self {
+        Self {
+            legato_threshold_micros,
+            last_note_off: None,
+            is_legato: false,
+            clock: PhantomData,
+        }
+    }
+
+    /// To be called upon receipt of a NoteOff.
+    pub fn note_off(&mut self) {
+        self.note_off_at(C::now());
+    }
+
+    fn note_off_at(&mut self, now: NoteEventTimestamp) {
+        self.last_note_off = Some(now);
+    }
+
+    /// To be called upon receipt of a NoteOn, before the note is added to `activated_notes`, classifying the
+    /// transition to the new note.
+    pub fn note_on(&mut self, activated_notes: &ActivatedNotes) {
+        self.note_on_at(activated_notes, C::now());
+    }
+
+    fn note_on_at(&mut self, activated_notes: &ActivatedNotes, now: NoteEventTimestamp) {
+        self.is_legato = !activated_notes.is_empty()
+            || self
+                .last_note_off
+                .is_some_and(|at| now.micros_since(at) < self.legato_threshold_micros);
+    }
+
+    /// Returns `true` if the most recent NoteOn was legato, `false` if it was staccato (or if there has been none).
+    pub fn is_legato(&self) -> bool {
+        self.is_legato
+    }
+}
+
+impl<C: NowProvider> Default for StaccatoDetector<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wmidi::Note;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Epoch;
+
+    impl NowProvider for Epoch {
+        fn now() -> NoteEventTimestamp {
+            NoteEventTimestamp::default()
+        }
+    }
+
+    type StaccatoDetector = super::StaccatoDetector<Epoch>;
+
+    #[test]
+    fn first_note_is_staccato() {
+        let mut detector = StaccatoDetector::new();
+        detector.note_on(&ActivatedNotes::new());
+        assert!(!detector.is_legato(), "Expected staccato");
+    }
+
+    #[test]
+    fn overlapping_notes_are_legato() {
+        let mut held = ActivatedNotes::new();
+        held.add(Note::C4);
+
+        let mut detector = StaccatoDetector::new();
+        detector.note_on(&held);
+        assert!(detector.is_legato(), "Expected legato");
+    }
+
+    #[test]
+    fn gap_within_threshold_is_legato() {
+        let mut detector = StaccatoDetector::new();
+        detector.note_off_at(NoteEventTimestamp::from_micros(1_000));
+        detector.note_on_at(
+            &ActivatedNotes::new(),
+            NoteEventTimestamp::from_micros(6_000),
+        );
+        assert!(detector.is_legato(), "Expected legato");
+    }
+
+    #[test]
+    fn gap_beyond_threshold_is_staccato() {
+        let mut detector = StaccatoDetector::with_legato_threshold(50_000);
+        detector.note_off_at(NoteEventTimestamp::from_micros(1_000));
+        detector.note_on_at(
+            &ActivatedNotes::new(),
+            NoteEventTimestamp::from_micros(51_000),
+        );
+        assert!(!detector.is_legato(), "Expected staccato");
+    }
+}
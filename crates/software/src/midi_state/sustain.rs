@@ -0,0 +1,145 @@
+//! Provides a data structure for managing the MIDI Sustain (i.e., damper pedal) control of an instrument.
+
+use super::ActivatedNotes;
+use crate::timestamp::NoteEventTimestamp;
+use wmidi::{Channel, Note, U7};
+
+/// A struct for managing the Sustain control (MIDI CC 64) of an instrument.
+///
+/// As with a piano's damper pedal, any note released while the pedal is down remains activated until the pedal is
+/// lifted, whether it was pressed before or after the pedal went down.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sustain {
+    /// MIDI CC 64: Damper Pedal On/Off
+    engaged: bool,
+    /// Notes which were released while the pedal was down.
+    released_notes: ActivatedNotes,
+}
+
+impl Sustain {
+    /// Constructs a new [`Sustain`] with the pedal up.
+    pub const fn new() -> Self {
+        Self {
+            engaged: false,
+            released_notes: ActivatedNotes::new(),
+        }
+    }
+
+    /// Returns `true` if the pedal is down.
+    pub fn is_engaged(&self) -> bool {
+        self.engaged
+    }
+
+    /// Puts the pedal down. Does nothing if the pedal is already down.
+    pub fn engage(&mut self) {
+        if !self.engaged {
+            self.engaged = true;
+            self.released_notes = ActivatedNotes::new();
+        }
+    }
+
+    /// Lifts the pedal, returning the sustained notes which were released while it was down and should now be
+    /// deactivated.
+    pub fn disengage(&mut self) -> ActivatedNotes {
+        let released_notes = self.released_notes;
+        *self = Self::new();
+        released_notes
+    }
+
+    /// To be called upon receipt of a NoteOff. Returns `true` if the note is sustained, i.e., it should remain
+    /// activated on `channel` until the pedal is lifted.
+    pub fn hold(&mut self, channel: Channel, note: Note) -> bool {
+        if self.engaged {
+            // only which notes were released on which channels matters, not the details of their activation
+            self.released_notes.add_note_on_at(
+                channel,
+                note,
+                U7::MIN,
+                NoteEventTimestamp::default(),
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    /// To be called upon receipt of a NoteOn. A sustained note which is pressed again on the same channel should no
+    /// longer be deactivated when the pedal is lifted.
+    pub fn press(&mut self, channel: Channel, note: Note) {
+        self.released_notes.remove_note_off(channel, note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hold() {
+        let mut sustain = Sustain::new();
+        sustain.engage();
+        assert!(
+            sustain.hold(Channel::Ch1, Note::C4),
+            "Expected C4 to be sustained"
+        );
+        assert!(
+            sustain.hold(Channel::Ch1, Note::E4),
+            "Expected a note pressed after the pedal to be sustained as well"
+        );
+    }
+
+    #[test]
+    fn hold_when_disengaged() {
+        let mut sustain = Sustain::new();
+        assert!(
+            !sustain.hold(Channel::Ch1, Note::C4),
+            "Expected nothing to be sustained without the pedal"
+        );
+    }
+
+    #[test]
+    fn engage_twice() {
+        let mut sustain = Sustain::new();
+        sustain.engage();
+        sustain.hold(Channel::Ch1, Note::C4);
+        sustain.engage();
+        assert!(
+            sustain.disengage().iter().eq([Note::C4]),
+            "Expected a repeated pedal down not to forget the notes it sustained"
+        );
+    }
+
+    #[test]
+    fn disengage() {
+        let mut sustain = Sustain::new();
+        sustain.engage();
+        sustain.hold(Channel::Ch1, Note::C4);
+        sustain.hold(Channel::Ch1, Note::G4);
+        sustain.hold(Channel::Ch1, Note::G4);
+        sustain.press(Channel::Ch1, Note::G4);
+
+        assert!(
+            sustain.disengage().iter().eq([Note::C4]),
+            "Expected only sustained notes which are no longer pressed to be released"
+        );
+        assert!(!sustain.is_engaged(), "Expected pedal to be up");
+        assert!(
+            !sustain.hold(Channel::Ch1, Note::E4),
+            "Expected nothing to be sustained after the pedal is lifted"
+        );
+    }
+
+    #[test]
+    fn press_on_another_channel() {
+        let mut sustain = Sustain::new();
+        sustain.engage();
+        sustain.hold(Channel::Ch1, Note::C4);
+        sustain.press(Channel::Ch2, Note::C4);
+
+        assert!(
+            sustain.disengage().iter().eq([Note::C4]),
+            "Expected pressing the note on another channel not to cancel its release"
+        );
+    }
+}
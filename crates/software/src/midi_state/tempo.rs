@@ -0,0 +1,212 @@
+//! Provides a data structure for estimating the tempo of incoming MIDI Timing Clock.
+
+use core::marker::PhantomData;
+
+use crate::{
+    configuration::PULSES_PER_QUARTER_NOTE,
+    timestamp::{NoteEventTimestamp, NowProvider},
+};
+
+/// The number of intervals between Timing Clock pulses averaged into the tempo, smoothing out jitter in their delivery.
+const TEMPO_WINDOW: usize = 8;
+
+/// How long may pass without a Timing Clock pulse before the tempo is no longer known, in microseconds.
+pub const CLOCK_TIMEOUT_MICROS: u64 = 2_000_000;
+
+/// A struct for estimating tempo from the intervals between MIDI Timing Clock pulses, which arrive 24 times per beat.
+///
+/// Pulses are timestamped by the [`NowProvider`] `C`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tempo<C> {
+    /// The most recent intervals between pulses, in microseconds; once full, the oldest is overwritten first.
+    intervals: [u32; TEMPO_WINDOW],
+    /// The number of intervals recorded, up to [`TEMPO_WINDOW`].
+    interval_cnt: u8,
+    /// The index at which the next interval is recorded.
+    next_interval: u8,
+    /// When the most recent pulse arrived, if one has.
+    last_pulse: Option<NoteEventTimestamp>,
+    clock: PhantomData<C>,
+}
+
+impl<C: NowProvider> Default for Tempo<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: NowProvider> Tempo<C> {
+    /// Constructs a new [`Tempo`] reflecting that no clock has been received.
+    pub const fn new() -> Self {
+        Self {
+            intervals: [0; TEMPO_WINDOW],
+            interval_cnt: 0,
+            next_interval: 0,
+            last_pulse: None,
+            clock: PhantomData,
+        }
+    }
+
+    /// Registers a Timing Clock pulse received now.
+    ///
+    /// A pulse arriving more than [`CLOCK_TIMEOUT_MICROS`] after the previous one starts the estimate over, as the gap
+    /// reflects a stopped clock rather than the tempo.
+    pub fn tick(&mut self) {
+        self.tick_at(C::now());
+    }
+
+    fn tick_at(&mut self, now: NoteEventTimestamp) {
+        if let Some(last_pulse) = self.last_pulse {
+            let interval = now.micros_since(last_pulse);
+            if interval > CLOCK_TIMEOUT_MICROS {
+                *self = Self::new();
+            } else if interval > 0 {
+                self.intervals[usize::from(self.next_interval)] = interval as u32;
+                self.next_interval = (self.next_interval + 1) % TEMPO_WINDOW as u8;
+                self.interval_cnt = (self.interval_cnt + 1).min(TEMPO_WINDOW as u8);
+            }
+        }
+        self.last_pulse = Some(now);
+    }
+
+    /// Returns the tempo in beats per minute, rounded to the nearest whole number so that jitter doesn't register as a
+    /// change, or `None` if fewer than two pulses have been received.
+    pub fn bpm(&self) -> Option<f32> {
+        if self.interval_cnt == 0 {
+            return None;
+        }
+        let total: u64 = self.intervals[..usize::from(self.interval_cnt)]
+            .iter()
+            .map(|&interval| u64::from(interval))
+            .sum();
+        let pulse_micros = total as f32 / f32::from(self.interval_cnt);
+        let bpm = 60_000_000.0 / (pulse_micros * PULSES_PER_QUARTER_NOTE as f32);
+        // `f32::round` isn't available without `std`; the tempo is always positive, so truncation rounds half up
+        Some((bpm + 0.5) as u32 as f32)
+    }
+
+    /// Returns when the tempo will no longer be known unless another pulse arrives, or `None` if none has.
+    pub fn expiry(&self) -> Option<NoteEventTimestamp> {
+        self.last_pulse.map(|last_pulse| {
+            NoteEventTimestamp::from_micros(last_pulse.as_micros() + CLOCK_TIMEOUT_MICROS)
+        })
+    }
+
+    /// Forgets the tempo if no pulse has arrived within the last [`CLOCK_TIMEOUT_MICROS`].
+    pub fn expire(&mut self) {
+        self.expire_at(C::now());
+    }
+
+    fn expire_at(&mut self, now: NoteEventTimestamp) {
+        if self.expiry().is_some_and(|expiry| now >= expiry) {
+            *self = Self::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    static MICROS: AtomicU64 = AtomicU64::new(0);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct FakeClock;
+
+    impl NowProvider for FakeClock {
+        fn now() -> NoteEventTimestamp {
+            NoteEventTimestamp::from_micros(MICROS.load(Ordering::Relaxed))
+        }
+    }
+
+    type Tempo = super::Tempo<FakeClock>;
+
+    /// A pulse at 125 BPM.
+    const PULSE_MICROS: u64 = 20_000;
+
+    fn pulses(tempo: &mut Tempo, start: u64, interval: u64, cnt: u64) -> u64 {
+        (0..cnt).for_each(|i| tempo.tick_at(NoteEventTimestamp::from_micros(start + i * interval)));
+        start + (cnt - 1) * interval
+    }
+
+    #[test]
+    fn bpm() {
+        let mut tempo = Tempo::new();
+        assert_eq!(None, tempo.bpm(), "Expected no tempo before any clock");
+
+        tempo.tick_at(NoteEventTimestamp::from_micros(0));
+        assert_eq!(None, tempo.bpm(), "Expected no tempo from a single pulse");
+
+        pulses(&mut tempo, 0, PULSE_MICROS, 3);
+        assert_eq!(Some(125.0), tempo.bpm(), "Expected left but got right");
+    }
+
+    #[test]
+    fn bpm_averages_recent_intervals() {
+        let mut tempo = Tempo::new();
+        let last = pulses(&mut tempo, 0, 25_000, 9);
+        assert_eq!(Some(100.0), tempo.bpm(), "Expected left but got right");
+
+        // a single late pulse is averaged out
+        tempo.tick_at(NoteEventTimestamp::from_micros(last + 27_000));
+        assert_eq!(Some(99.0), tempo.bpm(), "Expected left but got right");
+
+        // once the window holds only the new tempo, the old one no longer counts
+        pulses(&mut tempo, last + 27_000, PULSE_MICROS, 9);
+        assert_eq!(Some(125.0), tempo.bpm(), "Expected left but got right");
+    }
+
+    #[test]
+    fn tick_after_timeout() {
+        let mut tempo = Tempo::new();
+        let last = pulses(&mut tempo, 0, 25_000, 9);
+        tempo.tick_at(NoteEventTimestamp::from_micros(
+            last + CLOCK_TIMEOUT_MICROS + 1,
+        ));
+        assert_eq!(
+            None,
+            tempo.bpm(),
+            "Expected the pause not to be taken as a tempo"
+        );
+    }
+
+    #[test]
+    fn expire() {
+        let mut tempo = Tempo::new();
+        let last = pulses(&mut tempo, 0, PULSE_MICROS, 3);
+        assert_eq!(
+            Some(NoteEventTimestamp::from_micros(last + CLOCK_TIMEOUT_MICROS)),
+            tempo.expiry(),
+            "Expected left but got right"
+        );
+
+        tempo.expire_at(NoteEventTimestamp::from_micros(last + PULSE_MICROS));
+        assert_eq!(
+            Some(125.0),
+            tempo.bpm(),
+            "Expected the tempo to be kept while the clock runs"
+        );
+
+        tempo.expire_at(NoteEventTimestamp::from_micros(last + CLOCK_TIMEOUT_MICROS));
+        assert_eq!(None, tempo.bpm(), "Expected the tempo to be forgotten");
+        assert_eq!(None, tempo.expiry(), "Expected left but got right");
+    }
+
+    #[test]
+    fn custom_clock() {
+        let mut tempo = Tempo::new();
+        [0, PULSE_MICROS, 2 * PULSE_MICROS]
+            .iter()
+            .for_each(|&micros| {
+                MICROS.store(micros, Ordering::Relaxed);
+                tempo.tick();
+            });
+        assert_eq!(Some(125.0), tempo.bpm(), "Expected left but got right");
+
+        MICROS.store(2 * PULSE_MICROS + CLOCK_TIMEOUT_MICROS, Ordering::Relaxed);
+        tempo.expire();
+        assert_eq!(None, tempo.bpm(), "Expected the tempo to be forgotten");
+    }
+}
@@ -0,0 +1,22 @@
+//! Provides exclusive access to Embassy's mock time driver for tests.
+//!
+//! The driver is process-wide, whereas the default test runner runs tests in parallel, so a test which advances or
+//! resets the clock would otherwise disturb any other test reading it at the same time.
+
+extern crate std;
+
+use embassy_time::MockDriver;
+use std::sync::{Mutex, MutexGuard};
+
+static CLOCK: Mutex<()> = Mutex::new(());
+
+/// Resets the [`MockDriver`], returning it along with a guard which keeps other tests from using it until dropped.
+pub(crate) fn mock_driver() -> (MutexGuard<'static, ()>, &'static MockDriver) {
+    // a failed test poisons the lock, but the clock is reset all the same, so the next test can use it
+    let guard = CLOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let driver = MockDriver::get();
+    driver.reset();
+    (guard, driver)
+}
@@ -1,14 +1,19 @@
 //! Provides struct for managing intra-note states, i.e., gliding from one note to another.
 
-use crate::configuration::{Keyboard, ProvideNote};
-use core::{future::poll_fn, task::Poll};
-use embassy_time::{Duration, Instant};
+use crate::{
+    configuration::{Keyboard, PortamentoTimeCurve, ProvideNote, VelocityGlide},
+    timestamp::{EmbassyNow, NoteEventTimestamp, NowProvider},
+};
+use core::{future::poll_fn, marker::PhantomData, task::Poll};
+use embassy_time::Duration;
 use measurements::Voltage;
-use wmidi::{ControlValue, Note};
+use wmidi::{ControlValue, Note, U7};
 
 /// Contains data necessary to execute a portamento or glide effect.
-#[derive(Clone, Debug, PartialEq)]
-pub struct Portamento<T> {
+///
+/// The current time is read from the [`NowProvider`] `C`, which defaults to Embassy's clock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Portamento<T, C = EmbassyNow> {
     /// Indicates the starting point of the glide.
     ///
     /// Uses [`Voltage`] instead of [`Note`] so that intra-note state can be represented, as
@@ -16,37 +21,72 @@ pub struct Portamento<T> {
     origin: Voltage,
     /// Indicates the end of the glide; when this [`Note`] is reached, there is nothing left to do.
     destination: Note,
-    /// The [`Instant`] at which the glide began.
-    start: Instant,
+    /// The point in time at which the glide began.
+    start: NoteEventTimestamp,
     /// How long after the `start` to stretch the effect.
     duration: Duration,
+    /// Determines how a Portamento Time control value maps to `duration`.
+    time_curve: PortamentoTimeCurve,
+    /// Scales `duration` according to the velocity of the destination note (see [`VelocityGlide`]).
+    velocity_factor: f64,
     /// Keyboard configuration.
     ///
     /// Voltages can't be calculated without the context of the keyboard, but it's possible adding
     /// them to this struct is not the best way of sharing that data.
     keyboard: Keyboard<T>,
+    /// The source of the current time.
+    clock: PhantomData<C>,
 }
 
-impl<T> Portamento<T>
+impl<T, C> Portamento<T, C>
 where
     T: ProvideNote,
+    C: NowProvider,
 {
-    /// The Portamento Time control value is scaled against this constant such that the max value will have a [`Duration`] of `MAX_GLIDE_TIME`.
-    ///
-    /// The value for this constant was selected to match the built-in behavior of the Micromoog.
-    const MAX_GLIDE_TIME: Duration = Duration::from_secs(5);
-
-    /// Constructs a new [`Portamento`].
+    /// Constructs a new [`Portamento`] using the default [`PortamentoTimeCurve`].
     pub fn new(origin: Note, destination: Note, time: ControlValue, keyboard: Keyboard<T>) -> Self {
+        let time_curve = PortamentoTimeCurve::default();
         Self {
             origin: keyboard.voltage(origin),
-            destination: destination,
-            start: Instant::now(),
-            duration: Self::MAX_GLIDE_TIME * u8::from(time).into() / 127,
+            destination,
+            start: C::now(),
+            duration: time_curve.duration(time),
+            time_curve,
+            velocity_factor: 1.0,
             keyboard,
+            clock: PhantomData,
         }
     }
 
+    /// Constructs a new [`Portamento`] which glides at a constant rate, such that its duration depends on the distance
+    /// between `origin` and `destination`.
+    pub fn from_rate(
+        origin: Note,
+        destination: Note,
+        semitones_per_second: f32,
+        keyboard: Keyboard<T>,
+    ) -> Self {
+        let mut portamento = Self::new(origin, destination, ControlValue::default(), keyboard);
+        portamento.adjust_duration_for_interval(
+            u8::from(origin).abs_diff(u8::from(destination)),
+            semitones_per_second,
+        );
+        portamento
+    }
+
+    /// Given an interval, sets the duration of the glide such that it proceeds at the given rate.
+    ///
+    /// A rate that is not positive results in instant note changes.
+    pub fn adjust_duration_for_interval(&mut self, semitones: u8, semitones_per_second: f32) {
+        self.duration = if semitones_per_second > 0.0 {
+            Duration::from_micros(
+                (f32::from(semitones) / semitones_per_second * 1_000_000.0) as u64,
+            )
+        } else {
+            Duration::from_micros(0)
+        };
+    }
+
     /// Given a new destination, constructs a new [`Portamento`] using the existing one as a template.
     ///
     /// This is especially useful for starting a glide from in-between [`Note`]s.
@@ -54,11 +94,21 @@ where
         Self {
             origin: self.voltage(),
             destination,
-            start: Instant::now(),
+            start: C::now(),
             ..self
         }
     }
 
+    /// Restarts the glide from the exact pitch of `note`, keeping the destination, duration, and curve, e.g., so that a
+    /// glide triggered by CC 84 (Portamento Control) begins from the note it names.
+    ///
+    /// Unlike [`new_destination`][Self::new_destination], which starts from wherever the current glide has reached,
+    /// this discards the current position entirely.
+    pub fn set_origin_note(&mut self, note: Note) {
+        self.origin = self.keyboard.voltage(note);
+        self.start = C::now();
+    }
+
     /// Returns a [`Future`] of the Portamento's current [`Voltage`] which resolves until the destination voltage is reached.
     pub fn glide(&self) -> impl Future<Output = Voltage> {
         poll_fn(|_| {
@@ -87,9 +137,31 @@ where
         self.duration
     }
 
-    /// Given a Portamento Time control value, sets the duration of the glide.
+    /// Given a Portamento Time control value, sets the duration of the glide per the [`PortamentoTimeCurve`].
     pub fn set_duration(&mut self, time: ControlValue) {
-        self.duration = Self::MAX_GLIDE_TIME * u8::from(time).into() / 127;
+        self.duration = self.time_curve.duration(time);
+    }
+
+    /// Given a 14-bit Portamento Time (see [`high_res_time`][crate::midi_state::Portamento::high_res_time]), sets the
+    /// duration of the glide per the [`PortamentoTimeCurve`].
+    pub fn set_high_res_duration(&mut self, time: u16) {
+        self.duration = self.time_curve.high_res_duration(time);
+    }
+
+    /// Sets the [`PortamentoTimeCurve`] used by subsequent calls to [`set_duration`][Self::set_duration] and
+    /// [`set_high_res_duration`][Self::set_high_res_duration].
+    pub fn set_time_curve(&mut self, time_curve: PortamentoTimeCurve) {
+        self.time_curve = time_curve;
+    }
+
+    /// Given the velocity of the destination [`Note`], scales the duration of the glide per the [`VelocityGlide`].
+    pub fn set_velocity(&mut self, velocity: U7, velocity_glide: VelocityGlide) {
+        self.velocity_factor = velocity_glide.factor(velocity);
+    }
+
+    /// Returns the duration of the glide after scaling for velocity.
+    fn effective_duration(&self) -> Duration {
+        Duration::from_micros((self.duration.as_micros() as f64 * self.velocity_factor) as u64)
     }
 
     /// Returns a [`Voltage`] representing the voicing (which may be between [`Note`]s) at the current position in the glide.
@@ -101,52 +173,64 @@ where
         self.origin + journey_so_far
     }
 
-    /// Indicates progress through the duration of the glide as a decimal fraction.
-    fn progress(&self) -> f64 {
-        let now = Instant::now();
-        let time_gliding = now - self.start;
+    /// Indicates progress through the duration of the glide as a decimal fraction, from `0.0` to `1.0`.
+    pub fn progress(&self) -> f64 {
+        let time_gliding = C::now().micros_since(self.start);
+        let duration = self.effective_duration();
 
         // if the portamento time has been reduced so much that the glide should have
         // already finished (or if the call to this method was for some reason so delayed),
         // progress is 100% and the portamento should end
-        if time_gliding >= self.duration {
+        if time_gliding >= duration.as_micros() {
             1.0
         } else {
-            time_gliding.as_micros() as f64 / self.duration.as_micros() as f64
+            time_gliding as f64 / duration.as_micros() as f64
         }
     }
 }
 
+#[cfg(feature = "defmt")]
+impl<T, C> defmt::Format for Portamento<T, C>
+where
+    T: ProvideNote,
+    C: NowProvider,
+{
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "Portamento {{ origin: {} V, destination: {}, elapsed: {} us, duration: {} us, progress: {}% }}",
+            self.origin.as_volts(),
+            u8::from(self.destination),
+            C::now().micros_since(self.start),
+            self.effective_duration().as_micros(),
+            self.progress() * 100.0
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::configuration::NotePriority;
-    use embassy_time::MockDriver;
+    use crate::mock_time::mock_driver;
     use wmidi::U7;
 
     fn keyboard() -> Keyboard<NotePriority> {
-        Keyboard::new(
-            NotePriority::Low,
-            Note::F3..=Note::C6,
-            Voltage::from_volts(1.0),
-        )
-    }
-
-    fn time_driver() -> &'static MockDriver {
-        let driver = MockDriver::get();
-        driver.reset();
-        driver
+        Keyboard::default()
     }
 
     #[test]
     fn new_destination() {
-        let driver = time_driver();
-        let portamento_in_progress = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento_in_progress: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.75), // this is a D4
             destination: Note::D5,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(2500),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         driver.advance(Duration::from_millis(500));
@@ -155,24 +239,63 @@ mod tests {
             Portamento {
                 origin: Voltage::from_volts(0.95), // somewhere between E4 and F4
                 destination: Note::C4,
-                start: Instant::now(),
+                start: EmbassyNow::now(),
                 duration: Duration::from_millis(2500),
+                time_curve: PortamentoTimeCurve::Linear,
+                velocity_factor: 1.0,
                 keyboard: keyboard(),
+                clock: PhantomData,
             },
             portamento_in_progress.new_destination(Note::C4),
             "Expected left but got right"
         );
     }
 
+    #[test]
+    fn set_origin_note() {
+        let (_clock, driver) = mock_driver();
+        let mut portamento: Portamento<_> = Portamento {
+            origin: Voltage::from_volts(0.75), // this is a D4
+            destination: Note::D5,
+            start: EmbassyNow::now(),
+            duration: Duration::from_millis(2500),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+
+        driver.advance(Duration::from_millis(500));
+        portamento.set_origin_note(Note::F3);
+
+        assert_eq!(
+            Portamento {
+                origin: Voltage::from_volts(0.0),
+                destination: Note::D5,
+                start: EmbassyNow::now(),
+                duration: Duration::from_millis(2500),
+                time_curve: PortamentoTimeCurve::Linear,
+                velocity_factor: 1.0,
+                keyboard: keyboard(),
+                clock: PhantomData,
+            },
+            portamento,
+            "Expected left but got right"
+        );
+    }
+
     #[test]
     fn glide_up() {
-        let driver = time_driver();
-        let portamento = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.75), // this is a D4
             destination: Note::D5,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(1000),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         driver.advance(Duration::from_millis(500));
@@ -186,13 +309,16 @@ mod tests {
 
     #[test]
     fn glide_down() {
-        let driver = time_driver();
-        let portamento = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(1.75), // this is a D5
             destination: Note::D4,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(1000),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         driver.advance(Duration::from_millis(500));
@@ -206,13 +332,16 @@ mod tests {
 
     #[test]
     fn glide_disabled() {
-        let driver = time_driver();
-        let portamento = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.75), // this is a D4
             destination: Note::D5,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(0),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         driver.advance(Duration::from_millis(0));
@@ -226,13 +355,16 @@ mod tests {
 
     #[test]
     fn glide_late() {
-        let driver = time_driver();
-        let portamento = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.75), // this is a D4
             destination: Note::D5,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(1000),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         driver.advance(Duration::from_millis(1111));
@@ -246,12 +378,15 @@ mod tests {
 
     #[test]
     fn set_duration() {
-        let mut portamento = Portamento {
+        let mut portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.0),
             destination: Note::C4,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(0),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         portamento.set_duration(U7::from_u8_lossy(127));
@@ -276,15 +411,145 @@ mod tests {
         );
     }
 
+    #[test]
+    fn set_duration_fine() {
+        let mut portamento: Portamento<_> = Portamento {
+            origin: Voltage::from_volts(0.0),
+            destination: Note::C4,
+            start: EmbassyNow::now(),
+            duration: Duration::from_millis(0),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+
+        portamento.set_time_curve(PortamentoTimeCurve::Fine);
+        portamento.set_duration(U7::from_u8_lossy(64));
+        assert_eq!(
+            Duration::from_secs(1),
+            portamento.duration,
+            "Duration should follow the configured time curve; expected left got right"
+        );
+    }
+
+    #[test]
+    fn set_high_res_duration() {
+        let mut portamento: Portamento<_> = Portamento {
+            origin: Voltage::from_volts(0.0),
+            destination: Note::C4,
+            start: EmbassyNow::now(),
+            duration: Duration::from_millis(0),
+            time_curve: PortamentoTimeCurve::Fine,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+
+        portamento.set_high_res_duration(64 << 7 | 64);
+        assert_eq!(
+            Duration::from_micros(1_031_253),
+            portamento.duration,
+            "Duration should follow the configured time curve at full resolution; expected left got right"
+        );
+    }
+
+    #[test]
+    fn adjust_duration_for_interval() {
+        let mut portamento: Portamento<_> = Portamento {
+            origin: Voltage::from_volts(0.0),
+            destination: Note::C4,
+            start: EmbassyNow::now(),
+            duration: Duration::from_millis(0),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+
+        portamento.adjust_duration_for_interval(12, 12.0);
+        assert_eq!(
+            Duration::from_secs(1),
+            portamento.duration,
+            "Expected an octave at 12 semitones per second to take one second; expected left got right"
+        );
+
+        portamento.adjust_duration_for_interval(3, 12.0);
+        assert_eq!(
+            Duration::from_millis(250),
+            portamento.duration,
+            "Expected duration to scale with the interval; expected left got right"
+        );
+
+        portamento.adjust_duration_for_interval(12, 0.0);
+        assert_eq!(
+            Duration::from_millis(0),
+            portamento.duration,
+            "Expected a rate of zero to yield instant note changes; expected left got right"
+        );
+    }
+
+    #[test]
+    fn from_rate() {
+        let portamento: Portamento<_> = Portamento::from_rate(Note::C5, Note::C4, 12.0, keyboard());
+        assert_eq!(
+            Duration::from_secs(1),
+            portamento.duration(),
+            "Expected left got right"
+        );
+    }
+
+    #[test]
+    fn set_velocity() {
+        let (_clock, driver) = mock_driver();
+        let mut portamento: Portamento<_> = Portamento {
+            origin: Voltage::from_volts(0.0),
+            destination: Note::F4,
+            start: EmbassyNow::now(),
+            duration: Duration::from_millis(100),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+        portamento.set_velocity(U7::from_u8_lossy(127), VelocityGlide::Off);
+        driver.advance(Duration::from_millis(50));
+        assert_eq!(
+            0.5,
+            portamento.progress(),
+            "Expected velocity to have no effect; expected left but got right"
+        );
+
+        portamento.set_velocity(U7::MAX, VelocityGlide::HarderIsFaster);
+        assert!(
+            portamento.is_done(),
+            "Expected a note struck at maximum velocity to change instantly"
+        );
+
+        portamento.set_velocity(U7::MAX, VelocityGlide::HarderIsSlower);
+        assert_eq!(
+            Duration::from_millis(100),
+            portamento.duration(),
+            "Expected the configured duration to be unaffected by velocity; expected left but got right"
+        );
+        assert!(
+            !portamento.is_done(),
+            "Expected a note struck at maximum velocity to glide for the full duration"
+        );
+    }
+
     #[test]
     fn is_done() {
-        let driver = time_driver();
-        let portamento = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.0),
             destination: Note::F4,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(100),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
         assert!(!portamento.is_done(), "Expected portamento not to be done");
 
@@ -292,17 +557,46 @@ mod tests {
         assert!(portamento.is_done(), "Expected portamento to be done");
     }
 
+    #[test]
+    fn progress() {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
+            origin: Voltage::from_volts(0.0),
+            destination: Note::F4,
+            start: EmbassyNow::now(),
+            duration: Duration::from_millis(100),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+        assert_eq!(0.0, portamento.progress(), "Expected left but got right");
+
+        driver.advance(Duration::from_millis(25));
+        assert_eq!(0.25, portamento.progress(), "Expected left but got right");
+
+        driver.advance(Duration::from_millis(100));
+        assert_eq!(
+            1.0,
+            portamento.progress(),
+            "Should not exceed 100%; expected left but got right"
+        );
+    }
+
     #[test]
     fn glide() {
         use embassy_futures::poll_once;
 
-        let driver = time_driver();
-        let portamento = Portamento {
+        let (_clock, driver) = mock_driver();
+        let portamento: Portamento<_> = Portamento {
             origin: Voltage::from_volts(0.0),
             destination: Note::F4,
-            start: Instant::now(),
+            start: EmbassyNow::now(),
             duration: Duration::from_millis(100),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
             keyboard: keyboard(),
+            clock: PhantomData,
         };
 
         assert!(
@@ -322,4 +616,35 @@ mod tests {
             "Expected portamento to go into pending state upon reaching destination"
         );
     }
+
+    #[test]
+    fn custom_clock() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+
+        static MICROS: AtomicU64 = AtomicU64::new(0);
+        struct FakeClock;
+        impl NowProvider for FakeClock {
+            fn now() -> NoteEventTimestamp {
+                NoteEventTimestamp::from_micros(MICROS.load(Ordering::Relaxed))
+            }
+        }
+
+        let portamento: Portamento<_, FakeClock> = Portamento {
+            origin: Voltage::from_volts(0.75), // this is a D4
+            destination: Note::D5,
+            start: FakeClock::now(),
+            duration: Duration::from_millis(1000),
+            time_curve: PortamentoTimeCurve::Linear,
+            velocity_factor: 1.0,
+            keyboard: keyboard(),
+            clock: PhantomData,
+        };
+
+        MICROS.store(500_000, Ordering::Relaxed);
+        assert_eq!(
+            Voltage::from_volts(1.25),
+            portamento.voltage(),
+            "Expected glide to follow the provided clock rather than Embassy's"
+        );
+    }
 }
@@ -0,0 +1,302 @@
+//! Provides [`SysExRouter`] for dispatching completed System Exclusive messages, keeping SysEx handling separate from
+//! [`MidiState::update`][crate::midi_state::MidiState::update].
+
+/// Manufacturer ID reserved for non-commercial use, under which device-specific messages are exchanged.
+const NON_COMMERCIAL: u8 = 0x7D;
+/// Manufacturer ID for Universal Non-Real-Time messages.
+const UNIVERSAL_NON_REAL_TIME: u8 = 0x7E;
+/// Manufacturer ID for Universal Real-Time messages.
+const UNIVERSAL_REAL_TIME: u8 = 0x7F;
+/// Device ID which addresses every device, regardless of its own ID.
+const ALL_CALL: u8 = 0x7F;
+
+/// Sub-ID #1 of Universal Non-Real-Time messages concerning General Information.
+const GENERAL_INFORMATION: u8 = 0x06;
+/// Sub-ID #2 of a General Information Identity Request.
+const IDENTITY_REQUEST: u8 = 0x01;
+/// Sub-ID #2 of a General Information Identity Reply.
+const IDENTITY_REPLY: u8 = 0x02;
+
+/// The most payload bytes a [`SysExAssembler`] retains; ample for every message [`SysExRouter`] dispatches on.
+const ASSEMBLED_PAYLOAD_LEN: usize = 16;
+
+/// The response, if any, warranted by a System Exclusive message.
+#[derive(Debug, PartialEq)]
+pub enum SysExResponse<'a> {
+    /// Nothing to do.
+    None,
+    /// A complete System Exclusive message (including the start and end bytes) to send back to the host.
+    Reply(&'a [u8]),
+    /// The host requested a dump of the [`MidiEventLog`][crate::event_log::MidiEventLog], which the caller owns.
+    #[cfg(feature = "event_log")]
+    EventLogDump,
+}
+
+/// Dispatches System Exclusive messages based on their manufacturer ID and sub-IDs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SysExRouter {
+    /// Reply to an Identity Request, prepared once up front since it never changes.
+    identity_reply: [u8; 15],
+}
+
+impl SysExRouter {
+    /// Constructs a [`SysExRouter`] for a device with the given ID.
+    pub const fn new(device_id: u8) -> Self {
+        Self {
+            identity_reply: [
+                0xF0,
+                UNIVERSAL_NON_REAL_TIME,
+                device_id,
+                GENERAL_INFORMATION,
+                IDENTITY_REPLY,
+                NON_COMMERCIAL,
+                // family code, LSB first
+                0x00,
+                0x00,
+                // family member code, LSB first
+                0x00,
+                0x00,
+                // software revision level
+                0x00,
+                0x01,
+                0x00,
+                0x00,
+                0xF7,
+            ],
+        }
+    }
+
+    fn device_id(&self) -> u8 {
+        self.identity_reply[2]
+    }
+
+    /// Given the payload of a System Exclusive message (i.e., excluding the start and end bytes), returns the
+    /// appropriate [`SysExResponse`].
+    pub fn dispatch(&self, payload: &[u8]) -> SysExResponse<'_> {
+        match payload {
+            [
+                UNIVERSAL_NON_REAL_TIME,
+                device_id,
+                GENERAL_INFORMATION,
+                IDENTITY_REQUEST,
+                ..,
+            ] if *device_id == self.device_id() || *device_id == ALL_CALL => {
+                SysExResponse::Reply(&self.identity_reply)
+            }
+            [UNIVERSAL_REAL_TIME, ..] => {
+                // TODO: MIDI Machine Control
+                SysExResponse::None
+            }
+            #[cfg(feature = "event_log")]
+            [NON_COMMERCIAL, crate::event_log::EVENT_LOG_DUMP, ..] => SysExResponse::EventLogDump,
+            [NON_COMMERCIAL, ..] => {
+                // TODO: configuration dump/restore
+                SysExResponse::None
+            }
+            _ => {
+                #[cfg(feature = "defmt")]
+                defmt::info!("Received unsupported System Exclusive message");
+                SysExResponse::None
+            }
+        }
+    }
+}
+
+/// Reassembles System Exclusive messages, which USB MIDI spreads across several USB-MIDI Event Packets, so that they
+/// can be [dispatched][SysExRouter::dispatch].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SysExAssembler {
+    /// The payload received so far, i.e., excluding the start and end bytes.
+    payload: [u8; ASSEMBLED_PAYLOAD_LEN],
+    /// The number of bytes of `payload` which were actually received.
+    len: usize,
+    /// Whether a message has been started but not yet ended.
+    is_receiving: bool,
+}
+
+impl SysExAssembler {
+    /// Constructs a [`SysExAssembler`] awaiting the start of a message.
+    pub const fn new() -> Self {
+        Self {
+            payload: [0; ASSEMBLED_PAYLOAD_LEN],
+            len: 0,
+            is_receiving: false,
+        }
+    }
+
+    /// Given a USB-MIDI Event Packet, returns the payload of the System Exclusive message it completes, if any.
+    ///
+    /// Packets which don't carry System Exclusive data are ignored. Payload bytes beyond the first
+    /// [`ASSEMBLED_PAYLOAD_LEN`] are dropped, as messages are dispatched on their leading bytes.
+    pub fn push(&mut self, packet: &[u8]) -> Option<&[u8]> {
+        // the Code Index Number (the lower nibble of the Packet Header) gives the number of bytes the packet carries:
+        // 0x4 starts or continues a message, while 0x5 through 0x7 end it with one to three bytes
+        let len = match packet.first()? & 0x0F {
+            0x4 | 0x7 => 3,
+            0x5 => 1,
+            0x6 => 2,
+            _ => return None,
+        };
+
+        let mut is_complete = false;
+        for &byte in packet.get(1..=len)? {
+            match byte {
+                0xF0 => {
+                    self.len = 0;
+                    self.is_receiving = true;
+                }
+                0xF7 => {
+                    is_complete = self.is_receiving;
+                    self.is_receiving = false;
+                }
+                _ if self.is_receiving && self.len < ASSEMBLED_PAYLOAD_LEN => {
+                    self.payload[self.len] = byte;
+                    self.len += 1;
+                }
+                _ => {}
+            }
+        }
+
+        is_complete.then(|| &self.payload[..self.len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE_ID: u8 = 0x10;
+
+    #[test]
+    fn assemble() {
+        let mut assembler = SysExAssembler::new();
+        assert_eq!(
+            None,
+            assembler.push(&[0x04, 0xF0, 0x7E, DEVICE_ID]),
+            "Expected no payload before the message ends"
+        );
+        assert_eq!(
+            Some(&[0x7E, DEVICE_ID, 0x06, 0x01][..]),
+            assembler.push(&[0x07, 0x06, 0x01, 0xF7]),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            assembler.push(&[0x09, 0x90, 0x3C, 0x7F]),
+            "Expected a channel message to be ignored"
+        );
+    }
+
+    #[test]
+    fn assemble_short() {
+        let mut assembler = SysExAssembler::new();
+        assembler.push(&[0x04, 0xF0, 0x7D, 0x01]);
+        assert_eq!(
+            Some(&[0x7D, 0x01][..]),
+            assembler.push(&[0x05, 0xF7, 0x00, 0x00]),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(&[][..]),
+            assembler.push(&[0x06, 0xF0, 0xF7, 0x00]),
+            "Expected an empty message to complete within one packet; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn assemble_end_without_start() {
+        let mut assembler = SysExAssembler::new();
+        assert_eq!(
+            None,
+            assembler.push(&[0x07, 0x06, 0x01, 0xF7]),
+            "Expected the end of a message never started to be ignored"
+        );
+    }
+
+    #[test]
+    fn assemble_long() {
+        let mut assembler = SysExAssembler::new();
+        assembler.push(&[0x04, 0xF0, 0x7D, 0x00]);
+        for _ in 0..ASSEMBLED_PAYLOAD_LEN {
+            assembler.push(&[0x04, 0x01, 0x02, 0x03]);
+        }
+        let payload = assembler.push(&[0x05, 0xF7, 0x00, 0x00]);
+        assert_eq!(
+            Some(&[0x7D, 0x00, 0x01, 0x02][..]),
+            payload.map(|payload| &payload[..4]),
+            "Expected the leading bytes to be retained; expected left but got right"
+        );
+        assert_eq!(
+            Some(ASSEMBLED_PAYLOAD_LEN),
+            payload.map(<[u8]>::len),
+            "Expected the excess to be dropped; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn identity_request() {
+        let router = SysExRouter::new(DEVICE_ID);
+        let response = router.dispatch(&[0x7E, DEVICE_ID, 0x06, 0x01]);
+        match response {
+            SysExResponse::Reply(reply) => {
+                assert_eq!(
+                    [0xF0, 0x7E, DEVICE_ID, 0x06, 0x02, 0x7D],
+                    reply[..6],
+                    "Expected left but got right"
+                );
+                assert_eq!(Some(&0xF7), reply.last(), "Expected reply to be terminated");
+            }
+            _ => panic!("Expected an Identity Reply"),
+        }
+    }
+
+    #[test]
+    fn identity_request_all_call() {
+        let router = SysExRouter::new(DEVICE_ID);
+        assert!(
+            matches!(
+                router.dispatch(&[0x7E, 0x7F, 0x06, 0x01]),
+                SysExResponse::Reply(_)
+            ),
+            "Expected device to answer an all-call Identity Request"
+        );
+    }
+
+    #[test]
+    fn identity_request_other_device() {
+        let router = SysExRouter::new(DEVICE_ID);
+        assert_eq!(
+            SysExResponse::None,
+            router.dispatch(&[0x7E, 0x11, 0x06, 0x01]),
+            "Expected left but got right"
+        );
+    }
+
+    #[cfg(feature = "event_log")]
+    #[test]
+    fn event_log_dump() {
+        let router = SysExRouter::new(DEVICE_ID);
+        assert_eq!(
+            SysExResponse::EventLogDump,
+            router.dispatch(&[0x7D, 0x01]),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let router = SysExRouter::new(DEVICE_ID);
+        for payload in [
+            &[0x7F, DEVICE_ID, 0x06, 0x01][..],
+            &[0x7D, 0x00],
+            &[0x41],
+            &[],
+        ] {
+            assert_eq!(
+                SysExResponse::None,
+                router.dispatch(payload),
+                "Expected left but got right"
+            );
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Provides test doubles for exercising MIDI handling on the host, without embedded hardware.
+
+use crate::{
+    midi_state::{bytes_to_midi, midi_to_bytes},
+    usb_midi::{MidiPort, PACKET_SIZE},
+};
+use tinyvec::ArrayVec;
+use wmidi::MidiMessage;
+
+/// The number of packets a [`MockMidiClass`] can hold in each direction.
+pub const MOCK_PACKET_CAPACITY: usize = 32;
+
+/// Error returned by [`MockMidiClass`] once its input is exhausted (or its output log is full), standing in for the
+/// endpoint error the real MIDI class returns when the host disconnects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Disconnected;
+
+/// A single packet read from or written to a [`MockMidiClass`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MockPacket {
+    data: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+// `Default` isn't derivable, as arrays longer than 32 elements don't implement it
+impl Default for MockPacket {
+    fn default() -> Self {
+        Self {
+            data: [0; PACKET_SIZE],
+            len: 0,
+        }
+    }
+}
+
+impl MockPacket {
+    fn new(data: &[u8]) -> Self {
+        let mut packet = Self::default();
+        packet.data[..data.len()].copy_from_slice(data);
+        packet.len = data.len();
+        packet
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// Stands in for the USB MIDI class as a [`MidiPort`].
+///
+/// Input packets are pre-loaded and then read back in order; once they are exhausted, reads fail as though the host
+/// had disconnected, which lets a read loop under test run to completion. Written packets are logged for inspection.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MockMidiClass {
+    input: ArrayVec<[MockPacket; MOCK_PACKET_CAPACITY]>,
+    /// The number of input packets read so far.
+    read: usize,
+    output: ArrayVec<[MockPacket; MOCK_PACKET_CAPACITY]>,
+}
+
+impl MockMidiClass {
+    /// Constructs a [`MockMidiClass`] with no input queued.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues raw packet data (one or more USB-MIDI Event Packets) to be read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` exceeds the size of a USB packet or if [`MOCK_PACKET_CAPACITY`] packets are already queued.
+    pub fn push_input(&mut self, data: &[u8]) {
+        self.input.push(MockPacket::new(data));
+    }
+
+    /// Queues each [`MidiMessage`] in its own packet, to be read in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a message isn't a channel message (see [`midi_to_bytes`]) or if the queue overflows.
+    pub fn push_messages(&mut self, messages: &[MidiMessage]) {
+        messages.iter().for_each(|msg| {
+            self.push_input(
+                &midi_to_bytes(msg).expect("mock input should contain only channel messages"),
+            )
+        });
+    }
+
+    /// Returns an [`Iterator`] over the packets written so far, in order.
+    pub fn written(&self) -> impl Iterator<Item = &[u8]> {
+        self.output.iter().map(MockPacket::as_slice)
+    }
+
+    /// Returns an [`Iterator`] over the [`MidiMessage`]s contained in the packets written so far, in order.
+    pub fn written_messages(&self) -> impl Iterator<Item = MidiMessage<'_>> {
+        self.written().flat_map(bytes_to_midi)
+    }
+}
+
+impl MidiPort for MockMidiClass {
+    type Error = Disconnected;
+
+    /// Copies the next queued packet into `buf`, returning its length, or returns [`Disconnected`] if none remain.
+    async fn read_packet(&mut self, buf: &mut [u8]) -> Result<usize, Disconnected> {
+        let packet = self.input.get(self.read).ok_or(Disconnected)?;
+        self.read += 1;
+        buf[..packet.len].copy_from_slice(packet.as_slice());
+        Ok(packet.len)
+    }
+
+    /// Logs `data` as a written packet, returning [`Disconnected`] if the log is full.
+    async fn write_packet(&mut self, data: &[u8]) -> Result<(), Disconnected> {
+        self.output
+            .try_push(MockPacket::new(data))
+            .map_or(Ok(()), |_| Err(Disconnected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+    use wmidi::{Channel, ControlFunction, Note, U7};
+
+    #[test]
+    fn read_packet() {
+        let note_on = MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX);
+        let note_off = MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN);
+        let mut class = MockMidiClass::new();
+        class.push_messages(&[note_on.clone(), note_off.clone()]);
+
+        let mut buf = [0; PACKET_SIZE];
+        let len = block_on(class.read_packet(&mut buf)).unwrap();
+        assert!(
+            bytes_to_midi(&buf[..len]).eq([note_on]),
+            "Expected the first queued message"
+        );
+        let len = block_on(class.read_packet(&mut buf)).unwrap();
+        assert!(
+            bytes_to_midi(&buf[..len]).eq([note_off]),
+            "Expected the second queued message"
+        );
+        assert_eq!(
+            Err(Disconnected),
+            block_on(class.read_packet(&mut buf)),
+            "Expected exhausted input to read as a disconnection"
+        );
+    }
+
+    #[test]
+    fn write_packet() {
+        let msg = MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::MODULATION_WHEEL,
+            U7::from_u8_lossy(64),
+        );
+        let mut class = MockMidiClass::new();
+        block_on(class.write_packet(&midi_to_bytes(&msg).unwrap())).unwrap();
+
+        assert!(
+            class.written_messages().eq([msg]),
+            "Expected the written message to be logged"
+        );
+    }
+
+    #[test]
+    fn write_packet_overflow() {
+        let mut class = MockMidiClass::new();
+        for _ in 0..MOCK_PACKET_CAPACITY {
+            block_on(class.write_packet(&[0])).unwrap();
+        }
+        assert_eq!(
+            Err(Disconnected),
+            block_on(class.write_packet(&[0])),
+            "Expected left but got right"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Provides [`Thumbwheel`], which turns raw <abbr title="analog-to-digital converter">ADC</abbr> readings of a
+//! potentiometer into MIDI control values, ignoring the jitter inherent in analog inputs.
+
+use wmidi::{ControlValue, U7};
+
+/// The largest reading of a 12-bit ADC.
+pub const ADC_MAX: u16 = 4095;
+
+/// The amount by which a reading must differ from the last reported one to be considered a real change rather than
+/// noise. Half of one control value step, so that deliberate movements are never missed.
+pub const JITTER_THRESHOLD: u16 = 16;
+
+/// Maps a 12-bit ADC reading (0–4095) to a control value (0–127), saturating readings beyond the ADC's range.
+pub fn to_control_value(reading: u16) -> ControlValue {
+    U7::from_u8_lossy((reading.min(ADC_MAX) >> 5) as u8)
+}
+
+/// The furthest, in semitones, [`to_transpose`] transposes either way.
+pub const TRANSPOSE_RANGE_SEMITONES: u8 = 12;
+
+/// Maps a control value (0–127) to a transposition from -[`TRANSPOSE_RANGE_SEMITONES`] to +[`TRANSPOSE_RANGE_SEMITONES`]
+/// semitones, in equal steps, with no transposition at the center of the thumbwheel's travel.
+pub fn to_transpose(value: ControlValue) -> i8 {
+    let steps = u16::from(TRANSPOSE_RANGE_SEMITONES) * 2 + 1;
+    (u16::from(u8::from(value)) * steps / 128) as i8 - TRANSPOSE_RANGE_SEMITONES as i8
+}
+
+/// Tracks the last reported reading of a potentiometer so that only meaningful changes are acted upon.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Thumbwheel {
+    /// The reading most recently reported as a change, if any.
+    last_reading: Option<u16>,
+}
+
+impl Thumbwheel {
+    /// Constructs a new [`Thumbwheel`], whose first reading will always be reported.
+    pub const fn new() -> Self {
+        Self { last_reading: None }
+    }
+
+    /// Takes a new ADC reading, returning the corresponding control value if it differs from the last reported reading
+    /// by more than [`JITTER_THRESHOLD`], otherwise `None`.
+    pub fn update(&mut self, reading: u16) -> Option<ControlValue> {
+        match self.last_reading {
+            Some(last) if last.abs_diff(reading) <= JITTER_THRESHOLD => None,
+            _ => {
+                self.last_reading = Some(reading);
+                Some(to_control_value(reading))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_control_value() {
+        assert_eq!(
+            U7::MIN,
+            super::to_control_value(0),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            U7::from_u8_lossy(64),
+            super::to_control_value(2048),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            U7::MAX,
+            super::to_control_value(ADC_MAX),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            U7::MAX,
+            super::to_control_value(u16::MAX),
+            "Should saturate; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn to_transpose() {
+        assert_eq!(
+            -12,
+            super::to_transpose(U7::MIN),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0,
+            super::to_transpose(U7::from_u8_lossy(64)),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            12,
+            super::to_transpose(U7::MAX),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn first_reading_is_reported() {
+        let mut thumbwheel = Thumbwheel::new();
+        assert_eq!(
+            Some(U7::MIN),
+            thumbwheel.update(0),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn jitter_is_ignored() {
+        let mut thumbwheel = Thumbwheel::new();
+        thumbwheel.update(2048);
+        assert_eq!(
+            None,
+            thumbwheel.update(2048 + JITTER_THRESHOLD),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            thumbwheel.update(2048 - JITTER_THRESHOLD),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn change_is_reported() {
+        let mut thumbwheel = Thumbwheel::new();
+        thumbwheel.update(2048);
+        assert_eq!(
+            Some(U7::from_u8_lossy(65)),
+            thumbwheel.update(2048 + JITTER_THRESHOLD * 2),
+            "Expected left but got right"
+        );
+        // the threshold is relative to the last reported reading, so slow drift is still caught eventually
+        assert_eq!(
+            None,
+            thumbwheel.update(2048 + JITTER_THRESHOLD * 3),
+            "Expected left but got right"
+        );
+    }
+}
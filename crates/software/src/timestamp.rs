@@ -0,0 +1,80 @@
+//! Provides [`NoteEventTimestamp`] and the [`NowProvider`] trait, which decouple timing logic from any particular
+//! clock, so that it can run under Embassy, in a `std`-based tool, or in tests with a fake clock.
+
+/// A point in time, expressed in microseconds since an arbitrary epoch chosen by the [`NowProvider`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoteEventTimestamp {
+    micros: u64,
+}
+
+impl NoteEventTimestamp {
+    /// Constructs a [`NoteEventTimestamp`] from a number of microseconds since the epoch.
+    pub const fn from_micros(micros: u64) -> Self {
+        Self { micros }
+    }
+
+    /// Returns the number of microseconds since the epoch.
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    /// Returns the number of microseconds elapsed since `earlier`, or 0 if `earlier` is in fact later.
+    pub const fn micros_since(&self, earlier: Self) -> u64 {
+        self.micros.saturating_sub(earlier.micros)
+    }
+}
+
+/// A source for the current time.
+///
+/// Implementations are zero-sized types used as type parameters, so that the choice of clock costs nothing at runtime.
+pub trait NowProvider {
+    /// Returns the current time.
+    fn now() -> NoteEventTimestamp;
+}
+
+/// Provides the current time from Embassy's time driver.
+#[cfg(feature = "embassy-time")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EmbassyNow;
+
+#[cfg(feature = "embassy-time")]
+impl NowProvider for EmbassyNow {
+    fn now() -> NoteEventTimestamp {
+        NoteEventTimestamp::from_micros(embassy_time::Instant::now().as_micros())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn micros_since() {
+        let earlier = NoteEventTimestamp::from_micros(1_000);
+        let later = NoteEventTimestamp::from_micros(1_500);
+        assert_eq!(
+            500,
+            later.micros_since(earlier),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            0,
+            earlier.micros_since(later),
+            "Expected elapsed time never to be negative"
+        );
+    }
+
+    #[cfg(feature = "embassy-time")]
+    #[test]
+    fn embassy_now() {
+        let (_clock, driver) = crate::mock_time::mock_driver();
+        let start = EmbassyNow::now();
+        driver.advance(embassy_time::Duration::from_micros(250));
+        assert_eq!(
+            250,
+            EmbassyNow::now().micros_since(start),
+            "Expected left but got right"
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Provides the [`MidiPort`] trait, which stands for the USB MIDI class so that the device's side of a USB MIDI
+//! connection can be exercised on the host (e.g., with `MockMidiClass`, behind the `test_utils` feature), along with
+//! the routines built on it.
+
+#[cfg(feature = "event_log")]
+use crate::event_log::MidiEventLog;
+use crate::{
+    midi_state::{MidiState, bytes_to_midi, midi_to_bytes},
+    sysex::{SysExAssembler, SysExResponse, SysExRouter},
+};
+use core::future::Future;
+use wmidi::MidiMessage;
+
+/// The size of a full-speed USB packet, i.e., the most data a [`MidiPort`] reads or writes at once.
+pub const PACKET_SIZE: usize = 64;
+
+/// A connection over which USB-MIDI Event Packets are exchanged with the host, e.g., the USB MIDI class.
+pub trait MidiPort {
+    /// Returned once the host disconnects.
+    type Error;
+
+    /// Waits for a packet from the host, copying it into `buf` and returning its length.
+    fn read_packet(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>>;
+
+    /// Sends `data`, containing one or more USB-MIDI Event Packets, to the host.
+    fn write_packet(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// Brings a newly connected host up to date on any controller values it may have lost track of (e.g., after the cable
+/// was replugged); see [`MidiState::non_default_cc_messages`].
+pub async fn announce_state<P: MidiPort>(port: &mut P, state: &MidiState) -> Result<(), P::Error> {
+    write_messages(port, state.non_default_cc_messages()).await
+}
+
+/// Answers the System Exclusive messages received by [`receive_midi`], as directed by a [`SysExRouter`].
+#[derive(Clone, Debug)]
+pub struct SysExResponder {
+    router: SysExRouter,
+    assembler: SysExAssembler,
+    /// Writes a dump of the event log to the given buffer, returning its length; see [`MidiEventLog::write_sysex`].
+    #[cfg(feature = "event_log")]
+    dump_event_log: Option<fn(&mut [u8]) -> Option<usize>>,
+}
+
+impl SysExResponder {
+    /// Constructs a [`SysExResponder`] which answers as `router` directs. Requests for an event log dump go unanswered
+    /// unless one is supplied; see [`with_event_log`][Self::with_event_log].
+    pub const fn new(router: SysExRouter) -> Self {
+        Self {
+            router,
+            assembler: SysExAssembler::new(),
+            #[cfg(feature = "event_log")]
+            dump_event_log: None,
+        }
+    }
+
+    /// Answers requests for an event log dump with the System Exclusive message `dump` writes to the given buffer, e.g.,
+    /// by calling [`MidiEventLog::write_sysex`] on the device's log. The buffer holds [`MidiEventLog::SYSEX_LEN`] bytes.
+    #[cfg(feature = "event_log")]
+    pub const fn with_event_log(mut self, dump: fn(&mut [u8]) -> Option<usize>) -> Self {
+        self.dump_event_log = Some(dump);
+        self
+    }
+
+    /// Given a USB-MIDI Event Packet received from `port`, writes any response warranted by the System Exclusive
+    /// message it completes back to `port`.
+    async fn respond<P: MidiPort>(&mut self, port: &mut P, packet: &[u8]) -> Result<(), P::Error> {
+        let Some(payload) = self.assembler.push(packet) else {
+            return Ok(());
+        };
+        match self.router.dispatch(payload) {
+            SysExResponse::None => Ok(()),
+            SysExResponse::Reply(reply) => write_sysex(port, reply).await,
+            #[cfg(feature = "event_log")]
+            SysExResponse::EventLogDump => {
+                let mut dump = [0; <MidiEventLog>::SYSEX_LEN];
+                match self.dump_event_log.and_then(|write| write(&mut dump)) {
+                    Some(len) => write_sysex(port, &dump[..len]).await,
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Reads packets from `port` until the host disconnects, passing each one to `forward` (e.g., to queue it for
+/// processing) and, if `thru` is set, echoing the appropriate MIDI back to the host; see [`echo_thru`]. System
+/// Exclusive messages are answered by `sysex`.
+///
+/// Returns the error with which reading or writing failed, i.e., once the host disconnects.
+pub async fn receive_midi<P: MidiPort>(
+    port: &mut P,
+    thru: bool,
+    sysex: &mut SysExResponder,
+    mut forward: impl AsyncFnMut(&[u8]),
+) -> Result<(), P::Error> {
+    let mut buf = [0; PACKET_SIZE];
+    loop {
+        let len = port.read_packet(&mut buf).await?;
+        forward(&buf[..len]).await;
+
+        for packet in buf[..len].chunks_exact(4) {
+            sysex.respond(port, packet).await?;
+        }
+
+        if thru {
+            echo_thru(port, &buf[..len]).await?;
+        }
+    }
+}
+
+/// Echoes the Control Change and aftertouch messages contained in `data` back to the host (e.g., so that a DAW can
+/// record them as automation). Note events are omitted, so the host never receives them a second time.
+pub async fn echo_thru<P: MidiPort>(port: &mut P, data: &[u8]) -> Result<(), P::Error> {
+    let thru = bytes_to_midi(data).filter(|msg| {
+        matches!(
+            msg,
+            MidiMessage::ControlChange(..)
+                | MidiMessage::ChannelPressure(..)
+                | MidiMessage::PolyphonicKeyPressure(..)
+        )
+    });
+    write_messages(port, thru).await
+}
+
+/// Writes `messages` to `port`, as many to a packet as fit. Messages which aren't channel messages are skipped (see
+/// [`midi_to_bytes`]), and nothing is written if no messages remain.
+async fn write_messages<'a, P: MidiPort>(
+    port: &mut P,
+    messages: impl Iterator<Item = MidiMessage<'a>>,
+) -> Result<(), P::Error> {
+    write_event_packets(port, messages.filter_map(|msg| midi_to_bytes(&msg))).await
+}
+
+/// Writes `msg`, a complete System Exclusive message (including the start and end bytes), to `port`, spread across as
+/// many USB-MIDI Event Packets as it takes.
+pub async fn write_sysex<P: MidiPort>(port: &mut P, msg: &[u8]) -> Result<(), P::Error> {
+    let mut chunks = msg.chunks(3).peekable();
+    let packets = core::iter::from_fn(|| {
+        let chunk = chunks.next()?;
+        // Code Index Number 0x4 starts or continues a message, while 0x5 through 0x7 end it with one to three bytes
+        let mut packet = [0x4, 0, 0, 0];
+        if chunks.peek().is_none() {
+            packet[0] += chunk.len() as u8;
+        }
+        packet[1..=chunk.len()].copy_from_slice(chunk);
+        Some(packet)
+    });
+    write_event_packets(port, packets).await
+}
+
+/// Writes USB-MIDI Event Packets to `port`, as many to a USB packet as fit; nothing is written if there are none.
+async fn write_event_packets<P: MidiPort>(
+    port: &mut P,
+    packets: impl Iterator<Item = [u8; 4]>,
+) -> Result<(), P::Error> {
+    let mut buf = [0; PACKET_SIZE];
+    let mut len = 0;
+    for packet in packets {
+        if len + packet.len() > PACKET_SIZE {
+            port.write_packet(&buf[..len]).await?;
+            len = 0;
+        }
+        buf[len..len + packet.len()].copy_from_slice(&packet);
+        len += packet.len();
+    }
+
+    if len > 0 {
+        port.write_packet(&buf[..len]).await?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test_utils"))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{Disconnected, MockMidiClass};
+    use embassy_futures::block_on;
+    use wmidi::{Channel, ControlFunction, Note, U7};
+
+    const DEVICE_ID: u8 = 0x10;
+
+    /// Reads every packet queued in `class` with [`receive_midi`], updating `state` with each one, until the input is
+    /// exhausted.
+    fn receive(
+        class: &mut MockMidiClass,
+        state: &mut MidiState,
+        thru: bool,
+    ) -> Result<(), Disconnected> {
+        let mut sysex = SysExResponder::new(SysExRouter::new(DEVICE_ID));
+        block_on(receive_midi(
+            class,
+            thru,
+            &mut sysex,
+            async |data: &[u8]| bytes_to_midi(data).for_each(|msg| state.apply(msg)),
+        ))
+    }
+
+    /// Returns the System Exclusive data carried by the packets written to `class`, in order.
+    fn written_sysex(class: &MockMidiClass) -> impl Iterator<Item = u8> + '_ {
+        class
+            .written()
+            .flat_map(|data| data.chunks_exact(4))
+            .flat_map(|packet| {
+                let len = match packet[0] {
+                    0x4 | 0x7 => 3,
+                    0x6 => 2,
+                    0x5 => 1,
+                    _ => 0,
+                };
+                packet[1..=len].iter().copied()
+            })
+    }
+
+    #[test]
+    fn receive_and_echo_thru() {
+        let modulation = MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::MODULATION_WHEEL,
+            U7::from_u8_lossy(64),
+        );
+        let pressure = MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX);
+        let mut class = MockMidiClass::new();
+        class.push_messages(&[
+            MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX),
+            modulation.clone(),
+            pressure.clone(),
+            MidiMessage::NoteOn(Channel::Ch1, Note::E4, U7::MAX),
+        ]);
+
+        let mut state = MidiState::new();
+        assert_eq!(
+            Err(Disconnected),
+            receive(&mut class, &mut state, true),
+            "Expected reading to stop once the input is exhausted"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4, Note::E4]),
+            "Expected both notes to be activated"
+        );
+        assert!(
+            class.written_messages().eq([modulation, pressure]),
+            "Expected only the Control Change and aftertouch to be echoed"
+        );
+    }
+
+    #[test]
+    fn receive_without_thru() {
+        let mut class = MockMidiClass::new();
+        class.push_messages(&[
+            MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX),
+            MidiMessage::ChannelPressure(Channel::Ch1, U7::MAX),
+        ]);
+
+        let mut state = MidiState::new();
+        assert_eq!(
+            Err(Disconnected),
+            receive(&mut class, &mut state, false),
+            "Expected reading to stop once the input is exhausted"
+        );
+        assert!(
+            state.activated_notes.iter().eq([Note::C4]),
+            "Expected the note to be activated"
+        );
+        assert_eq!(
+            0,
+            class.written().count(),
+            "Expected nothing to be echoed with thru disabled"
+        );
+    }
+
+    #[test]
+    fn receive_identity_request() {
+        let mut class = MockMidiClass::new();
+        class.push_input(&[0x04, 0xF0, 0x7E, DEVICE_ID, 0x07, 0x06, 0x01, 0xF7]);
+
+        let mut state = MidiState::new();
+        assert_eq!(
+            Err(Disconnected),
+            receive(&mut class, &mut state, false),
+            "Expected reading to stop once the input is exhausted"
+        );
+        let router = SysExRouter::new(DEVICE_ID);
+        let SysExResponse::Reply(expected) = router.dispatch(&[0x7E, DEVICE_ID, 0x06, 0x01]) else {
+            panic!("Expected an Identity Reply");
+        };
+        assert!(
+            written_sysex(&class).eq(expected.iter().copied()),
+            "Expected the Identity Reply to be written"
+        );
+    }
+
+    #[cfg(feature = "event_log")]
+    #[test]
+    fn receive_event_log_dump() {
+        use crate::timestamp::NoteEventTimestamp;
+
+        fn dump(buf: &mut [u8]) -> Option<usize> {
+            let mut log = MidiEventLog::<2>::new();
+            log.record(
+                &MidiMessage::NoteOn(Channel::Ch1, Note::C4, U7::MAX),
+                NoteEventTimestamp::from_micros(10),
+            );
+            log.write_sysex(buf)
+        }
+
+        let mut class = MockMidiClass::new();
+        class.push_input(&[0x04, 0xF0, 0x7D, 0x01, 0x05, 0xF7, 0x00, 0x00]);
+        let mut sysex = SysExResponder::new(SysExRouter::new(DEVICE_ID)).with_event_log(dump);
+        assert_eq!(
+            Err(Disconnected),
+            block_on(receive_midi(
+                &mut class,
+                false,
+                &mut sysex,
+                async |_: &[u8]| {}
+            )),
+            "Expected reading to stop once the input is exhausted"
+        );
+
+        let mut expected = [0; MidiEventLog::<2>::SYSEX_LEN];
+        let len = dump(&mut expected).unwrap();
+        assert!(
+            written_sysex(&class).eq(expected[..len].iter().copied()),
+            "Expected the event log to be dumped"
+        );
+    }
+
+    #[test]
+    fn write_sysex() {
+        let mut class = MockMidiClass::new();
+        block_on(super::write_sysex(
+            &mut class,
+            &[0xF0, 0x7D, 0x01, 0x02, 0xF7],
+        ))
+        .unwrap();
+        assert!(
+            class
+                .written()
+                .eq([&[0x04, 0xF0, 0x7D, 0x01, 0x06, 0x02, 0xF7, 0x00][..]]),
+            "Expected the message to start in one Event Packet and end in the next"
+        );
+    }
+
+    #[test]
+    fn echo_thru_nothing() {
+        let mut class = MockMidiClass::new();
+        let data = midi_to_bytes(&MidiMessage::NoteOff(Channel::Ch1, Note::C4, U7::MIN)).unwrap();
+        block_on(echo_thru(&mut class, &data)).unwrap();
+        assert_eq!(
+            0,
+            class.written().count(),
+            "Expected no packet to be written when there is nothing to echo"
+        );
+    }
+
+    #[test]
+    fn announce_state() {
+        let mut state = MidiState::new();
+        state.apply(MidiMessage::ControlChange(
+            Channel::Ch1,
+            ControlFunction::PORTAMENTO_TIME,
+            U7::from_u8_lossy(100),
+        ));
+        let mut class = MockMidiClass::new();
+        block_on(super::announce_state(&mut class, &state)).unwrap();
+
+        assert_eq!(1, class.written().count(), "Expected left but got right");
+        assert!(
+            class
+                .written_messages()
+                .map(|msg| midi_to_bytes(&msg))
+                .eq(state
+                    .non_default_cc_messages()
+                    .map(|msg| midi_to_bytes(&msg))),
+            "Expected every non-default controller to be announced"
+        );
+    }
+
+    #[test]
+    fn write_messages_across_packets() {
+        let messages = (0..20).map(|i| {
+            MidiMessage::ControlChange(
+                Channel::Ch1,
+                ControlFunction::MODULATION_WHEEL,
+                U7::from_u8_lossy(i),
+            )
+        });
+        let mut class = MockMidiClass::new();
+        block_on(write_messages(&mut class, messages.clone())).unwrap();
+
+        assert!(
+            class.written().map(<[u8]>::len).eq([PACKET_SIZE, 16]),
+            "Expected a full packet followed by the remainder"
+        );
+        assert!(
+            class.written_messages().eq(messages),
+            "Expected every message to be written, in order"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Provides [`VoiceHistory`], a record of the most recently voiced notes which supports stepping back through them,
+//! e.g., to "undo" the last note during an improvisation.
+
+use crate::timestamp::NoteEventTimestamp;
+use tinyvec::ArrayVec;
+use wmidi::{Note, U7};
+
+/// The number of voiced notes retained by a [`VoiceHistory`].
+pub const VOICE_HISTORY_LEN: usize = 8;
+
+/// How long (in microseconds) all notes may be released before the [`VoiceHistory`] is forgotten, i.e., 2 seconds.
+pub const REST_TIMEOUT_MICROS: u64 = 2_000_000;
+
+/// The last [`VOICE_HISTORY_LEN`] notes voiced by the instrument, oldest first.
+///
+/// Unlike [`ActivatedNotes`][crate::midi_state::ActivatedNotes], which tracks the keys currently depressed, this tracks
+/// what the instrument actually sounded, after note priority and the like have been applied.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VoiceHistory {
+    /// Stored as [`U7`] rather than [`Note`] because [`tinyvec`] requires that `Items` implement [`Default`].
+    notes: ArrayVec<[U7; VOICE_HISTORY_LEN]>,
+    /// The number of notes up to and including the one currently voiced; less than `notes.len()` after stepping back.
+    position: usize,
+    /// The time at which all notes were released, or `None` if a note is voiced.
+    released_at: Option<NoteEventTimestamp>,
+}
+
+impl VoiceHistory {
+    /// Constructs a new, empty [`VoiceHistory`].
+    pub const fn new() -> Self {
+        Self {
+            notes: ArrayVec::from_array_empty([U7::MIN; VOICE_HISTORY_LEN]),
+            position: 0,
+            released_at: None,
+        }
+    }
+
+    /// Records a newly voiced [`Note`], displacing the oldest one if the history is full.
+    ///
+    /// Any notes stepped back over are discarded, as a new note starts a new branch of history.
+    pub fn push(&mut self, note: Note) {
+        self.notes.truncate(self.position);
+        if self.notes.len() == VOICE_HISTORY_LEN {
+            self.notes.remove(0);
+        }
+        self.notes.push(U7::from_u8_lossy(note as u8));
+        self.position = self.notes.len();
+        self.released_at = None;
+    }
+
+    /// Returns the [`Note`] before the current one, making it the current one, or `None` if there is no such note.
+    pub fn step_back(&mut self) -> Option<Note> {
+        if self.position < 2 {
+            return None;
+        }
+        self.position -= 1;
+        self.current()
+    }
+
+    /// Returns the [`Note`] currently voiced according to the history, or `None` if the history is empty.
+    pub fn current(&self) -> Option<Note> {
+        self.position
+            .checked_sub(1)
+            .map(|i| Note::from(self.notes[i]))
+    }
+
+    /// Records that notes are activated again, ending any rest, e.g., when the note already voiced is pressed again
+    /// and so isn't [`push`][Self::push]ed.
+    pub fn hold(&mut self) {
+        self.released_at = None;
+    }
+
+    /// Records that all notes were released at `timestamp`. Repeated calls during the same rest have no effect.
+    pub fn release(&mut self, timestamp: NoteEventTimestamp) {
+        self.released_at.get_or_insert(timestamp);
+    }
+
+    /// Forgets the history if, as of `now`, all notes have been released for longer than [`REST_TIMEOUT_MICROS`].
+    pub fn expire(&mut self, now: NoteEventTimestamp) {
+        if self
+            .released_at
+            .is_some_and(|released_at| now.micros_since(released_at) > REST_TIMEOUT_MICROS)
+        {
+            self.clear();
+        }
+    }
+
+    /// Forgets all voiced notes, e.g., upon All Notes Off.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(notes: &[Note]) -> VoiceHistory {
+        let mut history = VoiceHistory::new();
+        notes.iter().for_each(|&note| history.push(note));
+        history
+    }
+
+    #[test]
+    fn push() {
+        let actual = history(&[Note::C4, Note::E4]);
+        assert_eq!(
+            Some(Note::E4),
+            actual.current(),
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn push_displaces_oldest() {
+        let mut actual = history(&[
+            Note::C4,
+            Note::D4,
+            Note::E4,
+            Note::F4,
+            Note::G4,
+            Note::A4,
+            Note::B4,
+            Note::C5,
+            Note::D5,
+        ]);
+        for _ in 0..VOICE_HISTORY_LEN - 1 {
+            actual.step_back();
+        }
+        assert_eq!(
+            Some(Note::D4),
+            actual.current(),
+            "Expected the oldest note to have been displaced; expected left but got right"
+        );
+        assert_eq!(None, actual.step_back(), "Expected left but got right");
+    }
+
+    #[test]
+    fn step_back() {
+        let mut actual = history(&[Note::C4, Note::E4, Note::G4]);
+        assert_eq!(
+            Some(Note::E4),
+            actual.step_back(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            Some(Note::C4),
+            actual.step_back(),
+            "Expected left but got right"
+        );
+        assert_eq!(
+            None,
+            actual.step_back(),
+            "Expected no note before the first; expected left but got right"
+        );
+        assert_eq!(
+            Some(Note::C4),
+            actual.current(),
+            "Expected failing to step back not to change the current note; expected left but got right"
+        );
+    }
+
+    #[test]
+    fn push_after_step_back() {
+        let mut actual = history(&[Note::C4, Note::E4, Note::G4]);
+        actual.step_back();
+        actual.push(Note::F4);
+
+        assert_eq!(
+            history(&[Note::C4, Note::E4, Note::F4]),
+            actual,
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn expire() {
+        let mut actual = history(&[Note::C4, Note::E4]);
+        actual.release(NoteEventTimestamp::from_micros(1_000));
+        actual.release(NoteEventTimestamp::from_micros(500_000));
+
+        actual.expire(NoteEventTimestamp::from_micros(1_000 + REST_TIMEOUT_MICROS));
+        assert_eq!(
+            Some(Note::E4),
+            actual.current(),
+            "Expected history to survive a short rest; expected left but got right"
+        );
+
+        actual.expire(NoteEventTimestamp::from_micros(1_001 + REST_TIMEOUT_MICROS));
+        assert_eq!(VoiceHistory::new(), actual, "Expected left but got right");
+    }
+
+    #[test]
+    fn push_ends_rest() {
+        let mut actual = history(&[Note::C4]);
+        actual.release(NoteEventTimestamp::from_micros(0));
+        actual.push(Note::E4);
+
+        actual.expire(NoteEventTimestamp::from_micros(2 * REST_TIMEOUT_MICROS));
+        assert_eq!(
+            history(&[Note::C4, Note::E4]),
+            actual,
+            "Expected left but got right"
+        );
+    }
+
+    #[test]
+    fn hold_ends_rest() {
+        let mut actual = history(&[Note::C4]);
+        actual.release(NoteEventTimestamp::from_micros(0));
+        actual.hold();
+        actual.release(NoteEventTimestamp::from_micros(REST_TIMEOUT_MICROS));
+
+        actual.expire(NoteEventTimestamp::from_micros(2 * REST_TIMEOUT_MICROS));
+        assert_eq!(
+            Some(Note::C4),
+            actual.current(),
+            "Expected the rest to be timed from the latest release; expected left but got right"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Feeds arbitrary sequences of key presses and releases to [`ActivatedNotes`], checking that its invariants hold
+//! and that it agrees with a reference model after every operation. Run with `cargo +nightly fuzz run activated_notes` from the repository root.
+
+#![no_main]
+
+use std::collections::BTreeSet;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use midival_renaissance_lib::midi_state::ActivatedNotes;
+use wmidi::Note;
+
+/// Per the General MIDI Level 2 specification, the default capacity of [`ActivatedNotes`].
+const CAPACITY: usize = 32;
+
+#[derive(Arbitrary, Debug)]
+enum Operation {
+    Add(u8),
+    Remove(u8),
+}
+
+fuzz_target!(|operations: Vec<Operation>| {
+    let mut notes = ActivatedNotes::new();
+    // the notes expected to be activated: a note is added unless already activated or at capacity
+    let mut model = BTreeSet::new();
+
+    for operation in operations {
+        match operation {
+            Operation::Add(n) => {
+                let note = Note::from_u8_lossy(n);
+                notes.add(note);
+                if model.len() < CAPACITY {
+                    model.insert(note);
+                }
+            }
+            Operation::Remove(n) => {
+                let note = Note::from_u8_lossy(n);
+                notes.remove(note);
+                model.remove(&note);
+                assert!(!notes.contains(note), "Removed note is still activated");
+            }
+        }
+
+        let activated: Vec<Note> = notes.iter().collect();
+        for (i, note) in activated.iter().enumerate() {
+            assert!(
+                !activated[i + 1..].contains(note),
+                "Duplicate note {note:?} in {activated:?}"
+            );
+        }
+        assert!(notes.len() <= CAPACITY, "Exceeded capacity");
+        assert_eq!(activated.len(), notes.len(), "len disagrees with iter");
+        assert_eq!(
+            notes.len() == 0,
+            notes.is_empty(),
+            "is_empty disagrees with len"
+        );
+        assert_eq!(
+            model,
+            activated.iter().copied().collect::<BTreeSet<_>>(),
+            "Activated notes disagree with the model"
+        );
+        assert_eq!(
+            model.first().copied(),
+            notes.iter().min(),
+            "Lowest note disagrees with the model"
+        );
+        assert_eq!(
+            model.last().copied(),
+            notes.iter().max(),
+            "Highest note disagrees with the model"
+        );
+    }
+});